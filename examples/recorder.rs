@@ -0,0 +1,21 @@
+use diagramer::recorder::Recorder;
+use std::thread;
+
+fn main() {
+    let recorder = Recorder::new();
+
+    let worker = {
+        let recorder = recorder.clone();
+        thread::spawn(move || {
+            recorder.record("worker", "db", Some("Query"));
+            recorder.record("db", "worker", Some("Rows"));
+        })
+    };
+    recorder.record("client", "worker", Some("Request"));
+    worker.join().unwrap();
+    recorder.record("worker", "client", Some("Response"));
+
+    recorder.write_svg("recorder.svg").unwrap();
+    recorder.write_mermaid("recorder.mmd").unwrap();
+    println!("Wrote recorder.svg and recorder.mmd");
+}
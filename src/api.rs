@@ -1,30 +1,95 @@
+use crate::archive::Archive;
 use crate::data::{self, Sessions, SessionInner};
+use crate::diff::{self, Diff};
+#[cfg(feature = "import")]
+use crate::import::{self, logfile};
+#[cfg(feature = "otlp")]
+use crate::import::otlp;
 
+#[cfg(feature = "dot")]
+use crate::render::dot::{Document as DotDocument};
+#[cfg(feature = "html")]
+use crate::render::html::{self, Document as HtmlDocument};
 #[cfg(feature = "mermaid")]
-use crate::render::mermaid::{Document as MermaidDocument};
+use crate::render::mermaid::{self, Document as MermaidDocument};
+#[cfg(feature = "pdf")]
+use crate::render::pdf::{self, Document as PdfDocument};
+#[cfg(feature = "plantuml")]
+use crate::render::plantuml::{Document as PlantUmlDocument};
+#[cfg(feature = "png")]
+use crate::render::png::{self, Document as PngDocument};
+#[cfg(feature = "render")]
+use crate::render::{registry, Registry};
 #[cfg(feature = "svg")]
-use crate::render::svg::{Document as SvgDocument};
+use crate::render::{
+    layout::Layout,
+    svg::{self, Document as SvgDocument},
+};
+#[cfg(feature = "text")]
+use crate::render::text::{self, Document as TextDocument};
 
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use rocket::{
     State,
+    response::content::RawJson,
     serde::json::Json,
 };
+#[cfg(feature = "import")]
+use rocket::serde::json::serde_json;
+#[cfg(feature = "import")]
+use rocket::{
+    data::{ByteUnit, Data, Limits, ToByteUnit},
+    tokio::io::{AsyncBufReadExt, BufReader},
+};
+use rocket::request::{self, FromRequest, Request};
+#[cfg(any(feature = "otlp", feature = "render"))]
+use rocket::http::ContentType;
 use serde::{Deserialize, Serialize};
-use std::sync::Arc;
+use std::{
+    collections::HashMap,
+    sync::Arc,
+};
 
 #[derive(Responder)]
 pub enum ErrorKind {
+    #[response(status = 400)]
+    BadRequest(Json<ErrorResponse>),
     #[response(status = 404)]
     NotFound(Json<ErrorResponse>),
+    #[cfg(feature = "render")]
+    #[response(status = 406)]
+    NotAcceptable(Json<ErrorResponse>),
+    #[cfg(feature = "import")]
+    #[response(status = 422)]
+    Unprocessable(Json<ImportErrorResponse>),
+    #[response(status = 500)]
+    Internal(Json<ErrorResponse>),
 }
 
 impl ErrorKind {
+    pub fn bad_request(id: u64, cause: &str) -> Self {
+        ErrorKind::BadRequest(Json(ErrorResponse::new(id, cause)))
+    }
+
     pub fn not_found(id: u64, cause: &str) -> Self {
-        ErrorKind::NotFound(Json(ErrorResponse {
-            id,
+        ErrorKind::NotFound(Json(ErrorResponse::new(id, cause)))
+    }
+
+    #[cfg(feature = "render")]
+    pub fn not_acceptable(id: u64, cause: &str) -> Self {
+        ErrorKind::NotAcceptable(Json(ErrorResponse::new(id, cause)))
+    }
+
+    pub fn internal(id: u64, cause: &str) -> Self {
+        ErrorKind::Internal(Json(ErrorResponse::new(id, cause)))
+    }
+
+    #[cfg(feature = "import")]
+    pub fn unprocessable(errors: Vec<import::ParseError>) -> Self {
+        ErrorKind::Unprocessable(Json(ImportErrorResponse {
             status: "ERROR".to_string(),
-            cause: cause.to_string(),
+            cause: "Import contains unsupported constructs".to_string(),
+            errors,
         }))
     }
 }
@@ -36,6 +101,24 @@ pub struct ErrorResponse {
     pub cause: String,
 }
 
+impl ErrorResponse {
+    pub fn new(id: u64, cause: &str) -> Self {
+        ErrorResponse {
+            id,
+            status: "ERROR".to_string(),
+            cause: cause.to_string(),
+        }
+    }
+}
+
+#[cfg(feature = "import")]
+#[derive(Deserialize, Serialize)]
+pub struct ImportErrorResponse {
+    pub status: String,
+    pub cause: String,
+    pub errors: Vec<import::ParseError>,
+}
+
 #[derive(Deserialize, Serialize)]
 pub struct Link {
     pub timestamp: u64,
@@ -52,8 +135,20 @@ pub struct Session {
     pub last_link: u64,
     #[cfg(feature = "mermaid")]
     pub mermaid_url: String,
+    #[cfg(feature = "plantuml")]
+    pub plantuml_url: String,
     #[cfg(feature = "svg")]
     pub svg_url: String,
+    #[cfg(feature = "png")]
+    pub png_url: String,
+    #[cfg(feature = "pdf")]
+    pub pdf_url: String,
+    #[cfg(feature = "text")]
+    pub text_url: String,
+    #[cfg(feature = "html")]
+    pub html_url: String,
+    #[cfg(feature = "dot")]
+    pub dot_url: String,
 }
 
 impl From<&data::SessionInner> for Session {
@@ -70,8 +165,20 @@ impl From<&data::SessionInner> for Session {
                 .unwrap_or(0),
             #[cfg(feature = "mermaid")]
             mermaid_url: uri!(get_mermaid(session.id)).to_string(),
+            #[cfg(feature = "plantuml")]
+            plantuml_url: uri!(get_plantuml(session.id)).to_string(),
             #[cfg(feature = "svg")]
             svg_url: uri!(get_svg(session.id)).to_string(),
+            #[cfg(feature = "png")]
+            png_url: uri!(get_png(session.id)).to_string(),
+            #[cfg(feature = "pdf")]
+            pdf_url: uri!(get_pdf(session.id)).to_string(),
+            #[cfg(feature = "text")]
+            text_url: uri!(get_text(session.id)).to_string(),
+            #[cfg(feature = "html")]
+            html_url: uri!(get_html(session.id)).to_string(),
+            #[cfg(feature = "dot")]
+            dot_url: uri!(get_dot(session.id)).to_string(),
         }
     }
 }
@@ -104,8 +211,266 @@ pub(crate) fn new_session(sessions: &State<Sessions>) -> Result<Json<NewSessionR
     }))
 }
 
+/// Copies a session into a new session, only up to the link `until` if given.
+#[post("/api/session/<id>/fork?<until>")]
+pub(crate) fn fork_session(sessions: &State<Sessions>, id: u64, until: Option<u64>) -> Result<Json<NewSessionResponse>, ErrorKind> {
+    let session = sessions.get(id).ok_or(ErrorKind::not_found(id, "Session doesn't exist"))?;
+    let fork = session.read().unwrap().fork(until);
+    Ok(added_session(sessions, fork))
+}
+
+/// Creates a new session with the links of a session and the session `with`,
+/// ordered by their timestamps.
+#[post("/api/session/<id>/merge?<with>")]
+pub(crate) fn merge_sessions(sessions: &State<Sessions>, id: u64, with: u64) -> Result<Json<NewSessionResponse>, ErrorKind> {
+    let session = sessions.get(id).ok_or(ErrorKind::not_found(id, "Session doesn't exist"))?;
+    let other = sessions.get(with).ok_or(ErrorKind::not_found(with, "Session to merge doesn't exist"))?;
+    let other = other.read().unwrap().clone();
+    let merged = session.read().unwrap().merge(&other);
+    Ok(added_session(sessions, merged))
+}
+
+/// Size limit of uploaded imports, the `import` limit, defaults to 8 MiB.
+#[cfg(feature = "import")]
+fn import_limit(limits: &Limits) -> ByteUnit {
+    limits.get("import").unwrap_or_else(|| 8.mebibytes())
+}
+
+/// Reads an uploaded import with the `import` limit.
+#[cfg(feature = "import")]
+async fn read_import_bytes(body: Data<'_>, limits: &Limits) -> Result<Vec<u8>, ErrorKind> {
+    let source = body.open(import_limit(limits)).into_bytes().await
+        .map_err(|e| ErrorKind::bad_request(0, &e.to_string()))?;
+    if !source.is_complete() {
+        return Err(ErrorKind::bad_request(0, "Import exceeds the size limit"));
+    }
+    Ok(source.into_inner())
+}
+
+/// Reads an uploaded text import with the `import` limit.
+#[cfg(feature = "import")]
+async fn read_import(body: Data<'_>, limits: &Limits) -> Result<String, ErrorKind> {
+    String::from_utf8(read_import_bytes(body, limits).await?)
+        .map_err(|_| ErrorKind::bad_request(0, "Import isn't valid UTF-8"))
+}
+
+/// Adds an imported, forked or merged session under a new id.
+fn added_session(sessions: &Sessions, session: SessionInner) -> Json<NewSessionResponse> {
+    let session = sessions.add_session(session);
+    let session = session.read().unwrap();
+    Json(NewSessionResponse {
+        id: session.id.to_string(),
+        uri: uri!(get_session(session.id)).to_string(),
+    })
+}
+
+/// The session as a pretty printed [`Archive`].
+#[get("/api/session/<id>/export")]
+pub(crate) fn export_session(sessions: &State<Sessions>, id: u64) -> Result<RawJson<String>, ErrorKind> {
+    let session = sessions.get(id).ok_or(ErrorKind::not_found(id, "Session doesn't exist"))?;
+    let archive = Archive::from(&*session.read().unwrap());
+    Ok(RawJson(archive.to_json()))
+}
+
+/// Loads a session exported as [`Archive`] under a new id.
+#[cfg(feature = "import")]
+#[post("/api/sessions/import", data = "<body>")]
+pub(crate) async fn import_session(sessions: &State<Sessions>, limits: &Limits, body: Data<'_>) -> Result<Json<NewSessionResponse>, ErrorKind> {
+    let source = read_import(body, limits).await?;
+    let archive: Archive = serde_json::from_str(&source)
+        .map_err(|e| ErrorKind::unprocessable(vec![e.into()]))?;
+    let session = SessionInner::try_from(archive)
+        .map_err(|e| ErrorKind::unprocessable(vec![import::ParseError::new(0, &e.to_string())]))?;
+    Ok(added_session(sessions, session))
+}
+
+#[cfg(feature = "import")]
+#[post("/api/session/import/mermaid", data = "<body>")]
+pub(crate) async fn import_mermaid(sessions: &State<Sessions>, limits: &Limits, body: Data<'_>) -> Result<Json<NewSessionResponse>, ErrorKind> {
+    let source = read_import(body, limits).await?;
+    let session = import::mermaid::parse(&source)
+        .map_err(|e| ErrorKind::unprocessable(vec![e]))?;
+    Ok(added_session(sessions, session))
+}
+
+/// [`import_mermaid`] next to the other imports.
+#[cfg(feature = "import")]
+#[post("/api/sessions/import/mermaid", data = "<body>")]
+pub(crate) async fn import_mermaid_alias(sessions: &State<Sessions>, limits: &Limits, body: Data<'_>) -> Result<Json<NewSessionResponse>, ErrorKind> {
+    import_mermaid(sessions, limits, body).await
+}
+
+#[cfg(feature = "import")]
+#[post("/api/session/import/plantuml", data = "<body>")]
+pub(crate) async fn import_plantuml(sessions: &State<Sessions>, limits: &Limits, body: Data<'_>) -> Result<Json<NewSessionResponse>, ErrorKind> {
+    let source = read_import(body, limits).await?;
+    let session = import::plantuml::parse(&source)
+        .map_err(ErrorKind::unprocessable)?;
+    Ok(added_session(sessions, session))
+}
+
+/// [`import_plantuml`] next to the other imports.
+#[cfg(feature = "import")]
+#[post("/api/sessions/import/plantuml", data = "<body>")]
+pub(crate) async fn import_plantuml_alias(sessions: &State<Sessions>, limits: &Limits, body: Data<'_>) -> Result<Json<NewSessionResponse>, ErrorKind> {
+    import_plantuml(sessions, limits, body).await
+}
+
+/// Creates a session from Zipkin v2 JSON spans or traces.
+#[cfg(feature = "import")]
+#[post("/api/sessions/import/zipkin", data = "<body>")]
+pub(crate) async fn import_zipkin(sessions: &State<Sessions>, limits: &Limits, body: Data<'_>) -> Result<Json<NewSessionResponse>, ErrorKind> {
+    let source = read_import(body, limits).await?;
+    let session = import::zipkin::parse(&source)
+        .map_err(|e| ErrorKind::unprocessable(vec![e]))?;
+    Ok(added_session(sessions, session))
+}
+
+/// Creates a session from traces downloaded from Jaeger as JSON.
+#[cfg(feature = "import")]
+#[post("/api/sessions/import/jaeger", data = "<body>")]
+pub(crate) async fn import_jaeger(sessions: &State<Sessions>, limits: &Limits, body: Data<'_>) -> Result<Json<NewSessionResponse>, ErrorKind> {
+    let source = read_import(body, limits).await?;
+    let session = import::jaeger::parse(&source)
+        .map_err(|e| ErrorKind::unprocessable(vec![e]))?;
+    Ok(added_session(sessions, session))
+}
+
+/// Creates a session from a HTTP archive recorded by a browser.
+#[cfg(feature = "import")]
+#[post("/api/sessions/import/har", data = "<body>")]
+pub(crate) async fn import_har(sessions: &State<Sessions>, limits: &Limits, body: Data<'_>) -> Result<Json<NewSessionResponse>, ErrorKind> {
+    let source = read_import(body, limits).await?;
+    let session = import::har::parse(&source)
+        .map_err(|e| ErrorKind::unprocessable(vec![e]))?;
+    Ok(added_session(sessions, session))
+}
+
+#[cfg(feature = "import")]
+#[derive(Deserialize, Serialize)]
+pub struct LogImportResponse {
+    pub id: String,
+    pub uri: String,
+    pub links: usize,
+}
+
+/// Creates a session with the links found in an uploaded log by the rule set
+/// `rules` of the server config. The log is read line by line up to the `log`
+/// limit, 1 GiB by default, and the session added once it is read completely.
+#[cfg(feature = "import")]
+#[post("/api/sessions/import/log/<rules>", data = "<body>")]
+pub(crate) async fn import_log(sessions: &State<Sessions>, rule_sets: &State<logfile::RuleSets>, limits: &Limits, rules: &str, body: Data<'_>) -> Result<Json<LogImportResponse>, ErrorKind> {
+    let rules = rule_sets.get(rules).ok_or_else(|| ErrorKind::not_found(0, "Log rule set doesn't exist"))?;
+    let limit = limits.get("log").unwrap_or_else(|| 1.gibibytes());
+
+    // One byte over the limit tells a truncated log from one of exactly the limit
+    let mut reader = BufReader::new(body.open(limit + 1));
+    let mut import = logfile::Import::new(rules);
+    let (mut read, mut line) = (0, String::new());
+    loop {
+        line.clear();
+        let length = reader.read_line(&mut line).await
+            .map_err(|_| ErrorKind::bad_request(0, "Log isn't valid UTF-8"))?;
+        read += length as u64;
+        if read > limit {
+            return Err(ErrorKind::bad_request(0, "Log exceeds the size limit"));
+        }
+        if length == 0 {
+            break;
+        }
+        let line = line.strip_suffix('\n').unwrap_or(&line);
+        import.line(line.strip_suffix('\r').unwrap_or(line));
+    }
+
+    let session = import.finish().map_err(ErrorKind::unprocessable)?;
+    let links = session.links.len();
+    let Json(response) = added_session(sessions, session);
+    Ok(Json(LogImportResponse {
+        id: response.id,
+        uri: response.uri,
+        links,
+    }))
+}
+
+/// `Content-Encoding` of the request body, lower case.
+#[cfg(feature = "otlp")]
+pub(crate) struct ContentEncoding(Option<String>);
+
+#[cfg(feature = "otlp")]
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ContentEncoding {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, ()> {
+        let encoding = request.headers().get_one("Content-Encoding").map(str::to_lowercase);
+        request::Outcome::Success(ContentEncoding(encoding))
+    }
+}
+
+/// Decompresses a gzip compressed upload up to the `import` limit.
+#[cfg(feature = "otlp")]
+fn gunzip(data: &[u8], limits: &Limits) -> Result<Vec<u8>, ErrorKind> {
+    use std::io::Read;
+
+    let limit = import_limit(limits).as_u64();
+    let mut decompressed = vec![];
+    flate2::read::GzDecoder::new(data).take(limit + 1).read_to_end(&mut decompressed)
+        .map_err(|e| ErrorKind::bad_request(0, &e.to_string()))?;
+    if decompressed.len() as u64 > limit {
+        return Err(ErrorKind::bad_request(0, "Import exceeds the size limit"));
+    }
+    Ok(decompressed)
+}
+
+/// Receives traces exported with OTLP/HTTP, in protobuf or with a JSON
+/// content type in JSON encoding. The calls between services are added to
+/// the session of their trace or their configured resource attribute.
+#[cfg(feature = "otlp")]
+#[post("/v1/traces", data = "<body>")]
+pub(crate) async fn receive_traces(sessions: &State<Sessions>, receiver: &State<otlp::Receiver>, limits: &Limits, content_type: Option<&ContentType>, encoding: ContentEncoding, body: Data<'_>) -> Result<(ContentType, Vec<u8>), ErrorKind> {
+    let data = read_import_bytes(body, limits).await?;
+    let data = match encoding.0.as_deref() {
+        None | Some("identity") => data,
+        Some("gzip") => gunzip(&data, limits)?,
+        Some(encoding) => return Err(ErrorKind::bad_request(0, &format!("Unsupported content encoding `{encoding}`"))),
+    };
+
+    let json = content_type.is_some_and(|content_type| content_type.is_json());
+    let resources = match json {
+        true => otlp::decode_json(&data),
+        false => otlp::decode_protobuf(&data),
+    }.map_err(|e| ErrorKind::bad_request(0, &e.to_string()))?;
+    receiver.receive(sessions, resources);
+
+    // An empty `ExportTraceServiceResponse`
+    Ok(match json {
+        true => (ContentType::JSON, b"{}".to_vec()),
+        false => (ContentType::new("application", "x-protobuf"), vec![]),
+    })
+}
+
+#[cfg(not(feature = "render"))]
 #[get("/api/session/<id>")]
 pub(crate) fn get_session(sessions: &State<Sessions>, id: u64) -> Result<Json<Session>, ErrorKind> {
+    session_json(sessions, id)
+}
+
+/// The session as JSON, or rendered in the format of the registry preferred
+/// by the `Accept` header.
+#[cfg(feature = "render")]
+#[get("/api/session/<id>")]
+pub(crate) fn get_session(sessions: &State<Sessions>, registry: &State<Registry>, id: u64, format: Negotiated, options: RenderOptions, filter: QueryFilter) -> Result<SessionResponse, ErrorKind> {
+    match format {
+        Negotiated::Json => session_json(sessions, id).map(SessionResponse::Json),
+        Negotiated::Format(format) => render(sessions, registry, id, &format, options, filter).map(SessionResponse::Rendered),
+        Negotiated::NotAcceptable => Err(ErrorKind::not_acceptable(id, &format!(
+            "No acceptable format, available formats: json, {}",
+            registry.formats().collect::<Vec<_>>().join(", "),
+        ))),
+    }
+}
+
+fn session_json(sessions: &State<Sessions>, id: u64) -> Result<Json<Session>, ErrorKind> {
     let session = sessions.get(id)
         .ok_or(ErrorKind::not_found(id, "Session not exists"))?;
     let session = session.read().unwrap();
@@ -113,6 +478,75 @@ pub(crate) fn get_session(sessions: &State<Sessions>, id: u64) -> Result<Json<Se
     Ok(Json(session))
 }
 
+#[cfg(feature = "render")]
+#[derive(Responder)]
+pub(crate) enum SessionResponse {
+    Json(Json<Session>),
+    Rendered((ContentType, Vec<u8>)),
+}
+
+/// Response format of the session URL by the `Accept` header. JSON is
+/// preferred when it's acceptable or no specific type is asked for.
+#[cfg(feature = "render")]
+pub(crate) enum Negotiated {
+    Json,
+    Format(String),
+    NotAcceptable,
+}
+
+#[cfg(feature = "render")]
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Negotiated {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, ()> {
+        let (Some(accept), Some(registry)) = (request.accept(), request.rocket().state::<Registry>()) else {
+            return request::Outcome::Success(Negotiated::Json);
+        };
+        let mut media_types = accept.iter().collect::<Vec<_>>();
+        media_types.sort_by(|a, b| b.weight_or(1.0).total_cmp(&a.weight_or(1.0)));
+        for media_type in media_types {
+            let media_type = media_type.media_type();
+            let any = media_type.sub() == "*" && matches!(media_type.top().as_str(), "*" | "application");
+            if any || media_type.is_json() {
+                return request::Outcome::Success(Negotiated::Json);
+            }
+            if let Some(format) = registry.format_of(media_type) {
+                return request::Outcome::Success(Negotiated::Format(format.to_string()));
+            }
+        }
+        request::Outcome::Success(Negotiated::NotAcceptable)
+    }
+}
+
+/// The query of a render request as options of the format.
+#[cfg(feature = "render")]
+pub(crate) struct RenderOptions(registry::Options);
+
+#[cfg(feature = "render")]
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for RenderOptions {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, ()> {
+        let options = request.query_fields()
+            .map(|field| (field.name.source().to_string(), field.value.to_string()))
+            .collect();
+        request::Outcome::Success(RenderOptions(options))
+    }
+}
+
+#[cfg(any(feature = "html", feature = "mermaid", feature = "pdf", feature = "png", feature = "svg", feature = "text"))]
+impl RenderOptions {
+    /// Options of a format, parsed like the options of the render route.
+    fn parse<T>(&self, id: u64) -> Result<T, ErrorKind>
+    where
+        T: for<'a> TryFrom<&'a registry::Options, Error = registry::Error>,
+    {
+        T::try_from(&self.0).map_err(|e| ErrorKind::bad_request(id, &e.to_string()))
+    }
+}
+
 #[derive(Deserialize, Serialize)]
 pub struct AddLinkRequest<'a> {
     pub from: &'a str,
@@ -134,13 +568,89 @@ pub(crate) fn add_link<'a>(sessions: &State<Sessions>, id: u64, body: Json<AddLi
     }))
 }
 
-#[get("/api/session/<id>/links")]
-pub(crate) fn get_links<'a>(sessions: &State<Sessions>, id: u64) -> Result<Json<Vec<Link>>, ErrorKind> {
-    let session = sessions.get(id).ok_or(ErrorKind::not_found(id, "Session doesn't exist"))?;
+/// Parses a filter from a query like
+/// `first_link=10&end=2024-05-01T10:00:00Z&parties=web,api&others=collapse`,
+/// `None` when the query doesn't filter.
+fn parse_filter(query: &HashMap<String, String>) -> Result<Option<data::Filter>, String> {
+    let invalid = |name: &str, value: &str| format!("Invalid value `{value}` for `{name}`");
+    let link_id = |name: &str| query.get(name)
+        .map(|value| value.parse().map_err(|_| invalid(name, value)))
+        .transpose();
+    let timestamp = |name: &str| query.get(name)
+        .map(|value| DateTime::parse_from_rfc3339(value)
+            .map(|timestamp| timestamp.with_timezone(&Utc))
+            .map_err(|_| invalid(name, value)))
+        .transpose();
+
+    let filter = data::Filter {
+        first_link: link_id("first_link")?,
+        last_link: link_id("last_link")?,
+        start: timestamp("start")?,
+        end: timestamp("end")?,
+        parties: query.get("parties").map(|names| names.split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(str::to_string)
+            .collect()),
+        collapse_others: match query.get("others").map(String::as_str) {
+            None | Some("hide") => false,
+            Some("collapse") => true,
+            Some(value) => return Err(invalid("others", value)),
+        },
+    };
+    Ok((filter != data::Filter::default()).then_some(filter))
+}
+
+/// The [`data::Filter`] given by the query of a request, see [`parse_filter`].
+pub(crate) struct QueryFilter(Result<Option<data::Filter>, String>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for QueryFilter {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, ()> {
+        let query = request.query_fields()
+            .map(|field| (field.name.source().to_string(), field.value.to_string()))
+            .collect();
+        request::Outcome::Success(QueryFilter(parse_filter(&query)))
+    }
+}
+
+impl QueryFilter {
+    /// Whether the query filters or is invalid.
+    #[cfg(feature = "svg")]
+    fn is_set(&self) -> bool {
+        !matches!(self.0, Ok(None))
+    }
+}
+
+/// A snapshot of the session with the links kept by `filter`, taken to
+/// render without holding the lock of the session.
+fn snapshot(sessions: &Sessions, id: u64, filter: QueryFilter) -> Result<SessionInner, ErrorKind> {
+    let session = sessions.get(id).ok_or(ErrorKind::not_found(id, "Session not exists"))?;
+    let filter = filter.0.map_err(|e| ErrorKind::bad_request(id, &e))?;
     let session = session.read().unwrap();
+    Ok(match filter {
+        Some(filter) => session.filter(&filter),
+        None => session.clone(),
+    })
+}
+
+#[get("/api/session/<id>/links")]
+pub(crate) fn get_links(sessions: &State<Sessions>, id: u64, filter: QueryFilter) -> Result<Json<Vec<Link>>, ErrorKind> {
+    let session = snapshot(sessions, id, filter)?;
     Ok(Json(session.links.iter().cloned().map(|link| link.into()).collect()))
 }
 
+/// Sets custom colors of parties and links, keeping the other colors.
+#[put("/api/session/<id>/colors", data = "<body>")]
+pub(crate) fn set_colors(sessions: &State<Sessions>, id: u64, body: Json<data::Colors>) -> Result<Json<data::Colors>, ErrorKind> {
+    let session = sessions.get(id).ok_or(ErrorKind::not_found(id, "Session doesn't exist"))?;
+    let mut session = session.write().unwrap();
+    session.colors.merge(body.into_inner()).map_err(|e| ErrorKind::bad_request(id, &e))?;
+    Ok(Json(session.colors.clone()))
+}
+
 #[derive(Deserialize, Serialize)]
 pub struct EventResponse {
     pub highest_link_id: u64,
@@ -149,7 +659,7 @@ pub struct EventResponse {
 }
 
 #[get("/api/session/<id>/events/<link_id>")]
-pub(crate) fn get_events<'a>(sessions: &State<Sessions>, id: u64, link_id: u64) -> Result<Json<EventResponse>, ErrorKind> {
+pub(crate) fn get_events(sessions: &State<Sessions>, id: u64, link_id: u64) -> Result<Json<EventResponse>, ErrorKind> {
     let session = sessions.get(id).ok_or(ErrorKind::not_found(id, "Session doesn't exist"))?;
     let session = session.read().unwrap();
     let (highest_link_id, new_links) = session.links_above_id(link_id);
@@ -160,21 +670,197 @@ pub(crate) fn get_events<'a>(sessions: &State<Sessions>, id: u64, link_id: u64)
     }))
 }
 
+#[cfg(feature = "svg")]
+#[derive(Serialize)]
+pub struct LayoutResponse {
+    #[serde(flatten)]
+    pub layout: Layout,
+    pub highest_link_id: u64,
+    /// Whether `rows` contains all links, otherwise only the links after `since`.
+    pub complete: bool,
+    pub layout_url: String,
+}
+
+/// Positions of all lanes and of the rows of the links after `since`.
+#[cfg(feature = "svg")]
+#[get("/api/session/<id>/layout?<since>")]
+pub(crate) fn get_layout(sessions: &State<Sessions>, id: u64, since: Option<u64>) -> Result<Json<LayoutResponse>, ErrorKind> {
+    let session = snapshot(sessions, id, QueryFilter(Ok(None)))?;
+    let since = since.unwrap_or(0);
+    let mut layout = Layout::from(&session);
+    layout.rows.retain(|row| row.id > since);
+    Ok(Json(LayoutResponse {
+        layout,
+        highest_link_id: session.links_highest_id,
+        complete: since == 0,
+        layout_url: uri!(get_layout(id, Some(session.links_highest_id))).to_string(),
+    }))
+}
+
 #[cfg(feature = "mermaid")]
 #[get("/api/session/<id>/mermaid")]
-pub(crate) fn get_mermaid(sessions: &State<Sessions>, id: u64) -> Result<MermaidDocument, ErrorKind> {
-    let session = sessions.get(id).ok_or(ErrorKind::not_found(id, "Session not exists"))?;
-    let session = session.read().unwrap();
-    let mermaid: MermaidDocument = Into::<MermaidDocument>::into(&*session);
-    Ok(mermaid)
+pub(crate) fn get_mermaid(sessions: &State<Sessions>, id: u64, options: RenderOptions, filter: QueryFilter) -> Result<MermaidDocument, ErrorKind> {
+    let options: mermaid::Options = options.parse(id)?;
+    let session = snapshot(sessions, id, filter)?;
+    Ok(MermaidDocument::render(&session, &options))
+}
+
+#[cfg(feature = "plantuml")]
+#[get("/api/session/<id>/plantuml")]
+pub(crate) fn get_plantuml(sessions: &State<Sessions>, id: u64, filter: QueryFilter) -> Result<PlantUmlDocument, ErrorKind> {
+    let session = snapshot(sessions, id, filter)?;
+    Ok(PlantUmlDocument::from(&session))
 }
 
 #[cfg(feature = "svg")]
 #[get("/api/session/<id>/svg")]
-pub(crate) fn get_svg(sessions: &State<Sessions>, id: u64) -> Result<SvgDocument, ErrorKind> {
-    let session = sessions.get(id).ok_or(ErrorKind::not_found(id, "Session not exists"))?;
-    let session = session.read().unwrap();
-    let doc: SvgDocument = Into::<SvgDocument>::into(&*session);
-    Ok(doc)
+pub(crate) fn get_svg(sessions: &State<Sessions>, cache: &State<svg::Cache>, id: u64, options: RenderOptions, filter: QueryFilter) -> Result<SvgDocument, ErrorKind> {
+    let options: svg::Options = options.parse(id)?;
+    // Only whole sessions are cached
+    let cached = !filter.is_set();
+    let snapshot = snapshot(sessions, id, filter)?;
+    Ok(match cached {
+        true => cache.render(&snapshot, &options),
+        false => SvgDocument::render(&snapshot, &options),
+    })
+}
+
+#[cfg(feature = "png")]
+#[get("/api/session/<id>/png")]
+pub(crate) fn get_png(sessions: &State<Sessions>, cache: &State<svg::Cache>, id: u64, options: RenderOptions, filter: QueryFilter) -> Result<PngDocument, ErrorKind> {
+    let options: png::Options = options.parse(id)?;
+    let cached = !filter.is_set();
+    let snapshot = snapshot(sessions, id, filter)?;
+    let svg_options = svg::Options { theme: options.theme, ..Default::default() };
+    let svg: String = match cached {
+        true => cache.render(&snapshot, &svg_options),
+        false => SvgDocument::render(&snapshot, &svg_options),
+    }.into();
+    PngDocument::from_svg(&svg, &options).map_err(|e| match e {
+        png::Error::InvalidOptions(_) | png::Error::TooLarge { .. } => ErrorKind::bad_request(id, &e.to_string()),
+        png::Error::Svg(_) | png::Error::Encode(_) => ErrorKind::internal(id, &e.to_string()),
+    })
+}
+
+#[cfg(feature = "pdf")]
+#[get("/api/session/<id>/pdf")]
+pub(crate) fn get_pdf(sessions: &State<Sessions>, id: u64, options: RenderOptions, filter: QueryFilter) -> Result<PdfDocument, ErrorKind> {
+    let options: pdf::Options = options.parse(id)?;
+    let session = snapshot(sessions, id, filter)?;
+    Ok(PdfDocument::render(&session, &options))
+}
+
+#[cfg(feature = "text")]
+#[get("/api/session/<id>/text")]
+pub(crate) fn get_text(sessions: &State<Sessions>, id: u64, options: RenderOptions, filter: QueryFilter) -> Result<TextDocument, ErrorKind> {
+    let options: text::Options = options.parse(id)?;
+    let session = snapshot(sessions, id, filter)?;
+    Ok(TextDocument::render(&session, &options))
+}
+
+#[cfg(feature = "html")]
+#[get("/api/session/<id>/html")]
+pub(crate) fn get_html(sessions: &State<Sessions>, id: u64, options: RenderOptions, filter: QueryFilter) -> Result<HtmlDocument, ErrorKind> {
+    let options: html::Options = options.parse(id)?;
+    let session = snapshot(sessions, id, filter)?;
+    Ok(HtmlDocument::render(&session, &options))
+}
+
+#[cfg(feature = "dot")]
+#[get("/api/session/<id>/dot")]
+pub(crate) fn get_dot(sessions: &State<Sessions>, id: u64, filter: QueryFilter) -> Result<DotDocument, ErrorKind> {
+    let session = snapshot(sessions, id, filter)?;
+    Ok(DotDocument::from(&session))
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct DiffEntry {
+    pub kind: diff::Kind,
+    pub from: String,
+    pub to: String,
+    /// Id of the link in the baseline, `None` when added.
+    pub baseline_id: Option<u64>,
+    /// Id of the link in the current session, `None` when removed.
+    pub current_id: Option<u64>,
+    pub baseline_label: Option<String>,
+    pub label: Option<String>,
+}
+
+impl From<&diff::Entry> for DiffEntry {
+    fn from(entry: &diff::Entry) -> Self {
+        let link = entry.link();
+        DiffEntry {
+            kind: entry.kind,
+            from: link.from.name.clone(),
+            to: link.to.name.clone(),
+            baseline_id: entry.baseline.as_ref().map(|link| link.id),
+            current_id: entry.current.as_ref().map(|link| link.id),
+            baseline_label: entry.baseline.as_ref().and_then(|link| link.label.as_ref().clone()),
+            label: entry.current.as_ref().and_then(|link| link.label.as_ref().clone()),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct DiffResponse {
+    pub baseline: u64,
+    pub current: u64,
+    pub added: usize,
+    pub removed: usize,
+    pub changed: usize,
+    pub entries: Vec<DiffEntry>,
+    #[cfg(feature = "svg")]
+    pub svg_url: String,
+}
+
+/// Snapshots of the session `id` and of the session `baseline`.
+fn diff_sessions(sessions: &Sessions, id: u64, baseline: u64) -> Result<(SessionInner, SessionInner), ErrorKind> {
+    let current = sessions.get(id).ok_or(ErrorKind::not_found(id, "Session doesn't exist"))?;
+    let baseline = sessions.get(baseline).ok_or(ErrorKind::not_found(baseline, "Baseline session doesn't exist"))?;
+    let current = current.read().unwrap().clone();
+    let baseline = baseline.read().unwrap().clone();
+    Ok((baseline, current))
+}
+
+/// Changes of the links of a session compared to the session `baseline`.
+#[get("/api/session/<id>/diff/<baseline>")]
+pub(crate) fn get_diff(sessions: &State<Sessions>, id: u64, baseline: u64) -> Result<Json<DiffResponse>, ErrorKind> {
+    let (baseline_session, current) = diff_sessions(sessions, id, baseline)?;
+    let diff = Diff::new(&baseline_session, &current);
+    Ok(Json(DiffResponse {
+        baseline,
+        current: id,
+        added: diff.count(diff::Kind::Added),
+        removed: diff.count(diff::Kind::Removed),
+        changed: diff.count(diff::Kind::Changed),
+        entries: diff.entries.iter().map(DiffEntry::from).collect(),
+        #[cfg(feature = "svg")]
+        svg_url: uri!(get_diff_svg(id, baseline)).to_string(),
+    }))
+}
+
+/// Diagram of the links of both sessions, colored by their changes.
+#[cfg(feature = "svg")]
+#[get("/api/session/<id>/diff/<baseline>/svg")]
+pub(crate) fn get_diff_svg(sessions: &State<Sessions>, id: u64, baseline: u64, options: RenderOptions) -> Result<SvgDocument, ErrorKind> {
+    let options: svg::Options = options.parse(id)?;
+    let (baseline, current) = diff_sessions(sessions, id, baseline)?;
+    Ok(SvgDocument::render(&Diff::new(&baseline, &current).session(id), &options))
+}
+
+#[cfg(feature = "render")]
+#[get("/api/session/<id>/render/<format>")]
+pub(crate) fn render(sessions: &State<Sessions>, registry: &State<Registry>, id: u64, format: &str, options: RenderOptions, filter: QueryFilter) -> Result<(ContentType, Vec<u8>), ErrorKind> {
+    let renderer = registry.get(format).ok_or_else(|| ErrorKind::not_found(id, &format!(
+        "Unknown format `{format}`, available formats: {}",
+        registry.formats().collect::<Vec<_>>().join(", "),
+    )))?;
+    let snapshot = snapshot(sessions, id, filter)?;
+    let body = renderer.render(&snapshot, &options.0).map_err(|e| match e {
+        registry::Error::InvalidOption(_) => ErrorKind::bad_request(id, &e.to_string()),
+        registry::Error::Render(_) => ErrorKind::internal(id, &e.to_string()),
+    })?;
+    let content_type = ContentType::parse_flexible(renderer.content_type()).unwrap_or(ContentType::Binary);
+    Ok((content_type, body))
 }
 
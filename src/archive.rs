@@ -0,0 +1,209 @@
+use crate::data::{Colors, Link, Party, PartyMappedByName, SessionInner};
+use chrono::{DateTime, Utc};
+use rocket::serde::json::serde_json;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt,
+    sync::Arc,
+};
+
+/// Value of [`Archive::format`].
+pub const FORMAT: &str = "diagramer-session";
+/// Version written by this crate, archives up to this version can be read.
+pub const VERSION: u32 = 1;
+
+/// Lossless JSON document of a session, which can be checked in and loaded
+/// again. Maps are ordered, so the same session always gives the same
+/// document.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Archive {
+    pub format: String,
+    pub version: u32,
+    /// Id of the exported session, imported sessions get a new id. A string
+    /// as JavaScript can't represent all ids as numbers.
+    pub id: String,
+    /// Parties in order of their ids.
+    pub parties: Vec<ArchivedParty>,
+    /// Links in order of their ids.
+    pub links: Vec<ArchivedLink>,
+    #[serde(default)]
+    pub colors: ArchivedColors,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ArchivedParty {
+    pub id: u64,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ArchivedLink {
+    pub id: u64,
+    pub timestamp: DateTime<Utc>,
+    /// Name of the party.
+    pub from: String,
+    /// Name of the party.
+    pub to: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct ArchivedColors {
+    #[serde(default)]
+    pub parties: BTreeMap<String, String>,
+    #[serde(default)]
+    pub links: BTreeMap<u64, String>,
+}
+
+/// Why an archive can't be loaded.
+#[derive(Debug, PartialEq)]
+pub struct Error(pub String);
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl Archive {
+    /// Pretty printed JSON ending with a line break, for readable diffs.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap() + "\n"
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, Error> {
+        serde_json::from_str(json).map_err(|e| Error(e.to_string()))
+    }
+}
+
+impl From<&SessionInner> for Archive {
+    fn from(session: &SessionInner) -> Self {
+        let mut parties = session.parties.iter().map(|party| &party.0).collect::<Vec<_>>();
+        parties.sort();
+
+        Archive {
+            format: FORMAT.to_string(),
+            version: VERSION,
+            id: session.id.to_string(),
+            parties: parties.into_iter()
+                .map(|party| ArchivedParty {
+                    id: party.id,
+                    name: party.name.clone(),
+                    label: party.label.clone(),
+                })
+                .collect(),
+            links: session.links.iter()
+                .map(|link| ArchivedLink {
+                    id: link.id,
+                    timestamp: link.timestamp,
+                    from: link.from.name.clone(),
+                    to: link.to.name.clone(),
+                    label: link.label.as_ref().clone(),
+                })
+                .collect(),
+            colors: ArchivedColors {
+                parties: session.colors.parties.clone().into_iter().collect(),
+                links: session.colors.links.clone().into_iter().collect(),
+            },
+        }
+    }
+}
+
+impl TryFrom<Archive> for SessionInner {
+    type Error = Error;
+
+    /// Restores the session with the ids of its parties and links.
+    fn try_from(archive: Archive) -> Result<Self, Self::Error> {
+        if archive.format != FORMAT {
+            return Err(Error(format!("Unknown format `{}`, expected `{FORMAT}`", archive.format)));
+        }
+        if archive.version == 0 || archive.version > VERSION {
+            return Err(Error(format!("Unsupported version {}, supported up to {VERSION}", archive.version)));
+        }
+
+        let mut session = SessionInner::new(0);
+        let mut ids = HashSet::new();
+        for party in archive.parties {
+            if party.id == 0 || !ids.insert(party.id) {
+                return Err(Error(format!("Invalid or duplicate id {} of party `{}`", party.id, party.name)));
+            }
+            session.parties_highest_id = session.parties_highest_id.max(party.id);
+            let name = party.name.clone();
+            if !session.parties.insert(PartyMappedByName(Arc::new(Party { id: party.id, name, label: party.label }))) {
+                return Err(Error(format!("Duplicate party `{}`", party.name)));
+            }
+        }
+
+        for link in archive.links {
+            if link.id <= session.links_highest_id {
+                return Err(Error(format!("Link {} isn't in ascending order of ids", link.id)));
+            }
+            let party = |name: &str| session.party(name)
+                .cloned()
+                .ok_or_else(|| Error(format!("Link {} refers to unknown party `{name}`", link.id)));
+            let (from, to) = (party(&link.from)?, party(&link.to)?);
+            session.links_highest_id = link.id;
+            session.links.push(Arc::new(Link::new(link.id, link.timestamp, from, to, link.label)));
+        }
+
+        session.colors.merge(Colors {
+            parties: archive.colors.parties.into_iter().collect(),
+            links: archive.colors.links.into_iter().collect(),
+        }).map_err(Error)?;
+        Ok(session)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn session() -> SessionInner {
+        let mut session = SessionInner::new(42);
+        let now = Utc::now();
+        session.add_labeled_party("web", Some("Web\nClient"));
+        session.add_link(now, "web", "api", Some("GET /items"));
+        session.add_link(now, "api", "web", None);
+        session.colors.links.insert(2, "#ff0000".to_string());
+        session
+    }
+
+    #[test]
+    fn roundtrip() {
+        let archive = Archive::from(&session());
+        assert_eq!("42", archive.id);
+        assert_eq!(Some("Web\nClient"), archive.parties[0].label.as_deref());
+
+        let restored = SessionInner::try_from(archive.clone()).unwrap();
+        assert_eq!(archive, Archive { id: "42".to_string(), ..Archive::from(&restored) });
+        assert_eq!(2, restored.links_highest_id);
+        assert_eq!(2, restored.parties_highest_id);
+
+        assert_eq!(Ok(archive.clone()), Archive::from_json(&archive.to_json()));
+    }
+
+    #[test]
+    fn reject_invalid() {
+        let mut archive = Archive::from(&session());
+        archive.version = VERSION + 1;
+        assert!(SessionInner::try_from(archive).map(|_| ()).unwrap_err().0.starts_with("Unsupported version"));
+
+        let mut archive = Archive::from(&session());
+        archive.links[1].to = "db".to_string();
+        assert_eq!(Err(Error("Link 2 refers to unknown party `db`".to_string())), SessionInner::try_from(archive).map(|_| ()));
+
+        let mut archive = Archive::from(&session());
+        archive.links.swap(0, 1);
+        assert!(SessionInner::try_from(archive).is_err());
+
+        let mut archive = Archive::from(&session());
+        archive.parties[1].id = 1;
+        assert!(SessionInner::try_from(archive).is_err());
+    }
+}
@@ -3,6 +3,7 @@ use chrono::{
     offset::Utc,
 };
 use ring::rand::{SystemRandom, SecureRandom};
+use serde::{Deserialize, Serialize};
 use std::{
     collections::{HashMap, HashSet},
     sync::{Arc, RwLock},
@@ -35,7 +36,7 @@ impl std::cmp::Eq for Party {}
 
 impl std::cmp::PartialOrd for Party {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        Some(self.id.cmp(&other.id))
+        Some(self.cmp(other))
     }
 }
 
@@ -79,6 +80,73 @@ impl Link {
     }
 }
 
+/// Custom CSS colors of parties by name and links by id.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Colors {
+    #[serde(default)]
+    pub parties: HashMap<String, String>,
+    #[serde(default)]
+    pub links: HashMap<u64, String>,
+}
+
+impl Colors {
+    /// Accepts hex colors like `#336699` and named colors like `teal`, which
+    /// can't break out of a style sheet.
+    pub fn is_valid(color: &str) -> bool {
+        match color.strip_prefix('#') {
+            Some(hex) => [3, 4, 6, 8].contains(&hex.len()) && hex.chars().all(|c| c.is_ascii_hexdigit()),
+            None => !color.is_empty() && color.chars().all(|c| c.is_ascii_alphabetic()),
+        }
+    }
+
+    /// Adds the colors of `other`, replacing existing colors.
+    pub fn merge(&mut self, other: Colors) -> Result<(), String> {
+        if let Some(invalid) = other.parties.values().chain(other.links.values()).find(|color| !Colors::is_valid(color)) {
+            return Err(format!("Invalid color `{invalid}`"));
+        }
+        self.parties.extend(other.parties);
+        self.links.extend(other.links);
+        Ok(())
+    }
+}
+
+/// Name of the lane which the parties outside of [`Filter::parties`] are
+/// collapsed into. A number is added when a party of the session has the
+/// name already, like `(others 2)`.
+pub const OTHERS: &str = "(others)";
+
+/// Selects a part of a session, everything by default.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Filter {
+    /// Lowest id of the links to keep.
+    pub first_link: Option<u64>,
+    /// Highest id of the links to keep.
+    pub last_link: Option<u64>,
+    /// Earliest timestamp of the links to keep.
+    pub start: Option<DateTime<Utc>>,
+    /// Latest timestamp of the links to keep.
+    pub end: Option<DateTime<Utc>>,
+    /// Names of the parties to keep, all parties when `None`.
+    pub parties: Option<Vec<String>>,
+    /// Keeps the links between kept and other parties with the other parties
+    /// collapsed into one lane, instead of hiding them.
+    pub collapse_others: bool,
+}
+
+impl Filter {
+    fn keeps_party(&self, party: &Party) -> bool {
+        self.parties.as_ref().is_none_or(|names| names.contains(&party.name))
+    }
+
+    fn keeps_time(&self, link: &Link) -> bool {
+        self.start.is_none_or(|start| link.timestamp >= start)
+            && self.end.is_none_or(|end| link.timestamp <= end)
+    }
+}
+
+/// Cloning shares the links and parties, a cheap snapshot to render from
+/// without holding the lock of the session.
+#[derive(Clone)]
 pub struct SessionInner {
     pub id: u64,
     pub links: Vec<Arc<Link>>,
@@ -87,6 +155,8 @@ pub struct SessionInner {
 
     pub parties_highest_id: u64,
     pub links_highest_id: u64,
+
+    pub colors: Colors,
 }
 
 impl SessionInner {
@@ -97,6 +167,7 @@ impl SessionInner {
             parties: HashSet::new(),
             parties_highest_id: 0,
             links_highest_id: 0,
+            colors: Colors::default(),
         }
     }
 
@@ -125,7 +196,118 @@ impl SessionInner {
         self.links_highest_id
     }
 
+    /// The links selected by `filter` with their parties. Links keep their
+    /// ids, links between two collapsed parties are left out.
+    pub fn filter(&self, filter: &Filter) -> SessionInner {
+        let mut filtered = SessionInner {
+            links: vec![],
+            parties: HashSet::new(),
+            colors: self.colors.clone(),
+            ..*self
+        };
+        let mut others = None;
+
+        let first = self.links.partition_point(|link| link.id < filter.first_link.unwrap_or(0));
+        for link in &self.links[first..] {
+            if filter.last_link.is_some_and(|last| link.id > last) {
+                break;
+            }
+            if !filter.keeps_time(link) {
+                continue;
+            }
+            let link = match (filter.keeps_party(&link.from), filter.keeps_party(&link.to)) {
+                (true, true) => Arc::clone(link),
+                (false, false) => continue,
+                _ if !filter.collapse_others => continue,
+                (keeps_from, keeps_to) => {
+                    let others = others.get_or_insert_with(|| Arc::new(Party {
+                        id: self.parties_highest_id + 1,
+                        name: (1..)
+                            .map(|n| if n == 1 { OTHERS.to_string() } else { format!("(others {n})") })
+                            .find(|name| self.party(name).is_none())
+                            .unwrap(),
+                        label: None,
+                    }));
+                    Arc::new(Link {
+                        id: link.id,
+                        timestamp: link.timestamp,
+                        from: Arc::clone(if keeps_from { &link.from } else { others }),
+                        to: Arc::clone(if keeps_to { &link.to } else { others }),
+                        label: Arc::clone(&link.label),
+                    })
+                }
+            };
+            filtered.parties.insert(PartyMappedByName(Arc::clone(&link.from)));
+            filtered.parties.insert(PartyMappedByName(Arc::clone(&link.to)));
+            filtered.links.push(link);
+        }
+
+        if others.is_some() {
+            filtered.parties_highest_id += 1;
+        }
+        filtered
+    }
+
+    /// Copies the parties, links and colors up to the link `until`.
+    pub fn fork(&self, until: Option<u64>) -> SessionInner {
+        let until = until.unwrap_or(u64::MAX);
+        let mut fork = SessionInner::new(0);
+        fork.add_parties_of(self);
+        for link in self.links.iter().take_while(|link| link.id <= until) {
+            let id = fork.add_link(link.timestamp, &link.from.name, &link.to.name, link.label.as_deref());
+            if let Some(color) = self.colors.links.get(&link.id) {
+                fork.colors.links.insert(id, color.clone());
+            }
+        }
+        fork.colors.parties = self.colors.parties.clone();
+        fork
+    }
+
+    /// Links of both sessions ordered by timestamp, links of this session
+    /// first at the same time. Party labels and colors of this session win.
+    pub fn merge(&self, other: &SessionInner) -> SessionInner {
+        let mut merged = SessionInner::new(0);
+        merged.add_parties_of(self);
+        merged.add_parties_of(other);
+
+        let mut links = self.links.iter().map(|link| (link, &self.colors))
+            .chain(other.links.iter().map(|link| (link, &other.colors)))
+            .collect::<Vec<_>>();
+        links.sort_by_key(|(link, _)| link.timestamp);
+        for (link, colors) in links {
+            let id = merged.add_link(link.timestamp, &link.from.name, &link.to.name, link.label.as_deref());
+            if let Some(color) = colors.links.get(&link.id) {
+                merged.colors.links.insert(id, color.clone());
+            }
+        }
+
+        for (name, color) in other.colors.parties.iter().chain(&self.colors.parties) {
+            merged.colors.parties.insert(name.clone(), color.clone());
+        }
+        merged
+    }
+
+    /// Adds the parties of `other` with their labels in order of their ids.
+    fn add_parties_of(&mut self, other: &SessionInner) {
+        let mut parties = other.parties.iter().map(|party| &party.0).collect::<Vec<_>>();
+        parties.sort();
+        for party in parties {
+            self.add_labeled_party(&party.name, party.label.as_deref());
+        }
+    }
+
+    pub fn party(&self, name: &str) -> Option<&Arc<Party>> {
+        self.parties.get(&PartyMappedByName(Arc::new(Party::new(name))))
+            .map(|party| &party.0)
+    }
+
     pub fn add_party(&mut self, name: &str) -> Arc<Party> {
+        self.add_labeled_party(name, None)
+    }
+
+    /// Adds a party with a display label. An existing party is returned
+    /// unchanged.
+    pub fn add_labeled_party(&mut self, name: &str, label: Option<&str>) -> Arc<Party> {
         let mut party = PartyMappedByName(Arc::new(Party::new(name)));
 
         if let Some(existing_party) = self.parties.get(&party) {
@@ -133,7 +315,9 @@ impl SessionInner {
         }
 
         self.parties_highest_id += 1;
-        Arc::<Party>::get_mut(&mut party.0).unwrap().id = self.parties_highest_id;
+        let new_party = Arc::<Party>::get_mut(&mut party.0).unwrap();
+        new_party.id = self.parties_highest_id;
+        new_party.label = label.map(ToString::to_string);
         self.parties.insert(party.clone());
         party.0
     }
@@ -151,6 +335,12 @@ fn get_random() -> u64 {
     u64::from_be_bytes(buf)
 }
 
+impl Default for Sessions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Sessions {
     pub fn new() -> Self {
         Self {
@@ -159,6 +349,11 @@ impl Sessions {
     }
 
     pub fn new_session(&self) -> Arc<RwLock<SessionInner>> {
+        self.add_session(SessionInner::new(0))
+    }
+
+    /// Adds an already populated session under a new id.
+    pub fn add_session(&self, mut session: SessionInner) -> Arc<RwLock<SessionInner>> {
         let mut sessions = self.sessions.write().unwrap();
 
         let mut id = get_random();
@@ -166,7 +361,8 @@ impl Sessions {
             id = get_random();
         }
 
-        let session = Arc::new(RwLock::new(SessionInner::new(id)));
+        session.id = id;
+        let session = Arc::new(RwLock::new(session));
         sessions.insert(id, session.clone());
         session
     }
@@ -235,4 +431,114 @@ mod tests {
         assert_eq!(4, highest_id);
         assert!(links_above.is_empty());
     }
+
+    #[test]
+    fn validate_colors() {
+        assert!(Colors::is_valid("#336699"));
+        assert!(Colors::is_valid("teal"));
+        assert!(!Colors::is_valid("#33669"));
+        assert!(!Colors::is_valid("red; } svg { display: none"));
+
+        let mut colors = Colors::default();
+        let update = Colors { links: HashMap::from([(1, "url(x)".to_string())]), ..Colors::default() };
+        assert_eq!(Err("Invalid color `url(x)`".to_string()), colors.merge(update));
+        assert!(colors.links.is_empty());
+    }
+
+    #[test]
+    fn filter_links() {
+        let session = simple_graph();
+        let session = session.read().unwrap();
+        let names = |session: &SessionInner| session.links.iter()
+            .map(|link| format!("{}:{}->{}", link.id, link.from.name, link.to.name))
+            .collect::<Vec<_>>();
+
+        let filter = Filter { first_link: Some(2), last_link: Some(3), ..Default::default() };
+        assert_eq!(["2:b->c", "3:c->b"], names(&session.filter(&filter)).as_slice());
+        assert_eq!(2, session.filter(&filter).parties.len());
+
+        let filter = Filter { start: Some(session.links[1].timestamp), end: Some(session.links[2].timestamp), ..Default::default() };
+        assert_eq!(["2:b->c", "3:c->b"], names(&session.filter(&filter)).as_slice());
+
+        let mut filter = Filter { parties: Some(vec!["a".to_string(), "b".to_string()]), ..Default::default() };
+        assert_eq!(["1:a->b", "4:b->a"], names(&session.filter(&filter)).as_slice());
+
+        filter.collapse_others = true;
+        let filtered = session.filter(&filter);
+        assert_eq!(["1:a->b", "2:b->(others)", "3:(others)->b", "4:b->a"], names(&filtered).as_slice());
+        assert_eq!(4, filtered.party(OTHERS).unwrap().id);
+        assert_eq!(4, filtered.parties_highest_id);
+    }
+
+    #[test]
+    fn collapse_others_beside_party_of_same_name() {
+        let mut session = SessionInner::new(0);
+        let now = Utc::now();
+        session.add_link(now, "a", OTHERS, None);
+        session.add_link(now, "a", "b", None);
+        let filter = Filter {
+            parties: Some(vec!["a".to_string(), OTHERS.to_string()]),
+            collapse_others: true,
+            ..Default::default()
+        };
+        let filtered = session.filter(&filter);
+        let names = filtered.links.iter().map(|link| link.to.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(vec![OTHERS, "(others 2)"], names);
+        assert_eq!(3, filtered.parties.len());
+        assert_eq!(4, filtered.party("(others 2)").unwrap().id);
+    }
+
+    #[test]
+    fn fork_until_link() {
+        let session = simple_graph();
+        let mut session = session.write().unwrap();
+        session.colors.links.insert(1, "red".to_string());
+        session.colors.links.insert(3, "blue".to_string());
+
+        let fork = session.fork(Some(2));
+        assert_eq!(2, fork.links.len());
+        assert_eq!(3, fork.parties.len());
+        assert_eq!(session.links[1].timestamp, fork.links[1].timestamp);
+        assert_eq!(vec![(&1, &"red".to_string())], fork.colors.links.iter().collect::<Vec<_>>());
+        assert_eq!(4, session.fork(None).links.len());
+    }
+
+    #[test]
+    fn fork_with_gaps_in_ids() {
+        let session = simple_graph();
+        let mut archive = crate::archive::Archive::from(&*session.read().unwrap());
+        for (link, id) in archive.links.iter_mut().zip([2, 5, 9, 10]) {
+            link.id = id;
+        }
+        archive.colors.links.insert(5, "red".to_string());
+        archive.colors.links.insert(9, "blue".to_string());
+        let session = SessionInner::try_from(archive).unwrap();
+
+        let fork = session.fork(Some(9));
+        assert_eq!(vec![1, 2, 3], fork.links.iter().map(|link| link.id).collect::<Vec<_>>());
+        let mut colors = fork.colors.links.iter().collect::<Vec<_>>();
+        colors.sort();
+        assert_eq!(vec![(&2, &"red".to_string()), (&3, &"blue".to_string())], colors);
+    }
+
+    #[test]
+    fn merge_by_timestamp() {
+        let now = Utc::now();
+        let mut web = SessionInner::new(0);
+        web.add_labeled_party("web", Some("Web"));
+        web.add_link(now, "web", "api", Some("GET /items"));
+        web.add_link(now + chrono::Duration::seconds(3), "api", "web", Some("200"));
+        let mut api = SessionInner::new(0);
+        api.add_link(now, "api", "db", Some("select"));
+        api.add_link(now + chrono::Duration::seconds(2), "db", "api", Some("rows"));
+        api.colors.links.insert(2, "green".to_string());
+
+        let merged = web.merge(&api);
+        assert_eq!(
+            vec!["GET /items", "select", "rows", "200"],
+            merged.links.iter().map(|link| link.label.as_deref().unwrap()).collect::<Vec<_>>());
+        assert_eq!(Some("Web"), merged.party("web").unwrap().label.as_deref());
+        assert_eq!(3, merged.party("db").unwrap().id);
+        assert_eq!(Some(&"green".to_string()), merged.colors.links.get(&3));
+    }
 }
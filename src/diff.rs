@@ -0,0 +1,227 @@
+use crate::data::{Link, SessionInner};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// Color of links only in the current session.
+pub const ADDED_COLOR: &str = "green";
+/// Color of links only in the baseline.
+pub const REMOVED_COLOR: &str = "red";
+/// Color of links with another label in the current session.
+pub const CHANGED_COLOR: &str = "orange";
+
+/// Largest number of compared link pairs after the common start and end,
+/// longer differences are shown as removed and added.
+pub const MAX_ALIGNED_PAIRS: usize = 4_000_000;
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Kind {
+    Same,
+    Added,
+    Removed,
+    /// Between the same parties with another label.
+    Changed,
+}
+
+/// A link of the baseline aligned with a link of the current session.
+#[derive(Clone)]
+pub struct Entry {
+    pub kind: Kind,
+    /// `None` when added.
+    pub baseline: Option<Arc<Link>>,
+    /// `None` when removed.
+    pub current: Option<Arc<Link>>,
+}
+
+/// Links of two sessions, aligned to keep as many links as possible the same.
+pub struct Diff {
+    pub entries: Vec<Entry>,
+}
+
+fn same_parties(a: &Link, b: &Link) -> bool {
+    a.from.name == b.from.name && a.to.name == b.to.name
+}
+
+/// Score of aligning two links, links with other parties can't be aligned.
+fn score(a: &Link, b: &Link) -> u32 {
+    match (same_parties(a, b), a.label == b.label) {
+        (true, true) => 2,
+        (true, false) => 1,
+        (false, _) => 0,
+    }
+}
+
+impl Entry {
+    fn aligned(baseline: &Arc<Link>, current: &Arc<Link>) -> Self {
+        Entry {
+            kind: if baseline.label == current.label { Kind::Same } else { Kind::Changed },
+            baseline: Some(Arc::clone(baseline)),
+            current: Some(Arc::clone(current)),
+        }
+    }
+
+    fn removed(baseline: &Arc<Link>) -> Self {
+        Entry { kind: Kind::Removed, baseline: Some(Arc::clone(baseline)), current: None }
+    }
+
+    fn added(current: &Arc<Link>) -> Self {
+        Entry { kind: Kind::Added, baseline: None, current: Some(Arc::clone(current)) }
+    }
+
+    /// The current link, the baseline link when removed.
+    pub fn link(&self) -> &Arc<Link> {
+        self.current.as_ref().or(self.baseline.as_ref()).unwrap()
+    }
+}
+
+/// Aligns `baseline` and `current` with the highest total score.
+fn align(baseline: &[Arc<Link>], current: &[Arc<Link>], entries: &mut Vec<Entry>) {
+    let (n, m) = (baseline.len(), current.len());
+    if n * m > MAX_ALIGNED_PAIRS {
+        entries.extend(baseline.iter().map(Entry::removed));
+        entries.extend(current.iter().map(Entry::added));
+        return;
+    }
+
+    // Best score of the links from `i` and `j` on, at `i * (m + 1) + j`
+    let mut scores = vec![0u32; (n + 1) * (m + 1)];
+    let at = |i: usize, j: usize| i * (m + 1) + j;
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            let aligned = match score(&baseline[i], &current[j]) {
+                0 => 0,
+                score => scores[at(i + 1, j + 1)] + score,
+            };
+            scores[at(i, j)] = aligned.max(scores[at(i + 1, j)]).max(scores[at(i, j + 1)]);
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        let score = score(&baseline[i], &current[j]);
+        if score > 0 && scores[at(i, j)] == scores[at(i + 1, j + 1)] + score {
+            entries.push(Entry::aligned(&baseline[i], &current[j]));
+            i += 1;
+            j += 1;
+        } else if scores[at(i, j)] == scores[at(i + 1, j)] {
+            entries.push(Entry::removed(&baseline[i]));
+            i += 1;
+        } else {
+            entries.push(Entry::added(&current[j]));
+            j += 1;
+        }
+    }
+    entries.extend(baseline[i..].iter().map(Entry::removed));
+    entries.extend(current[j..].iter().map(Entry::added));
+}
+
+impl Diff {
+    pub fn new(baseline: &SessionInner, current: &SessionInner) -> Self {
+        let (baseline, current) = (&baseline.links, &current.links);
+        let identical = |(a, b): (&Arc<Link>, &Arc<Link>)| same_parties(a, b) && a.label == b.label;
+        let prefix = baseline.iter().zip(current).take_while(|pair| identical(*pair)).count();
+        let suffix = baseline[prefix..].iter().rev()
+            .zip(current[prefix..].iter().rev())
+            .take_while(|pair| identical(*pair))
+            .count();
+
+        let mut entries = vec![];
+        for (baseline, current) in baseline.iter().zip(current).take(prefix) {
+            entries.push(Entry::aligned(baseline, current));
+        }
+        align(&baseline[prefix..baseline.len() - suffix], &current[prefix..current.len() - suffix], &mut entries);
+        for (baseline, current) in baseline[baseline.len() - suffix..].iter().zip(&current[current.len() - suffix..]) {
+            entries.push(Entry::aligned(baseline, current));
+        }
+        Diff { entries }
+    }
+
+    pub fn count(&self, kind: Kind) -> usize {
+        self.entries.iter().filter(|entry| entry.kind == kind).count()
+    }
+
+    /// A session with the links of both sessions in aligned order, colored by
+    /// their change. Changed links show both labels.
+    pub fn session(&self, id: u64) -> SessionInner {
+        let mut session = SessionInner::new(id);
+        for entry in &self.entries {
+            let link = entry.link();
+            for party in [&link.from, &link.to] {
+                session.add_labeled_party(&party.name, party.label.as_deref());
+            }
+            let label = match (entry.kind, &entry.baseline) {
+                (Kind::Changed, Some(baseline)) => Some(format!(
+                    "{}\n(was: {})",
+                    link.label.as_deref().unwrap_or_default(),
+                    baseline.label.as_deref().unwrap_or_default(),
+                )),
+                _ => link.label.as_ref().clone(),
+            };
+            let id = session.add_link(link.timestamp, &link.from.name, &link.to.name, label.as_deref());
+            let color = match entry.kind {
+                Kind::Same => continue,
+                Kind::Added => ADDED_COLOR,
+                Kind::Removed => REMOVED_COLOR,
+                Kind::Changed => CHANGED_COLOR,
+            };
+            session.colors.links.insert(id, color.to_string());
+        }
+        session
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::Utc;
+
+    fn session(links: &[(&str, &str, &str)]) -> SessionInner {
+        let mut session = SessionInner::new(0);
+        for (from, to, label) in links {
+            session.add_link(Utc::now(), from, to, Some(label));
+        }
+        session
+    }
+
+    #[test]
+    fn align_sequences() {
+        let baseline = session(&[
+            ("web", "api", "login"),
+            ("api", "db", "select user"),
+            ("api", "web", "200"),
+            ("web", "api", "GET /items"),
+            ("api", "web", "200"),
+        ]);
+        let current = session(&[
+            ("web", "api", "login"),
+            ("api", "cache", "get user"),
+            ("api", "web", "200"),
+            ("web", "api", "GET /items?page=1"),
+            ("api", "web", "200"),
+        ]);
+
+        let diff = Diff::new(&baseline, &current);
+        assert_eq!(
+            vec![Kind::Same, Kind::Removed, Kind::Added, Kind::Same, Kind::Changed, Kind::Same],
+            diff.entries.iter().map(|entry| entry.kind).collect::<Vec<_>>());
+        assert_eq!(Some(2), diff.entries[1].baseline.as_ref().map(|link| link.id));
+        assert_eq!(Some(2), diff.entries[2].current.as_ref().map(|link| link.id));
+        assert_eq!((1, 1, 1), (diff.count(Kind::Added), diff.count(Kind::Removed), diff.count(Kind::Changed)));
+
+        let session = diff.session(7);
+        assert_eq!(6, session.links.len());
+        assert_eq!(4, session.parties.len());
+        assert_eq!(Some("GET /items?page=1\n(was: GET /items)"), session.links[4].label.as_deref());
+        assert_eq!(Some(&REMOVED_COLOR.to_string()), session.colors.links.get(&2));
+        assert_eq!(Some(&ADDED_COLOR.to_string()), session.colors.links.get(&3));
+        assert!(!session.colors.links.contains_key(&1));
+    }
+
+    #[test]
+    fn identical_sessions() {
+        let baseline = session(&[("a", "b", "1"), ("b", "a", "2")]);
+        let diff = Diff::new(&baseline, &baseline);
+        assert!(diff.entries.iter().all(|entry| entry.kind == Kind::Same));
+        assert_eq!(2, diff.entries.len());
+    }
+}
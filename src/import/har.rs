@@ -0,0 +1,192 @@
+use super::ParseError;
+use crate::data::SessionInner;
+
+use chrono::{DateTime, Duration, FixedOffset, Utc};
+use rocket::serde::json::serde_json;
+use serde::Deserialize;
+
+/// Party sending all requests.
+pub const BROWSER_PARTY: &str = "browser";
+
+#[derive(Deserialize)]
+struct Har {
+    log: Log,
+}
+
+#[derive(Deserialize)]
+struct Log {
+    #[serde(default)]
+    entries: Vec<Entry>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Entry {
+    started_date_time: DateTime<FixedOffset>,
+    /// Total milliseconds until the response was received.
+    time: f64,
+    request: Request,
+    response: Response,
+    timings: Option<Timings>,
+}
+
+#[derive(Deserialize)]
+struct Request {
+    method: String,
+    url: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Response {
+    status: u16,
+    #[serde(default)]
+    status_text: String,
+}
+
+/// Milliseconds of the phases of a request, -1 when not applicable.
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct Timings {
+    blocked: f64,
+    dns: f64,
+    connect: f64,
+}
+
+impl Timings {
+    /// Milliseconds until the request was sent, the TLS handshake is part
+    /// of `connect`.
+    fn before_send(&self) -> f64 {
+        [self.blocked, self.dns, self.connect].iter().map(|time| time.max(0.0)).sum()
+    }
+}
+
+/// Duration of non-negative milliseconds, `None` when out of range.
+fn millis(time: f64) -> Option<Duration> {
+    let micros = time.max(0.0) * 1000.0;
+    (micros < i64::MAX as f64).then(|| Duration::microseconds(micros as i64))
+}
+
+/// Host with port and path of an absolute URL, `None` for URLs without host
+/// like `data:` URLs.
+fn split_url(url: &str) -> Option<(&str, &str)> {
+    let (_, rest) = url.split_once("://")?;
+    let rest = rest.split(['?', '#']).next().unwrap_or_default();
+    let (host, path) = rest.find('/').map_or((rest, "/"), |index| rest.split_at(index));
+    let host = host.rsplit('@').next().unwrap_or(host);
+    (!host.is_empty()).then_some((host, path))
+}
+
+/// Parses a HTTP archive into a new session. The browser and the hosts of
+/// the requests become parties, each entry a request labeled with method and
+/// path at the time it was sent and a response labeled with the status at the
+/// time it was received. Entries without host or with out of range timings
+/// are skipped.
+pub fn parse(source: &str) -> Result<SessionInner, ParseError> {
+    let har: Har = serde_json::from_str(source)?;
+
+    let mut links = vec![];
+    for entry in &har.log.entries {
+        let Some((host, path)) = split_url(&entry.request.url) else { continue };
+        let started = entry.started_date_time.with_timezone(&Utc);
+        let before_send = entry.timings.as_ref().map(Timings::before_send).unwrap_or_default();
+        let Some(sent) = millis(before_send).and_then(|time| started.checked_add_signed(time)) else { continue };
+        let Some(received) = millis(entry.time).and_then(|time| started.checked_add_signed(time)) else { continue };
+        let received = received.max(sent);
+        let status = match (entry.response.status, entry.response.status_text.as_str()) {
+            (0, _) => "failed".to_string(),
+            (status, "") => status.to_string(),
+            (status, text) => format!("{status} {text}"),
+        };
+        links.push((sent, BROWSER_PARTY, host, format!("{} {path}", entry.request.method)));
+        links.push((received, host, BROWSER_PARTY, status));
+    }
+    links.sort_by_key(|(timestamp, ..)| *timestamp);
+
+    let mut session = SessionInner::new(0);
+    session.add_party(BROWSER_PARTY);
+    for (timestamp, from, to, label) in links {
+        session.add_link(timestamp, from, to, Some(&label));
+    }
+    Ok(session)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn entries_with_timings() {
+        let source = r#"{"log": {"version": "1.2", "creator": {"name": "Firefox", "version": "120"}, "entries": [
+            {
+                "startedDateTime": "2024-03-01T10:00:00.000+01:00", "time": 120.5,
+                "request": {"method": "GET", "url": "https://shop.example.com/", "headers": []},
+                "response": {"status": 200, "statusText": "OK", "headers": []},
+                "timings": {"blocked": 1, "dns": 10, "connect": 20, "ssl": 15, "send": 0, "wait": 80, "receive": 9.5}
+            },
+            {
+                "startedDateTime": "2024-03-01T09:00:00.050Z", "time": 30,
+                "request": {"method": "POST", "url": "https://api.example.com:8443/v1/cart?session=1#top"},
+                "response": {"status": 201, "statusText": ""},
+                "timings": {"blocked": -1, "dns": -1, "connect": -1, "send": 1, "wait": 28, "receive": 1}
+            },
+            {
+                "startedDateTime": "2024-03-01T09:00:00.100Z", "time": 0,
+                "request": {"method": "GET", "url": "data:image/png;base64,AAAA"},
+                "response": {"status": 200}
+            },
+            {
+                "startedDateTime": "2024-03-01T09:00:00.200Z", "time": -1,
+                "request": {"method": "GET", "url": "https://cdn.example.com"},
+                "response": {"status": 0, "statusText": ""}
+            }
+        ]}}"#;
+
+        let session = parse(source).unwrap();
+        let links = session.links.iter()
+            .map(|link| (link.from.name.as_str(), link.to.name.as_str(), link.label.as_deref().unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(vec![
+            ("browser", "shop.example.com", "GET /"),
+            ("browser", "api.example.com:8443", "POST /v1/cart"),
+            ("api.example.com:8443", "browser", "201"),
+            ("shop.example.com", "browser", "200 OK"),
+            ("browser", "cdn.example.com", "GET /"),
+            ("cdn.example.com", "browser", "failed"),
+        ], links);
+        assert_eq!(31, session.links[0].timestamp.timestamp_subsec_millis());
+        assert_eq!(120, session.links[3].timestamp.timestamp_subsec_millis());
+        assert_eq!(4, session.parties.len());
+    }
+
+    #[test]
+    fn invalid_archive() {
+        let source = "{\"log\": {\"entries\": [\n{\"startedDateTime\": \"yesterday\"}]}}";
+        assert_eq!(2, parse(source).map(|_| ()).unwrap_err().line);
+    }
+
+    #[test]
+    fn skip_out_of_range_timings() {
+        let source = r#"{"log": {"entries": [
+            {
+                "startedDateTime": "2024-03-01T09:00:00Z", "time": 1e300,
+                "request": {"method": "GET", "url": "https://a.example.com/"},
+                "response": {"status": 200}
+            },
+            {
+                "startedDateTime": "2024-03-01T09:00:00Z", "time": 9223372036854775,
+                "request": {"method": "GET", "url": "https://b.example.com/"},
+                "response": {"status": 200},
+                "timings": {"blocked": 1e18}
+            },
+            {
+                "startedDateTime": "2024-03-01T09:00:00Z", "time": 10,
+                "request": {"method": "GET", "url": "https://c.example.com/"},
+                "response": {"status": 200}
+            }
+        ]}}"#;
+        let session = parse(source).unwrap();
+        let hosts = session.links.iter().map(|link| link.to.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(vec!["c.example.com", BROWSER_PARTY], hosts);
+    }
+}
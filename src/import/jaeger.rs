@@ -0,0 +1,204 @@
+use super::{trace::{self, Kind, Span}, ParseError};
+use crate::data::SessionInner;
+
+use chrono::DateTime;
+use rocket::serde::json::serde_json::{self, Value};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// Service of spans without process.
+pub const UNKNOWN_SERVICE: &str = "unknown";
+
+/// Tags naming the peer of a call, in order of preference.
+const PEER_TAGS: [&str; 4] = ["peer.service", "db.type", "peer.hostname", "peer.address"];
+
+/// Traces as returned by the Jaeger query API and downloaded by its UI.
+#[derive(Deserialize)]
+struct Export {
+    data: Vec<JaegerTrace>,
+}
+
+#[derive(Deserialize)]
+struct JaegerTrace {
+    spans: Vec<JaegerSpan>,
+    #[serde(default)]
+    processes: HashMap<String, Process>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JaegerSpan {
+    #[serde(rename = "traceID")]
+    trace_id: String,
+    #[serde(rename = "spanID")]
+    span_id: String,
+    #[serde(default)]
+    operation_name: String,
+    #[serde(default)]
+    references: Vec<Reference>,
+    /// Microseconds since the epoch.
+    start_time: i64,
+    /// Microseconds.
+    #[serde(default)]
+    duration: i64,
+    #[serde(default)]
+    tags: Vec<Tag>,
+    #[serde(rename = "processID")]
+    process_id: Option<String>,
+    /// Inline process of older exports.
+    process: Option<Process>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Reference {
+    ref_type: String,
+    #[serde(rename = "spanID")]
+    span_id: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Process {
+    service_name: String,
+}
+
+#[derive(Deserialize)]
+struct Tag {
+    key: String,
+    value: Value,
+}
+
+impl JaegerSpan {
+    fn tag(&self, key: &str) -> Option<String> {
+        self.tags.iter()
+            .find(|tag| tag.key == key)
+            .map(|tag| match &tag.value {
+                Value::String(value) => value.clone(),
+                value => value.to_string(),
+            })
+    }
+
+    fn into_span(self, processes: &HashMap<String, Process>) -> Option<Span> {
+        let start = DateTime::from_timestamp_micros(self.start_time)?;
+        let process = self.process.as_ref()
+            .or_else(|| self.process_id.as_ref().and_then(|id| processes.get(id)));
+        // The parent, or the span it follows from without parent
+        let parent_id = self.references.iter()
+            .find(|reference| reference.ref_type == "CHILD_OF")
+            .or(self.references.first())
+            .map(|reference| reference.span_id.to_lowercase());
+        Some(Span {
+            kind: match self.tag("span.kind").as_deref() {
+                Some("client") => Kind::Client,
+                Some("server") => Kind::Server,
+                Some("producer") => Kind::Producer,
+                Some("consumer") => Kind::Consumer,
+                _ => Kind::Internal,
+            },
+            service: process.map(|process| process.service_name.clone())
+                .unwrap_or_else(|| UNKNOWN_SERVICE.to_string()),
+            peer: PEER_TAGS.iter().find_map(|key| self.tag(key)),
+            status: self.tag("http.status_code")
+                .or_else(|| (self.tag("error").as_deref() == Some("true")).then(|| "error".to_string())),
+            end: start.checked_add_signed(chrono::Duration::microseconds(self.duration))?,
+            start,
+            parent_id,
+            trace_id: self.trace_id.to_lowercase(),
+            id: self.span_id.to_lowercase(),
+            name: self.operation_name,
+        })
+    }
+}
+
+/// Parses traces exported as Jaeger JSON into a new session. The services of
+/// the span processes become parties, calls between them links. Spans with
+/// an out of range start or duration are skipped.
+pub fn parse(source: &str) -> Result<SessionInner, ParseError> {
+    let export: Export = serde_json::from_str(source)?;
+    let spans = export.data.into_iter()
+        .flat_map(|trace| {
+            let processes = trace.processes;
+            trace.spans.into_iter().filter_map(move |span| span.into_span(&processes))
+        })
+        .collect::<Vec<_>>();
+    Ok(trace::session(&spans))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn processes_and_references() {
+        let source = r#"{"data": [{
+            "traceID": "3a7c2b1f",
+            "spans": [
+                {
+                    "traceID": "3a7c2b1f", "spanID": "a1", "operationName": "HTTP GET /dispatch",
+                    "references": [], "startTime": 1700000000000000, "duration": 900000,
+                    "tags": [{"key": "span.kind", "type": "string", "value": "server"}],
+                    "processID": "p1"
+                },
+                {
+                    "traceID": "3a7c2b1f", "spanID": "b2", "operationName": "HTTP GET",
+                    "references": [{"refType": "CHILD_OF", "traceID": "3a7c2b1f", "spanID": "a1"}],
+                    "startTime": 1700000000100000, "duration": 300000,
+                    "tags": [{"key": "span.kind", "type": "string", "value": "client"}],
+                    "processID": "p1"
+                },
+                {
+                    "traceID": "3a7c2b1f", "spanID": "c3", "operationName": "HTTP GET /customer",
+                    "references": [{"refType": "CHILD_OF", "traceID": "3a7c2b1f", "spanID": "b2"}],
+                    "startTime": 1700000000150000, "duration": 200000,
+                    "tags": [
+                        {"key": "span.kind", "type": "string", "value": "server"},
+                        {"key": "http.status_code", "type": "int64", "value": 200}
+                    ],
+                    "processID": "p2"
+                },
+                {
+                    "traceID": "3a7c2b1f", "spanID": "d4", "operationName": "SQL SELECT",
+                    "references": [{"refType": "CHILD_OF", "traceID": "3a7c2b1f", "spanID": "c3"}],
+                    "startTime": 1700000000200000, "duration": 50000,
+                    "tags": [
+                        {"key": "span.kind", "type": "string", "value": "client"},
+                        {"key": "peer.service", "type": "string", "value": "mysql"},
+                        {"key": "error", "type": "bool", "value": true}
+                    ],
+                    "processID": "p2"
+                }
+            ],
+            "processes": {
+                "p1": {"serviceName": "frontend", "tags": []},
+                "p2": {"serviceName": "customer", "tags": []}
+            }
+        }]}"#;
+
+        let session = parse(source).unwrap();
+        let links = session.links.iter()
+            .map(|link| (link.from.name.as_str(), link.to.name.as_str(), link.label.as_deref()))
+            .collect::<Vec<_>>();
+        assert_eq!(vec![
+            (trace::EXTERNAL_PARTY, "frontend", Some("HTTP GET /dispatch")),
+            ("frontend", "customer", Some("HTTP GET /customer")),
+            ("customer", "mysql", Some("SQL SELECT")),
+            ("mysql", "customer", Some("error")),
+            ("customer", "frontend", Some("200")),
+            ("frontend", trace::EXTERNAL_PARTY, None),
+        ], links);
+    }
+
+    #[test]
+    fn skip_out_of_range_duration() {
+        let source = r#"{"data": [{"traceID": "a", "spans": [
+            {"traceID": "a", "spanID": "1", "operationName": "x", "startTime": 1, "duration": 9223372036854775807}
+        ]}]}"#;
+        assert!(parse(source).unwrap().links.is_empty());
+    }
+
+    #[test]
+    fn reject_other_json() {
+        assert!(parse(r#"[{"traceId": "a"}]"#).is_err());
+    }
+}
@@ -0,0 +1,259 @@
+//! Reconstructs links from text logs with regular expressions, configured as
+//! named rule sets.
+
+use super::ParseError;
+use crate::data::SessionInner;
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use regex::Regex;
+use serde::Deserialize;
+use std::{collections::HashMap, fmt};
+
+/// A regular expression matching log lines of one kind of message. The named
+/// captures `from`, `to`, `label` and `timestamp` give the link, or the
+/// fixed values of the rule where the capture is missing.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+pub struct Rule {
+    pub pattern: String,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub label: Option<String>,
+}
+
+/// Rules tried in order for each log line, as configured in the `log_rules`
+/// table of the server config.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+pub struct RuleSet {
+    pub rules: Vec<Rule>,
+    /// `chrono` format of captured timestamps, RFC 3339 if not set. Formats
+    /// without offset are read as UTC.
+    pub timestamp_format: Option<String>,
+}
+
+/// Why a rule can't be used.
+#[derive(Debug, PartialEq)]
+pub struct RuleError {
+    /// Index of the rule in its set.
+    pub rule: usize,
+    pub message: String,
+}
+
+impl fmt::Display for RuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "rule {}: {}", self.rule, self.message)
+    }
+}
+
+impl std::error::Error for RuleError {}
+
+/// A link found in a log line, without timestamp when the rule has none.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Extracted {
+    pub timestamp: Option<DateTime<Utc>>,
+    pub from: String,
+    pub to: String,
+    pub label: Option<String>,
+}
+
+/// A [`RuleSet`] with compiled expressions.
+pub struct Rules {
+    rules: Vec<(Regex, Rule)>,
+    timestamp_format: Option<String>,
+}
+
+impl Rules {
+    /// Compiles the expressions, each rule needs to give both parties.
+    pub fn new(set: &RuleSet) -> Result<Self, RuleError> {
+        let rules = set.rules.iter().enumerate()
+            .map(|(index, rule)| {
+                let error = |message: String| RuleError { rule: index, message };
+                let regex = Regex::new(&rule.pattern).map_err(|e| error(e.to_string()))?;
+                let captures = regex.capture_names().flatten().collect::<Vec<_>>();
+                for (name, fixed) in [("from", &rule.from), ("to", &rule.to)] {
+                    if fixed.is_none() && !captures.contains(&name) {
+                        return Err(error(format!("Neither a `{name}` capture nor a fixed `{name}`")));
+                    }
+                }
+                Ok((regex, rule.clone()))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Rules {
+            rules,
+            timestamp_format: set.timestamp_format.clone(),
+        })
+    }
+
+    fn timestamp(&self, text: &str) -> Result<DateTime<Utc>, String> {
+        match &self.timestamp_format {
+            Some(format) => DateTime::parse_from_str(text, format)
+                .map(|timestamp| timestamp.with_timezone(&Utc))
+                .or_else(|_| NaiveDateTime::parse_from_str(text, format).map(|timestamp| timestamp.and_utc())),
+            None => DateTime::parse_from_rfc3339(text).map(|timestamp| timestamp.with_timezone(&Utc)),
+        }.map_err(|e| format!("Invalid timestamp `{text}`: {e}"))
+    }
+
+    /// The link of the first rule matching `line`, `None` for other lines.
+    pub fn extract(&self, line: &str) -> Option<Result<Extracted, String>> {
+        self.rules.iter().find_map(|(regex, rule)| {
+            let captures = regex.captures(line)?;
+            let value = |name: &str, fixed: &Option<String>| captures.name(name)
+                .map(|value| value.as_str().trim().to_string())
+                .or_else(|| fixed.clone());
+            let timestamp = match captures.name("timestamp") {
+                Some(timestamp) => match self.timestamp(timestamp.as_str()) {
+                    Ok(timestamp) => Some(timestamp),
+                    Err(e) => return Some(Err(e)),
+                },
+                None => None,
+            };
+            Some(Ok(Extracted {
+                timestamp,
+                from: value("from", &rule.from)?,
+                to: value("to", &rule.to)?,
+                label: value("label", &rule.label),
+            }))
+        })
+    }
+}
+
+/// Compiled rule sets by name.
+#[derive(Default)]
+pub struct RuleSets(pub HashMap<String, Rules>);
+
+impl RuleSets {
+    pub fn new(sets: &HashMap<String, RuleSet>) -> Result<Self, (String, RuleError)> {
+        sets.iter()
+            .map(|(name, set)| Ok((name.clone(), Rules::new(set).map_err(|e| (name.clone(), e))?)))
+            .collect::<Result<_, _>>()
+            .map(RuleSets)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Rules> {
+        self.0.get(name)
+    }
+}
+
+/// Most errors reported for a log, further errors are only counted.
+pub const MAX_ERRORS: usize = 100;
+
+/// Collects the links of a log read line by line into a new session.
+pub struct Import<'r> {
+    rules: &'r Rules,
+    session: SessionInner,
+    now: DateTime<Utc>,
+    lines: usize,
+    errors: Vec<ParseError>,
+    omitted: usize,
+}
+
+impl<'r> Import<'r> {
+    pub fn new(rules: &'r Rules) -> Self {
+        Import {
+            rules,
+            session: SessionInner::new(0),
+            now: Utc::now(),
+            lines: 0,
+            errors: vec![],
+            omitted: 0,
+        }
+    }
+
+    /// Adds the link of the next line if a rule matches it.
+    pub fn line(&mut self, line: &str) {
+        self.lines += 1;
+        match self.rules.extract(line) {
+            Some(Ok(link)) => {
+                self.session.add_link(link.timestamp.unwrap_or(self.now), &link.from, &link.to, link.label.as_deref());
+            }
+            Some(Err(_)) if self.errors.len() >= MAX_ERRORS => self.omitted += 1,
+            Some(Err(e)) => self.errors.push(ParseError::new(self.lines, &e)),
+            None => {}
+        }
+    }
+
+    /// The session, or the first [`MAX_ERRORS`] errors of matched lines
+    /// followed by the number of omitted errors.
+    pub fn finish(mut self) -> Result<SessionInner, Vec<ParseError>> {
+        if self.omitted > 0 {
+            self.errors.push(ParseError::new(0, &format!("{} more errors omitted", self.omitted)));
+        }
+        match self.errors.is_empty() {
+            true => Ok(self.session),
+            false => Err(self.errors),
+        }
+    }
+}
+
+/// Adds a link for each log line matched by `rules` to a new session, at the
+/// captured timestamp or the time of the import.
+pub fn parse(source: &str, rules: &Rules) -> Result<SessionInner, Vec<ParseError>> {
+    let mut import = Import::new(rules);
+    for line in source.lines() {
+        import.line(line);
+    }
+    import.finish()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn rules() -> Rules {
+        Rules::new(&RuleSet {
+            rules: vec![
+                Rule {
+                    pattern: r"^(?P<timestamp>\S+ \S+) \[(?P<from>\w+)\] call (?P<to>\w+): (?P<label>.*)$".to_string(),
+                    ..Default::default()
+                },
+                Rule {
+                    pattern: r"^(?P<timestamp>\S+ \S+) \[billing\] invoice (?P<label>\d+) sent".to_string(),
+                    from: Some("billing".to_string()),
+                    to: Some("mailer".to_string()),
+                    ..Default::default()
+                },
+            ],
+            timestamp_format: Some("%Y-%m-%d %H:%M:%S%.3f".to_string()),
+        }).unwrap()
+    }
+
+    #[test]
+    fn extract_links() {
+        let log = "\
+            2024-03-01 09:00:00.125 [shop] call billing: create invoice\n\
+            2024-03-01 09:00:00.300 [billing] connected to database\n\
+            2024-03-01 09:00:01.000 [billing] invoice 4711 sent\n";
+        let session = parse(log, &rules()).unwrap();
+        let links = session.links.iter()
+            .map(|link| (link.from.name.as_str(), link.to.name.as_str(), link.label.as_deref()))
+            .collect::<Vec<_>>();
+        assert_eq!(vec![("shop", "billing", Some("create invoice")), ("billing", "mailer", Some("4711"))], links);
+        assert_eq!(125, session.links[0].timestamp.timestamp_subsec_millis());
+
+        let errors = parse("\nyesterday 09:00 [shop] call billing: x", &rules()).map(|_| ()).unwrap_err();
+        assert_eq!(2, errors[0].line);
+    }
+
+    #[test]
+    fn limit_errors() {
+        let log = "yesterday 09:00 [shop] call billing: x\n".repeat(MAX_ERRORS + 5);
+        let errors = parse(&log, &rules()).map(|_| ()).unwrap_err();
+        assert_eq!(MAX_ERRORS + 1, errors.len());
+        assert_eq!(MAX_ERRORS, errors[MAX_ERRORS - 1].line);
+        assert_eq!(ParseError::new(0, "5 more errors omitted"), errors[MAX_ERRORS]);
+    }
+
+    #[test]
+    fn invalid_rules() {
+        let set = RuleSet {
+            rules: vec![Rule { pattern: "(?P<from>a)(".to_string(), to: Some("b".to_string()), ..Default::default() }],
+            ..Default::default()
+        };
+        assert_eq!(0, Rules::new(&set).map(|_| ()).unwrap_err().rule);
+
+        let set = RuleSet {
+            rules: vec![Rule { pattern: "(?P<from>a)".to_string(), ..Default::default() }],
+            ..Default::default()
+        };
+        assert!(Rules::new(&set).map(|_| ()).unwrap_err().message.contains("`to`"));
+    }
+}
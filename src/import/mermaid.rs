@@ -0,0 +1,223 @@
+use super::ParseError;
+use crate::data::SessionInner;
+
+use chrono::Utc;
+use regex::Regex;
+use std::sync::OnceLock;
+
+fn participant_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(
+        r"^(?:create\s+)?(?:participant|actor)\s+(?P<name>.+?)(?:\s+as\s+(?P<label>.+))?$"
+    ).unwrap())
+}
+
+fn message_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(
+        r"^(?P<from>[^:]+?)\s*(?:<<-->>|<<->>|-->>|->>|--x|-x|--\)|-\)|-->|->)\s*[+-]?\s*(?P<to>[^:]+?)\s*(?::(?P<label>.*))?$"
+    ).unwrap())
+}
+
+/// Mermaid writes line breaks in texts as `<br>`.
+fn unescape(text: &str) -> String {
+    text.trim()
+        .replace("<br/>", "\n")
+        .replace("<br />", "\n")
+        .replace("<br>", "\n")
+}
+
+/// Statements opening a block that is closed by `end`.
+const BLOCKS: [&str; 8] = ["loop", "alt", "opt", "par", "critical", "break", "rect", "box"];
+
+/// Statements without effect on the recorded links.
+const IGNORED: [&str; 10] = [
+    "activate", "deactivate", "autonumber", "destroy", "title",
+    "link", "links", "properties", "details", "note",
+];
+
+/// Parses the source of a Mermaid `sequenceDiagram` into a new session.
+///
+/// Participants with aliases become labeled parties and every message
+/// becomes a link. Notes, activations and the structure of blocks like
+/// `loop` and `alt` can't be represented and are skipped, the messages
+/// inside of blocks are kept.
+pub fn parse(source: &str) -> Result<SessionInner, ParseError> {
+    let mut session = SessionInner::new(0);
+    let now = Utc::now();
+    let mut header = false;
+    let mut blocks: Vec<(usize, String)> = vec![];
+
+    for (index, line) in source.lines().enumerate() {
+        let number = index + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with("%%") {
+            continue;
+        }
+
+        if !header {
+            if line != "sequenceDiagram" {
+                return Err(ParseError::new(number, "Expected `sequenceDiagram`"));
+            }
+            header = true;
+            continue;
+        }
+
+        let keyword = line.split_whitespace().next().unwrap_or_default().to_ascii_lowercase();
+        let keyword = keyword.as_str();
+
+        if let Some(captures) = participant_regex().captures(line) {
+            let name = unescape(&captures["name"]);
+            let label = captures.name("label").map(|label| unescape(label.as_str()));
+            let label = label.filter(|label| *label != name);
+            session.add_labeled_party(&name, label.as_deref());
+        } else if BLOCKS.contains(&keyword) {
+            blocks.push((number, keyword.to_string()));
+        } else if keyword == "end" {
+            if blocks.pop().is_none() {
+                return Err(ParseError::new(number, "`end` without an open block"));
+            }
+        } else if let Some((article, parent)) = match keyword {
+            "else" => Some(("an", "alt")),
+            "and" => Some(("a", "par")),
+            "option" => Some(("a", "critical")),
+            _ => None,
+        } {
+            if blocks.last().map(|(_, block)| block.as_str()) != Some(parent) {
+                return Err(ParseError::new(number, &format!("`{keyword}` outside of {article} `{parent}` block")));
+            }
+        } else if IGNORED.contains(&keyword) {
+            continue;
+        } else if let Some(captures) = message_regex().captures(line) {
+            let label = captures.name("label")
+                .map(|label| unescape(label.as_str()))
+                .filter(|label| !label.is_empty());
+            session.add_link(now, &unescape(&captures["from"]), &unescape(&captures["to"]), label.as_deref());
+        } else {
+            return Err(ParseError::new(number, &format!("Unsupported statement `{line}`")));
+        }
+    }
+
+    if !header {
+        return Err(ParseError::new(source.lines().count().max(1), "Missing `sequenceDiagram`"));
+    }
+
+    if let Some((number, block)) = blocks.pop() {
+        return Err(ParseError::new(number, &format!("`{block}` block is never closed")));
+    }
+
+    Ok(session)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn links(session: &SessionInner) -> Vec<(&str, &str, Option<&str>)> {
+        session.links.iter()
+            .map(|link| (link.from.name.as_str(), link.to.name.as_str(), link.label.as_deref()))
+            .collect()
+    }
+
+    #[test]
+    fn participants_and_arrows() {
+        let mut session = parse("
+            %% A comment
+            sequenceDiagram
+                participant A as Alice
+                actor B
+                A->>B: solid
+                B-->>A: dotted
+                A->B: open
+                B-->A: dotted open
+                A-xB: cross
+                B--xA: dotted cross
+                A-)B: async
+                B--)A: dotted async
+                A->>+node-1: activate
+                node-1-->>-A: deactivate<br>twice
+                A->>B:
+            ").unwrap();
+
+        assert_eq!(3, session.parties.len());
+        let alice = session.add_party("A");
+        assert_eq!(Some("Alice"), alice.label.as_deref());
+        assert_eq!(None, session.add_party("B").label.as_deref());
+
+        assert_eq!(vec![
+            ("A", "B", Some("solid")),
+            ("B", "A", Some("dotted")),
+            ("A", "B", Some("open")),
+            ("B", "A", Some("dotted open")),
+            ("A", "B", Some("cross")),
+            ("B", "A", Some("dotted cross")),
+            ("A", "B", Some("async")),
+            ("B", "A", Some("dotted async")),
+            ("A", "node-1", Some("activate")),
+            ("node-1", "A", Some("deactivate\ntwice")),
+            ("A", "B", None),
+        ], links(&session));
+    }
+
+    #[test]
+    fn blocks_and_notes() {
+        let session = parse("sequenceDiagram
+            autonumber
+            loop Every minute
+                A->>B: ping
+                Note right of B: thinks
+                alt is alive
+                    B->>A: pong
+                else is dead
+                    activate A
+                    A->>A: wait
+                    deactivate A
+                end
+            end
+            ").unwrap();
+
+        assert_eq!(vec![
+            ("A", "B", Some("ping")),
+            ("B", "A", Some("pong")),
+            ("A", "A", Some("wait")),
+        ], links(&session));
+    }
+
+    #[test]
+    fn errors_with_line_numbers() {
+        assert_eq!(Err(ParseError::new(1, "Expected `sequenceDiagram`")), parse("graph TD").map(|_| ()));
+        assert_eq!(Err(ParseError::new(1, "Missing `sequenceDiagram`")), parse("").map(|_| ()));
+        assert_eq!(
+            Err(ParseError::new(2, "`loop` block is never closed")),
+            parse("sequenceDiagram\nloop forever\nA->>B: x").map(|_| ()));
+        assert_eq!(
+            Err(ParseError::new(3, "`end` without an open block")),
+            parse("sequenceDiagram\nA->>B: x\nend").map(|_| ()));
+        assert_eq!(
+            Err(ParseError::new(3, "`else` outside of an `alt` block")),
+            parse("sequenceDiagram\nloop\nelse\nend").map(|_| ()));
+        assert_eq!(
+            Err(ParseError::new(2, "Unsupported statement `A => B`")),
+            parse("sequenceDiagram\nA => B").map(|_| ()));
+    }
+
+    #[cfg(feature = "mermaid")]
+    #[test]
+    fn round_trip() {
+        use crate::render::mermaid::Document;
+
+        let mut original = SessionInner::new(0);
+        let now = Utc::now();
+        original.add_labeled_party("gw", Some("Gateway"));
+        original.add_link(now, "gw", "api", Some("Request"));
+        original.add_link(now, "api", "gw", None);
+
+        let source: String = Document::from(&original).into();
+        let mut session = parse(&source).unwrap();
+        assert_eq!(links(&original), links(&session));
+        assert_eq!(Some("Gateway"), session.add_party("gw").label.as_deref());
+
+        let rendered: String = Document::from(&session).into();
+        assert_eq!(source, rendered);
+    }
+}
@@ -0,0 +1,43 @@
+pub mod har;
+pub mod jaeger;
+pub mod logfile;
+pub mod mermaid;
+#[cfg(feature = "otlp")]
+pub mod otlp;
+pub mod plantuml;
+pub mod trace;
+pub mod zipkin;
+
+use rocket::serde::json::serde_json;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Error of an importer pointing to the offending line of the source.
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+pub struct ParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl ParseError {
+    pub fn new(line: usize, message: &str) -> Self {
+        Self {
+            line,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl From<serde_json::Error> for ParseError {
+    fn from(e: serde_json::Error) -> Self {
+        ParseError::new(e.line(), &e.to_string())
+    }
+}
@@ -0,0 +1,716 @@
+//! Receiver of traces exported by OpenTelemetry with OTLP/HTTP, as protobuf
+//! or JSON. Services become parties and their calls links.
+
+use super::trace::{self, Kind, Span};
+use crate::data::Sessions;
+use chrono::DateTime;
+use rocket::serde::json::serde_json;
+use serde::Deserialize;
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Service of spans without `service.name` resource attribute.
+pub const UNKNOWN_SERVICE: &str = "unknown_service";
+
+/// Span attributes naming the peer of a call, in order of preference.
+const PEER_ATTRIBUTES: [&str; 5] = ["peer.service", "db.system.name", "db.system", "server.address", "net.peer.name"];
+/// Span attributes shown on replies, in order of preference.
+const STATUS_ATTRIBUTES: [&str; 3] = ["http.response.status_code", "http.status_code", "rpc.grpc.status_code"];
+/// Span status code of failed operations.
+const STATUS_ERROR: u64 = 2;
+
+/// Why an export request can't be decoded.
+#[derive(Debug, PartialEq)]
+pub struct Error(pub String);
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Spans of a resource, like a service instance.
+#[derive(Debug, Default, PartialEq)]
+pub struct Resource {
+    pub attributes: HashMap<String, String>,
+    pub spans: Vec<Span>,
+}
+
+/// A span before the attributes of its resource are known.
+#[derive(Default)]
+struct RawSpan {
+    trace_id: String,
+    id: String,
+    parent_id: String,
+    name: String,
+    kind: u64,
+    start: u64,
+    end: u64,
+    attributes: HashMap<String, String>,
+    status_code: u64,
+}
+
+impl RawSpan {
+    fn into_span(self, service: &str) -> Span {
+        let attribute = |keys: &[&str]| keys.iter().find_map(|key| self.attributes.get(*key)).cloned();
+        Span {
+            kind: match self.kind {
+                2 => Kind::Server,
+                3 => Kind::Client,
+                4 => Kind::Producer,
+                5 => Kind::Consumer,
+                _ => Kind::Internal,
+            },
+            service: service.to_string(),
+            peer: attribute(&PEER_ATTRIBUTES),
+            status: attribute(&STATUS_ATTRIBUTES)
+                .or_else(|| (self.status_code == STATUS_ERROR).then(|| "error".to_string())),
+            start: DateTime::from_timestamp_nanos(self.start as i64),
+            end: DateTime::from_timestamp_nanos(self.end as i64),
+            parent_id: Some(self.parent_id).filter(|id| !id.is_empty()),
+            trace_id: self.trace_id,
+            id: self.id,
+            name: self.name,
+        }
+    }
+}
+
+fn resource(attributes: HashMap<String, String>, spans: Vec<RawSpan>) -> Resource {
+    let service = attributes.get("service.name").map(String::as_str).unwrap_or(UNKNOWN_SERVICE);
+    let spans = spans.into_iter().map(|span| span.into_span(service)).collect();
+    Resource { attributes, spans }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Value of a protobuf field, by wire type.
+enum Value<'a> {
+    Varint(u64),
+    Fixed64(u64),
+    Bytes(&'a [u8]),
+    Fixed32,
+}
+
+impl<'a> Value<'a> {
+    fn varint(&self) -> u64 {
+        match self {
+            Value::Varint(value) | Value::Fixed64(value) => *value,
+            _ => 0,
+        }
+    }
+
+    fn bytes(&self) -> &'a [u8] {
+        match self {
+            Value::Bytes(bytes) => bytes,
+            _ => &[],
+        }
+    }
+
+    fn string(&self) -> Result<String, Error> {
+        String::from_utf8(self.bytes().to_vec()).map_err(|_| Error("String isn't valid UTF-8".to_string()))
+    }
+}
+
+/// Reads the fields of a protobuf message, only the wire format is checked.
+struct Fields<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Fields<'a> {
+    fn varint(&mut self) -> Result<u64, Error> {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let (byte, rest) = self.data.split_first().ok_or_else(|| Error("Truncated varint".to_string()))?;
+            self.data = rest;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(Error("Varint is too long".to_string()))
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if self.data.len() < len {
+            return Err(Error("Truncated field".to_string()));
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    fn next_field(&mut self) -> Result<(u64, Value<'a>), Error> {
+        let key = self.varint()?;
+        let value = match key & 7 {
+            0 => Value::Varint(self.varint()?),
+            1 => Value::Fixed64(u64::from_le_bytes(self.take(8)?.try_into().unwrap())),
+            2 => {
+                let len = self.varint()? as usize;
+                Value::Bytes(self.take(len)?)
+            }
+            5 => {
+                self.take(4)?;
+                Value::Fixed32
+            }
+            wire_type => return Err(Error(format!("Unsupported wire type {wire_type}"))),
+        };
+        Ok((key >> 3, value))
+    }
+}
+
+impl<'a> Iterator for Fields<'a> {
+    type Item = Result<(u64, Value<'a>), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.data.is_empty() {
+            return None;
+        }
+        let field = self.next_field();
+        if field.is_err() {
+            self.data = &[];
+        }
+        Some(field)
+    }
+}
+
+fn fields(data: &[u8]) -> Fields<'_> {
+    Fields { data }
+}
+
+/// Adds a `KeyValue` with a scalar value, other values are skipped.
+fn decode_attribute(data: &[u8], attributes: &mut HashMap<String, String>) -> Result<(), Error> {
+    let mut key = None;
+    let mut value = None;
+    for field in fields(data) {
+        match field? {
+            (1, field) => key = Some(field.string()?),
+            (2, field) => {
+                for field in fields(field.bytes()) {
+                    value = match field? {
+                        (1, field) => Some(field.string()?),
+                        (2, field) => Some((field.varint() != 0).to_string()),
+                        (3, field) => Some((field.varint() as i64).to_string()),
+                        (4, field) => Some(f64::from_bits(field.varint()).to_string()),
+                        _ => continue,
+                    };
+                }
+            }
+            _ => {}
+        }
+    }
+    if let (Some(key), Some(value)) = (key, value) {
+        attributes.insert(key, value);
+    }
+    Ok(())
+}
+
+fn decode_span(data: &[u8]) -> Result<RawSpan, Error> {
+    let mut span = RawSpan::default();
+    for field in fields(data) {
+        match field? {
+            (1, field) => span.trace_id = hex(field.bytes()),
+            (2, field) => span.id = hex(field.bytes()),
+            (4, field) => span.parent_id = hex(field.bytes()),
+            (5, field) => span.name = field.string()?,
+            (6, field) => span.kind = field.varint(),
+            (7, field) => span.start = field.varint(),
+            (8, field) => span.end = field.varint(),
+            (9, field) => decode_attribute(field.bytes(), &mut span.attributes)?,
+            (15, field) => {
+                for field in fields(field.bytes()) {
+                    if let (3, field) = field? {
+                        span.status_code = field.varint();
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(span)
+}
+
+/// Decodes an `ExportTraceServiceRequest` in protobuf encoding.
+pub fn decode_protobuf(data: &[u8]) -> Result<Vec<Resource>, Error> {
+    let mut resources = vec![];
+    for field in fields(data) {
+        let (1, resource_spans) = field? else { continue };
+        let mut attributes = HashMap::new();
+        let mut spans = vec![];
+        for field in fields(resource_spans.bytes()) {
+            match field? {
+                (1, field) => {
+                    for field in fields(field.bytes()) {
+                        if let (1, field) = field? {
+                            decode_attribute(field.bytes(), &mut attributes)?;
+                        }
+                    }
+                }
+                (2, scope_spans) => {
+                    for field in fields(scope_spans.bytes()) {
+                        if let (2, field) = field? {
+                            spans.push(decode_span(field.bytes())?);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        resources.push(resource(attributes, spans));
+    }
+    Ok(resources)
+}
+
+/// 64 bit integers and enums may be given as numbers or strings in JSON.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum JsonNumber {
+    Number(serde_json::Number),
+    String(String),
+}
+
+impl JsonNumber {
+    fn to_u64(&self) -> u64 {
+        match self {
+            JsonNumber::Number(number) => number.as_u64().unwrap_or_default(),
+            JsonNumber::String(string) => string.parse().unwrap_or(match string.as_str() {
+                "SPAN_KIND_INTERNAL" => 1,
+                "SPAN_KIND_SERVER" => 2,
+                "SPAN_KIND_CLIENT" => 3,
+                "SPAN_KIND_PRODUCER" => 4,
+                "SPAN_KIND_CONSUMER" => 5,
+                "STATUS_CODE_OK" => 1,
+                "STATUS_CODE_ERROR" => 2,
+                _ => 0,
+            }),
+        }
+    }
+
+    fn text(&self) -> String {
+        match self {
+            JsonNumber::Number(number) => number.to_string(),
+            JsonNumber::String(string) => string.clone(),
+        }
+    }
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct JsonRequest {
+    resource_spans: Vec<JsonResourceSpans>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct JsonResourceSpans {
+    resource: JsonResource,
+    scope_spans: Vec<JsonScopeSpans>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct JsonResource {
+    attributes: Vec<JsonAttribute>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct JsonScopeSpans {
+    spans: Vec<JsonSpan>,
+}
+
+#[derive(Deserialize)]
+struct JsonAttribute {
+    key: String,
+    value: JsonValue,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonValue {
+    string_value: Option<String>,
+    bool_value: Option<bool>,
+    int_value: Option<JsonNumber>,
+    double_value: Option<f64>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonSpan {
+    trace_id: String,
+    span_id: String,
+    #[serde(default)]
+    parent_span_id: String,
+    #[serde(default)]
+    name: String,
+    kind: Option<JsonNumber>,
+    start_time_unix_nano: JsonNumber,
+    end_time_unix_nano: JsonNumber,
+    #[serde(default)]
+    attributes: Vec<JsonAttribute>,
+    status: Option<JsonStatus>,
+}
+
+#[derive(Deserialize)]
+struct JsonStatus {
+    code: Option<JsonNumber>,
+}
+
+fn json_attributes(attributes: Vec<JsonAttribute>) -> HashMap<String, String> {
+    attributes.into_iter()
+        .filter_map(|JsonAttribute { key, value }| {
+            let value = value.string_value
+                .or(value.bool_value.map(|value| value.to_string()))
+                .or(value.int_value.map(|value| value.text()))
+                .or(value.double_value.map(|value| value.to_string()))?;
+            Some((key, value))
+        })
+        .collect()
+}
+
+/// Decodes an `ExportTraceServiceRequest` in JSON encoding, with ids in hex.
+pub fn decode_json(json: &[u8]) -> Result<Vec<Resource>, Error> {
+    let request: JsonRequest = serde_json::from_slice(json).map_err(|e| Error(e.to_string()))?;
+    Ok(request.resource_spans.into_iter()
+        .map(|resource_spans| {
+            let spans = resource_spans.scope_spans.into_iter()
+                .flat_map(|scope_spans| scope_spans.spans)
+                .map(|span| RawSpan {
+                    trace_id: span.trace_id.to_lowercase(),
+                    id: span.span_id.to_lowercase(),
+                    parent_id: span.parent_span_id.to_lowercase(),
+                    name: span.name,
+                    kind: span.kind.map(|kind| kind.to_u64()).unwrap_or_default(),
+                    start: span.start_time_unix_nano.to_u64(),
+                    end: span.end_time_unix_nano.to_u64(),
+                    attributes: json_attributes(span.attributes),
+                    status_code: span.status.and_then(|status| status.code).map(|code| code.to_u64()).unwrap_or_default(),
+                })
+                .collect();
+            resource(json_attributes(resource_spans.resource.attributes), spans)
+        })
+        .collect())
+}
+
+/// Configuration of the receiver, the `otlp` table of the server config.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct Config {
+    /// Resource attribute grouping traces into sessions, like
+    /// `deployment.environment`, one session per trace if not set.
+    pub session_attribute: Option<String>,
+    /// Number of traces kept to pair spans arriving in later exports, and of
+    /// session ids kept by group.
+    pub max_traces: usize,
+    /// Seconds without new spans after which a trace is done and its calls
+    /// are added.
+    pub idle_timeout: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            session_attribute: None,
+            max_traces: 1000,
+            idle_timeout: 30,
+        }
+    }
+}
+
+/// Spans of a trace received so far.
+struct Trace {
+    /// Value of the session attribute or the trace id.
+    group: String,
+    spans: Vec<Span>,
+    /// When the last span arrived.
+    updated: Instant,
+}
+
+#[derive(Default)]
+struct State {
+    /// Session ids by group.
+    sessions: HashMap<String, u64>,
+    /// Groups in order of their first session, the oldest are dropped first.
+    groups: VecDeque<String>,
+    traces: HashMap<String, Trace>,
+    /// Trace ids in order of arrival, the oldest traces are dropped first.
+    arrival: VecDeque<String>,
+}
+
+/// Adds the calls of received spans to sessions once their trace is done, so
+/// the links of a trace are in order of their timestamps whatever order the
+/// spans arrive in.
+#[derive(Clone, Default)]
+pub struct Receiver {
+    config: Config,
+    state: Arc<Mutex<State>>,
+}
+
+impl Receiver {
+    pub fn new(config: Config) -> Self {
+        Receiver {
+            config,
+            state: Arc::default(),
+        }
+    }
+
+    /// Keeps the spans of `resources` and returns the number of links added
+    /// for traces dropped to make room for new ones.
+    pub fn receive(&self, sessions: &Sessions, resources: Vec<Resource>) -> usize {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        let mut received = vec![];
+        for resource in resources {
+            let group = self.config.session_attribute.as_ref().and_then(|key| resource.attributes.get(key));
+            for span in resource.spans {
+                let trace = state.traces.entry(span.trace_id.clone()).or_insert_with(|| {
+                    received.push(span.trace_id.clone());
+                    Trace {
+                        group: group.cloned().unwrap_or_else(|| span.trace_id.clone()),
+                        spans: vec![],
+                        updated: now,
+                    }
+                });
+                trace.updated = now;
+                trace.spans.push(span);
+            }
+        }
+        state.arrival.extend(received);
+
+        let mut done = vec![];
+        while state.arrival.len() > self.config.max_traces {
+            let trace_id = state.arrival.pop_front().unwrap();
+            done.extend(state.traces.remove(&trace_id));
+        }
+        self.add_calls(&mut state, sessions, done)
+    }
+
+    /// Adds the calls of traces without new spans for the idle timeout and
+    /// returns the number of added links.
+    pub fn flush(&self, sessions: &Sessions) -> usize {
+        self.done(sessions, Some(Duration::from_secs(self.config.idle_timeout)))
+    }
+
+    /// Adds the calls of all traces, like on shutdown, and returns the number
+    /// of added links.
+    pub fn finish(&self, sessions: &Sessions) -> usize {
+        self.done(sessions, None)
+    }
+
+    /// Removes the traces idle for `timeout`, or all without, and adds their
+    /// calls.
+    fn done(&self, sessions: &Sessions, timeout: Option<Duration>) -> usize {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        let mut done = vec![];
+        let State { traces, arrival, .. } = &mut *state;
+        arrival.retain(|trace_id| {
+            let Some(trace) = traces.get(trace_id) else { return false };
+            if timeout.is_some_and(|timeout| now.duration_since(trace.updated) < timeout) {
+                return true;
+            }
+            done.extend(traces.remove(trace_id));
+            false
+        });
+        self.add_calls(&mut state, sessions, done)
+    }
+
+    /// Adds the calls of done traces to the sessions of their groups and
+    /// returns the number of added links.
+    fn add_calls(&self, state: &mut State, sessions: &Sessions, traces: Vec<Trace>) -> usize {
+        let mut added = 0;
+        for trace in traces {
+            let calls = trace::calls(&trace.spans, true);
+            if calls.is_empty() {
+                continue;
+            }
+            let session = match state.sessions.get(&trace.group).and_then(|id| sessions.get(*id)) {
+                Some(session) => session,
+                None => {
+                    let session = sessions.new_session();
+                    let id = session.read().unwrap().id;
+                    if state.sessions.insert(trace.group.clone(), id).is_none() {
+                        state.groups.push_back(trace.group);
+                    }
+                    while state.groups.len() > self.config.max_traces {
+                        let group = state.groups.pop_front().unwrap();
+                        state.sessions.remove(&group);
+                    }
+                    session
+                }
+            };
+            let mut session = session.write().unwrap();
+            let links = session.links.len();
+            trace::add_calls(&mut session, &calls);
+            added += session.links.len() - links;
+        }
+        added
+    }
+
+    /// Id of the session of a trace or session attribute value.
+    pub fn session_id(&self, group: &str) -> Option<u64> {
+        self.state.lock().unwrap().sessions.get(group).copied()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Protobuf field with a length delimited value.
+    fn message(field: u8, bytes: &[u8]) -> Vec<u8> {
+        let mut data = vec![field << 3 | 2];
+        let mut len = bytes.len();
+        while len >= 0x80 {
+            data.push(len as u8 | 0x80);
+            len >>= 7;
+        }
+        data.push(len as u8);
+        data.extend_from_slice(bytes);
+        data
+    }
+
+    fn attribute(key: &str, value: &str) -> Vec<u8> {
+        [message(1, key.as_bytes()), message(2, &message(1, value.as_bytes()))].concat()
+    }
+
+    fn span(id: u8, parent: Option<u8>, kind: u8, name: &str, start: u64, status: &str) -> Vec<u8> {
+        let mut span = [message(1, &[0xab; 16]), message(2, &[id; 8]), message(5, name.as_bytes())].concat();
+        if let Some(parent) = parent {
+            span.extend(message(4, &[parent; 8]));
+        }
+        span.extend([6 << 3, kind]);
+        span.push(7 << 3 | 1);
+        span.extend(start.to_le_bytes());
+        span.push(8 << 3 | 1);
+        span.extend((start + 1_000_000).to_le_bytes());
+        if !status.is_empty() {
+            span.extend(message(9, &attribute("http.response.status_code", status)));
+        }
+        span
+    }
+
+    fn resource_spans(service: &str, spans: &[Vec<u8>]) -> Vec<u8> {
+        let resource = message(1, &message(1, &attribute("service.name", service)));
+        let scope_spans = spans.iter().flat_map(|span| message(2, span)).collect::<Vec<_>>();
+        message(1, &[resource, message(2, &scope_spans)].concat())
+    }
+
+    #[test]
+    fn decode_protobuf_request() {
+        let request = [
+            resource_spans("web", &[span(1, None, 2, "GET /", 1_000, ""), span(2, Some(1), 3, "GET", 2_000, "")]),
+            resource_spans("api", &[span(3, Some(2), 2, "GET /items", 3_000, "200")]),
+        ].concat();
+
+        let resources = decode_protobuf(&request).unwrap();
+        assert_eq!(2, resources.len());
+        assert_eq!(Some(&"web".to_string()), resources[0].attributes.get("service.name"));
+        let server = &resources[1].spans[0];
+        assert_eq!(("api", Kind::Server, "GET /items"), (server.service.as_str(), server.kind, server.name.as_str()));
+        assert_eq!("ab".repeat(16), server.trace_id);
+        assert_eq!(Some("02".repeat(8)), server.parent_id);
+        assert_eq!(Some("200".to_string()), server.status);
+        assert_eq!(3_000, server.start.timestamp_nanos_opt().unwrap());
+        assert_eq!(None, resources[0].spans[0].parent_id);
+
+        assert!(decode_protobuf(&request[..request.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn receive_in_several_exports() {
+        let json = |service: &str, span_id: &str, parent: &str, kind: u8| format!(r#"{{"resourceSpans": [{{
+            "resource": {{"attributes": [
+                {{"key": "service.name", "value": {{"stringValue": "{service}"}}}},
+                {{"key": "deployment.environment", "value": {{"stringValue": "staging"}}}}
+            ]}},
+            "scopeSpans": [{{"spans": [{{
+                "traceId": "5B8EFFF798038103D269B633813FC60C", "spanId": "{span_id}", "parentSpanId": "{parent}",
+                "name": "{service} {kind}", "kind": {kind},
+                "startTimeUnixNano": "1544712660000000000", "endTimeUnixNano": 1544712661000000000,
+                "status": {{"code": 2}}
+            }}]}}]
+        }}]}}"#);
+
+        let sessions = Sessions::new();
+        let receiver = Receiver::new(Config { session_attribute: Some("deployment.environment".to_string()), ..Default::default() });
+        let api = decode_json(json("api", "0000000000000003", "0000000000000002", 2).as_bytes()).unwrap();
+        assert_eq!(0, receiver.receive(&sessions, api));
+        let web = decode_json(json("web", "0000000000000002", "", 3).as_bytes()).unwrap();
+        assert_eq!(0, receiver.receive(&sessions, web));
+        assert_eq!(2, receiver.finish(&sessions));
+
+        let session = sessions.get(receiver.session_id("staging").unwrap()).unwrap();
+        let session = session.read().unwrap();
+        let links = session.links.iter()
+            .map(|link| (link.from.name.as_str(), link.to.name.as_str(), link.label.as_deref()))
+            .collect::<Vec<_>>();
+        assert_eq!(vec![("web", "api", Some("api 2")), ("api", "web", Some("error"))], links);
+        assert_eq!(1_544_712_661, session.links[1].timestamp.timestamp());
+    }
+
+    #[test]
+    fn wait_for_server_span_of_peer() {
+        use crate::import::trace::test::span;
+
+        let resource = |span: Span| vec![Resource { attributes: HashMap::new(), spans: vec![span] }];
+        let mut client = span("2", Some("1"), Kind::Client, "web", "GET", 10, 70);
+        client.peer = Some("api.internal".to_string());
+        let mut db = span("4", Some("3"), Kind::Client, "api", "SELECT", 30, 40);
+        db.peer = Some("postgres".to_string());
+
+        let sessions = Sessions::new();
+        let receiver = Receiver::new(Config { idle_timeout: 3600, ..Default::default() });
+        assert_eq!(0, receiver.receive(&sessions, resource(span("1", None, Kind::Server, "web", "/checkout", 0, 80))));
+        assert_eq!(0, receiver.receive(&sessions, resource(client)));
+        assert_eq!(0, receiver.receive(&sessions, resource(db)));
+        assert_eq!(0, receiver.receive(&sessions, resource(span("3", Some("2"), Kind::Server, "api", "GET /items", 20, 60))));
+        assert_eq!(0, receiver.flush(&sessions));
+
+        let receiver = Receiver { config: Config { idle_timeout: 0, ..receiver.config.clone() }, ..receiver };
+        assert_eq!(6, receiver.flush(&sessions));
+        assert!(receiver.state.lock().unwrap().traces.is_empty());
+
+        let session = sessions.get(receiver.session_id("t1").unwrap()).unwrap();
+        let session = session.read().unwrap();
+        let links = session.links.iter()
+            .map(|link| (link.from.name.as_str(), link.to.name.as_str(), link.label.as_deref()))
+            .collect::<Vec<_>>();
+        assert_eq!(vec![
+            (trace::EXTERNAL_PARTY, "web", Some("/checkout")),
+            ("web", "api", Some("GET /items")),
+            ("api", "postgres", Some("SELECT")),
+            ("postgres", "api", None),
+            ("api", "web", None),
+            ("web", trace::EXTERNAL_PARTY, None),
+        ], links);
+        assert!(session.party("api.internal").is_none());
+    }
+
+    #[test]
+    fn bound_sessions_by_group() {
+        use crate::import::trace::test::span;
+
+        let sessions = Sessions::new();
+        let receiver = Receiver::new(Config { max_traces: 2, ..Default::default() });
+        let added = ["t1", "t2", "t3"].map(|trace_id| {
+            let mut span = span("1", None, Kind::Server, "web", "/", 0, 10);
+            span.trace_id = trace_id.to_string();
+            receiver.receive(&sessions, vec![Resource { attributes: HashMap::new(), spans: vec![span] }])
+        });
+        assert_eq!([0, 0, 2], added);
+        assert!(receiver.session_id("t1").is_some());
+        assert_eq!(4, receiver.finish(&sessions));
+        assert!(receiver.session_id("t1").is_none());
+        let state = receiver.state.lock().unwrap();
+        assert_eq!(vec!["t2", "t3"], state.groups.iter().map(String::as_str).collect::<Vec<_>>());
+        assert_eq!(2, state.sessions.len());
+    }
+}
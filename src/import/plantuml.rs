@@ -0,0 +1,285 @@
+use super::ParseError;
+use crate::data::SessionInner;
+
+use chrono::Utc;
+use regex::Regex;
+use std::sync::OnceLock;
+
+const NAME: &str = r#""[^"]+"|[\w.@]+"#;
+
+fn participant_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(&format!(
+        r"^(?:create\s+)?(?:participant|actor|boundary|control|entity|database|collections|queue)\s+(?P<first>{NAME})(?:\s+as\s+(?P<second>{NAME}))?(?:\s+order\s+-?\d+)?(?:\s+<<.*>>)?(?:\s+#\S+)?$"
+    )).unwrap())
+}
+
+fn message_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(&format!(
+        r"^(?P<from>{NAME})\s*(?P<arrow><<?-(?:\[[^\]]*\])?-?|-(?:\[[^\]]*\])?-?>>?)\s*(?P<to>{NAME})\s*(?:\+\+|--|\*\*|!!)?\s*(?::(?P<label>.*))?$"
+    )).unwrap())
+}
+
+fn unquote(name: &str) -> &str {
+    name.strip_prefix('"')
+        .and_then(|name| name.strip_suffix('"'))
+        .unwrap_or(name)
+}
+
+/// PlantUML writes line breaks in texts as `\n`.
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = text.trim().chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some('n')) => { unescaped.push('\n'); chars.next(); },
+            ('\\', Some('\\')) => { unescaped.push('\\'); chars.next(); },
+            _ => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+/// Statements opening a group that is closed by `end`.
+const GROUPS: [&str; 8] = ["group", "alt", "opt", "loop", "par", "break", "critical", "box"];
+
+/// Statements without effect on the recorded links.
+const IGNORED: [&str; 13] = [
+    "activate", "deactivate", "destroy", "autonumber", "title", "skinparam", "hide",
+    "show", "header", "footer", "newpage", "autoactivate", "mainframe",
+];
+
+/// Parses the source of a PlantUML sequence diagram into a new session.
+///
+/// Participant declarations with display names become labeled parties and
+/// every arrow becomes a link. Notes, dividers and the structure of groups
+/// are skipped, the messages inside of groups are kept. All constructs which
+/// can't be imported are reported with their line numbers.
+pub fn parse(source: &str) -> Result<SessionInner, Vec<ParseError>> {
+    let mut session = SessionInner::new(0);
+    let now = Utc::now();
+    let mut errors = vec![];
+    let mut groups: Vec<(usize, String)> = vec![];
+    // Closing line of a skipped multi-line construct like a note
+    let mut skip_until: Option<(usize, &str)> = None;
+    let mut in_comment = false;
+    let mut started = false;
+
+    for (index, line) in source.lines().enumerate() {
+        let number = index + 1;
+        let line = line.trim();
+
+        if in_comment {
+            in_comment = !line.ends_with("'/");
+            continue;
+        }
+        if line.starts_with("/'") {
+            in_comment = !line.ends_with("'/") || line.len() < 4;
+            continue;
+        }
+        if line.is_empty() || line.starts_with('\'') {
+            continue;
+        }
+
+        let lowercase = line.to_ascii_lowercase();
+        if let Some((_, end)) = skip_until {
+            if lowercase.split_whitespace().collect::<String>() == end {
+                skip_until = None;
+            }
+            continue;
+        }
+
+        let keyword = lowercase.split_whitespace().next().unwrap_or_default();
+
+        if keyword.starts_with("@startuml") {
+            started = true;
+            continue;
+        }
+        if keyword.starts_with("@enduml") {
+            break;
+        }
+
+        if let Some(captures) = participant_regex().captures(line) {
+            let first = &captures["first"];
+            // The unquoted side of `as` is the name used in arrows
+            let (name, label) = match captures.name("second").map(|second| second.as_str()) {
+                Some(second) if second.starts_with('"') => (unquote(first), Some(unquote(second))),
+                Some(second) => (second, Some(unquote(first))),
+                None => (unquote(first), None),
+            };
+            let label = label.map(unescape).filter(|label| label != name);
+            session.add_labeled_party(name, label.as_deref());
+        } else if let Some(captures) = message_regex().captures(line) {
+            let (from, to) = (unquote(&captures["from"]), unquote(&captures["to"]));
+            let (from, to) = if captures["arrow"].starts_with('<') { (to, from) } else { (from, to) };
+            let label = captures.name("label")
+                .map(|label| unescape(label.as_str()))
+                .filter(|label| !label.is_empty());
+            session.add_link(now, from, to, label.as_deref());
+        } else if GROUPS.contains(&keyword) {
+            groups.push((number, keyword.to_string()));
+        } else if keyword == "end" {
+            if groups.pop().is_none() {
+                errors.push(ParseError::new(number, "`end` without an open group"));
+            }
+        } else if keyword == "else" {
+            if groups.is_empty() {
+                errors.push(ParseError::new(number, "`else` outside of a group"));
+            }
+        } else if ["note", "rnote", "hnote"].contains(&keyword) {
+            if !line.contains(':') {
+                skip_until = Some((number, "endnote"));
+            }
+        } else if keyword == "legend" {
+            skip_until = Some((number, "endlegend"));
+        } else if IGNORED.contains(&keyword)
+            || line.starts_with("==")
+            || line.starts_with("...")
+            || line.starts_with("||") {
+            continue;
+        } else if keyword == "ref" {
+            errors.push(ParseError::new(number, "References are not supported"));
+            if !line.contains(':') {
+                skip_until = Some((number, "endref"));
+            }
+        } else if keyword == "return" {
+            errors.push(ParseError::new(number, "`return` is not supported, use an explicit arrow"));
+        } else if line.starts_with('!') {
+            errors.push(ParseError::new(number, "Preprocessor directives are not supported"));
+        } else if line.starts_with('[') || line.contains("->]") || line.contains("<-]") {
+            errors.push(ParseError::new(number, "Incoming and outgoing messages are not supported"));
+        } else {
+            errors.push(ParseError::new(number, &format!("Unsupported statement `{line}`")));
+        }
+    }
+
+    if let Some((number, _)) = skip_until {
+        errors.push(ParseError::new(number, "Block is never closed"));
+    }
+    for (number, group) in groups {
+        errors.push(ParseError::new(number, &format!("`{group}` group is never closed")));
+    }
+    if !started && session.links.is_empty() && errors.is_empty() {
+        errors.push(ParseError::new(source.lines().count().max(1), "Missing `@startuml`"));
+    }
+
+    if errors.is_empty() {
+        Ok(session)
+    } else {
+        errors.sort_by_key(|error| error.line);
+        Err(errors)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn links(session: &SessionInner) -> Vec<(&str, &str, Option<&str>)> {
+        session.links.iter()
+            .map(|link| (link.from.name.as_str(), link.to.name.as_str(), link.label.as_deref()))
+            .collect()
+    }
+
+    #[test]
+    fn participants_and_arrows() {
+        let mut session = parse(r#"
+            @startuml
+            ' A comment
+            participant "Web Client" as web
+            actor Bob as b
+            database db #99FF99
+            /' A block
+               comment '/
+            web -> b : request
+            b --> web : response\nwith break
+            web ->> db
+            db <- b : reversed
+            db <-- b : dotted reversed
+            b -[#red]> b ++ : colored
+            "Other Client" -> b
+            @enduml
+            "#).unwrap();
+
+        assert_eq!(Some("Web Client"), session.add_party("web").label.as_deref());
+        assert_eq!(Some("Bob"), session.add_party("b").label.as_deref());
+        assert_eq!(None, session.add_party("db").label.as_deref());
+        assert_eq!(vec![
+            ("web", "b", Some("request")),
+            ("b", "web", Some("response\nwith break")),
+            ("web", "db", None),
+            ("b", "db", Some("reversed")),
+            ("b", "db", Some("dotted reversed")),
+            ("b", "b", Some("colored")),
+            ("Other Client", "b", None),
+        ], links(&session));
+    }
+
+    #[test]
+    fn notes_and_groups() {
+        let session = parse("@startuml
+            autonumber
+            == Start ==
+            group Retry
+                a -> b : ping
+                note left of a : single line
+                alt ok
+                    b -> a : pong
+                    note over a, b
+                      multi line
+                    end note
+                else failed
+                    ...
+                    b -> a : error
+                end
+            end
+            |||
+            @enduml").unwrap();
+
+        assert_eq!(vec![
+            ("a", "b", Some("ping")),
+            ("b", "a", Some("pong")),
+            ("b", "a", Some("error")),
+        ], links(&session));
+    }
+
+    #[test]
+    fn report_unsupported_constructs() {
+        let errors = parse("@startuml
+            a -> b : ok
+            !include common.puml
+            [-> a : incoming
+            return done
+            ref over a, b : init
+            loop
+            a => b
+            @enduml").err().unwrap();
+
+        assert_eq!(vec![
+            ParseError::new(3, "Preprocessor directives are not supported"),
+            ParseError::new(4, "Incoming and outgoing messages are not supported"),
+            ParseError::new(5, "`return` is not supported, use an explicit arrow"),
+            ParseError::new(6, "References are not supported"),
+            ParseError::new(7, "`loop` group is never closed"),
+            ParseError::new(8, "Unsupported statement `a => b`"),
+        ], errors);
+    }
+
+    #[cfg(feature = "plantuml")]
+    #[test]
+    fn round_trip() {
+        use crate::render::plantuml::Document;
+
+        let mut original = SessionInner::new(0);
+        let now = Utc::now();
+        original.add_link(now, "web client", "api", Some("GET\nmulti line \\ label"));
+        original.add_link(now, "api", "web client", None);
+
+        let source: String = Document::from(&original).into();
+        let session = parse(&source).unwrap();
+        let rendered: String = Document::from(&session).into();
+        assert_eq!(source, rendered);
+        assert_eq!(Some("GET\nmulti line \\ label"), session.links[0].label.as_deref());
+    }
+}
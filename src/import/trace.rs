@@ -0,0 +1,224 @@
+use crate::data::SessionInner;
+use chrono::{DateTime, Utc};
+use std::collections::{HashMap, HashSet};
+
+/// Party calling services from outside of a trace.
+pub const EXTERNAL_PARTY: &str = "client";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Kind {
+    Internal,
+    Client,
+    Server,
+    Producer,
+    Consumer,
+}
+
+impl Kind {
+    /// Span of the side sending a call or message.
+    fn is_caller(&self) -> bool {
+        matches!(self, Kind::Client | Kind::Producer)
+    }
+
+    /// Span of the side receiving a call or message.
+    fn is_callee(&self) -> bool {
+        matches!(self, Kind::Server | Kind::Consumer)
+    }
+}
+
+/// A span of a distributed trace, independent of the trace format.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Span {
+    pub trace_id: String,
+    pub id: String,
+    pub parent_id: Option<String>,
+    pub kind: Kind,
+    pub service: String,
+    /// Service on the other side of a call, when it's known without a span
+    /// of its own, like a database.
+    pub peer: Option<String>,
+    pub name: String,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    /// Shown on the reply, like a HTTP status code.
+    pub status: Option<String>,
+}
+
+/// Reply to a [`Call`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Reply {
+    pub timestamp: DateTime<Utc>,
+    pub status: Option<String>,
+}
+
+/// A call between two services, without reply for one-way messages.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Call {
+    pub from: String,
+    pub to: String,
+    pub label: String,
+    pub start: DateTime<Utc>,
+    pub reply: Option<Reply>,
+}
+
+/// Finds the calls between the services of `spans`, ordered by their start.
+///
+/// A server span is called by its client span, which is either its parent
+/// or, with shared span ids like in Zipkin, the span with the same id. Calls
+/// depending on spans which may still arrive are only added when the traces
+/// are `complete`. These are server spans with an unknown parent and client
+/// spans without server span, which call their peer.
+pub fn calls(spans: &[Span], complete: bool) -> Vec<Call> {
+    let mut by_id: HashMap<(&str, &str), Vec<&Span>> = HashMap::new();
+    for span in spans {
+        by_id.entry((&span.trace_id, &span.id)).or_default().push(span);
+    }
+    let find = |trace_id: &str, id: &str, caller: bool| by_id.get(&(trace_id, id))
+        .and_then(|spans| spans.iter().find(|span| !caller || span.kind.is_caller()))
+        .copied();
+
+    let mut calls = vec![];
+    let mut answered = HashSet::new();
+    for span in spans.iter().filter(|span| span.kind.is_callee()) {
+        let trace_id = span.trace_id.as_str();
+        let caller = find(trace_id, &span.id, true)
+            .or_else(|| span.parent_id.as_deref().and_then(|parent| find(trace_id, parent, true)));
+        let parent = span.parent_id.as_deref().and_then(|parent| find(trace_id, parent, false));
+
+        let (from, start, reply) = match (caller, parent) {
+            (Some(caller), _) => {
+                answered.insert((trace_id, caller.id.as_str()));
+                (caller.service.clone(), caller.start, (caller.end, caller.status.clone()))
+            }
+            (None, Some(parent)) => (parent.service.clone(), span.start, (span.end, None)),
+            (None, None) if span.parent_id.is_some() && !complete => continue,
+            (None, None) => (
+                span.peer.clone().unwrap_or_else(|| EXTERNAL_PARTY.to_string()),
+                span.start,
+                (span.end, None),
+            ),
+        };
+        calls.push(Call {
+            from,
+            to: span.service.clone(),
+            label: span.name.clone(),
+            start,
+            reply: (span.kind == Kind::Server).then(|| Reply {
+                timestamp: reply.0,
+                status: span.status.clone().or(reply.1),
+            }),
+        });
+    }
+
+    if complete {
+        for span in spans.iter().filter(|span| span.kind.is_caller()) {
+            if answered.contains(&(span.trace_id.as_str(), span.id.as_str())) {
+                continue;
+            }
+            if let Some(peer) = &span.peer {
+                calls.push(Call {
+                    from: span.service.clone(),
+                    to: peer.clone(),
+                    label: span.name.clone(),
+                    start: span.start,
+                    reply: (span.kind == Kind::Client).then(|| Reply {
+                        timestamp: span.end,
+                        status: span.status.clone(),
+                    }),
+                });
+            }
+        }
+    }
+
+    calls.sort_by_key(|call| call.start);
+    calls
+}
+
+/// Adds the calls and their replies in order of their timestamps.
+pub fn add_calls(session: &mut SessionInner, calls: &[Call]) {
+    let mut links = vec![];
+    for call in calls {
+        links.push((call.start, &call.from, &call.to, Some(call.label.as_str())));
+        if let Some(reply) = &call.reply {
+            links.push((reply.timestamp, &call.to, &call.from, reply.status.as_deref()));
+        }
+    }
+    links.sort_by_key(|(timestamp, ..)| *timestamp);
+    for (timestamp, from, to, label) in links {
+        session.add_link(timestamp, from, to, label);
+    }
+}
+
+/// A session with the calls between the services of complete traces.
+pub fn session(spans: &[Span]) -> SessionInner {
+    let mut session = SessionInner::new(0);
+    add_calls(&mut session, &calls(spans, true));
+    session
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+    use chrono::TimeZone;
+
+    pub(crate) fn span(id: &str, parent_id: Option<&str>, kind: Kind, service: &str, name: &str, start: i64, end: i64) -> Span {
+        Span {
+            trace_id: "t1".to_string(),
+            id: id.to_string(),
+            parent_id: parent_id.map(str::to_string),
+            kind,
+            service: service.to_string(),
+            peer: None,
+            name: name.to_string(),
+            start: Utc.timestamp_millis_opt(start).unwrap(),
+            end: Utc.timestamp_millis_opt(end).unwrap(),
+            status: None,
+        }
+    }
+
+    fn links(session: &SessionInner) -> Vec<(&str, &str, Option<&str>)> {
+        session.links.iter()
+            .map(|link| (link.from.name.as_str(), link.to.name.as_str(), link.label.as_deref()))
+            .collect()
+    }
+
+    #[test]
+    fn pair_client_and_server_spans() {
+        let mut db = span("5", Some("3"), Kind::Client, "api", "SELECT", 30, 40);
+        db.peer = Some("postgres".to_string());
+        let mut server = span("3", Some("2"), Kind::Server, "api", "GET /items", 20, 60);
+        server.status = Some("200".to_string());
+        let spans = vec![
+            db,
+            server,
+            span("2", Some("1"), Kind::Client, "web", "GET", 10, 70),
+            span("4", Some("3"), Kind::Internal, "api", "render", 45, 50),
+            span("1", None, Kind::Server, "web", "/checkout", 0, 80),
+        ];
+
+        assert_eq!(vec![
+            (EXTERNAL_PARTY, "web", Some("/checkout")),
+            ("web", "api", Some("GET /items")),
+            ("api", "postgres", Some("SELECT")),
+            ("postgres", "api", None),
+            ("api", "web", Some("200")),
+            ("web", EXTERNAL_PARTY, None),
+        ], links(&session(&spans)));
+    }
+
+    #[test]
+    fn wait_for_parents() {
+        let spans = vec![span("3", Some("2"), Kind::Server, "api", "GET /items", 20, 60)];
+        assert!(calls(&spans, false).is_empty());
+        assert_eq!(EXTERNAL_PARTY, calls(&spans, true)[0].from);
+
+        let mut shared = vec![
+            span("2", Some("1"), Kind::Client, "web", "GET", 10, 70),
+            span("2", Some("1"), Kind::Server, "api", "GET /items", 20, 60),
+        ];
+        shared[0].peer = Some("10.0.0.2".to_string());
+        let calls = calls(&shared, true);
+        assert_eq!(1, calls.len());
+        assert_eq!(("web", "api"), (calls[0].from.as_str(), calls[0].to.as_str()));
+    }
+}
@@ -0,0 +1,160 @@
+use super::{trace::{self, Kind, Span}, ParseError};
+use crate::data::SessionInner;
+
+use chrono::DateTime;
+use rocket::serde::json::serde_json;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// Service of spans without `localEndpoint`.
+pub const UNKNOWN_SERVICE: &str = "unknown";
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ZipkinSpan {
+    trace_id: String,
+    id: String,
+    parent_id: Option<String>,
+    #[serde(default)]
+    name: String,
+    kind: Option<String>,
+    /// Microseconds since the epoch, missing for incomplete spans.
+    timestamp: Option<i64>,
+    /// Microseconds.
+    #[serde(default)]
+    duration: i64,
+    local_endpoint: Option<Endpoint>,
+    remote_endpoint: Option<Endpoint>,
+    #[serde(default)]
+    tags: HashMap<String, String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Endpoint {
+    service_name: Option<String>,
+    ipv4: Option<String>,
+    ipv6: Option<String>,
+    port: Option<u16>,
+}
+
+impl Endpoint {
+    /// The service name, or the address of endpoints without name.
+    fn name(&self) -> Option<String> {
+        let address = self.ipv4.as_ref().or(self.ipv6.as_ref());
+        match (&self.service_name, address, self.port) {
+            (Some(name), _, _) if !name.is_empty() => Some(name.clone()),
+            (_, Some(address), Some(port)) => Some(format!("{address}:{port}")),
+            (_, Some(address), None) => Some(address.clone()),
+            _ => None,
+        }
+    }
+}
+
+impl ZipkinSpan {
+    fn into_span(self) -> Option<Span> {
+        let start = DateTime::from_timestamp_micros(self.timestamp?)?;
+        Some(Span {
+            kind: match self.kind.as_deref() {
+                Some("CLIENT") => Kind::Client,
+                Some("SERVER") => Kind::Server,
+                Some("PRODUCER") => Kind::Producer,
+                Some("CONSUMER") => Kind::Consumer,
+                _ => Kind::Internal,
+            },
+            service: self.local_endpoint.as_ref()
+                .and_then(Endpoint::name)
+                .unwrap_or_else(|| UNKNOWN_SERVICE.to_string()),
+            peer: self.remote_endpoint.as_ref().and_then(Endpoint::name),
+            status: self.tags.get("http.status_code").cloned()
+                .or_else(|| self.tags.contains_key("error").then(|| "error".to_string())),
+            end: start.checked_add_signed(chrono::Duration::microseconds(self.duration))?,
+            start,
+            trace_id: self.trace_id.to_lowercase(),
+            id: self.id.to_lowercase(),
+            parent_id: self.parent_id.map(|id| id.to_lowercase()),
+            name: self.name,
+        })
+    }
+}
+
+/// Parses Zipkin v2 JSON spans into a new session. Services named by the
+/// local and remote endpoints become parties, calls between them links.
+/// Spans without timestamp or with an out of range duration are skipped.
+pub fn parse(source: &str) -> Result<SessionInner, ParseError> {
+    // A list of spans as sent to `/api/v2/spans`, or a list of traces as
+    // returned by `/api/v2/traces`
+    let traces = source.trim_start().strip_prefix('[').is_some_and(|rest| rest.trim_start().starts_with('['));
+    let spans: Vec<ZipkinSpan> = match traces {
+        true => serde_json::from_str::<Vec<Vec<ZipkinSpan>>>(source)?.into_iter().flatten().collect(),
+        false => serde_json::from_str(source)?,
+    };
+    let spans = spans.into_iter().filter_map(ZipkinSpan::into_span).collect::<Vec<_>>();
+    Ok(trace::session(&spans))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn shared_spans() {
+        let source = r#"[
+          {
+            "traceId": "5af7183fb1d4cf5f", "id": "352bff9a74ca9ad2", "parentId": "6b221d5bc9e6496c",
+            "kind": "SERVER", "name": "get /api", "timestamp": 1556604172355737, "duration": 1431,
+            "localEndpoint": {"serviceName": "backend", "ipv4": "192.168.99.1", "port": 3306},
+            "remoteEndpoint": {"ipv4": "172.19.0.2", "port": 58648},
+            "tags": {"http.method": "GET", "http.path": "/api"},
+            "shared": true
+          },
+          {
+            "traceId": "5af7183fb1d4cf5f", "id": "352bff9a74ca9ad2", "parentId": "6b221d5bc9e6496c",
+            "kind": "CLIENT", "name": "get", "timestamp": 1556604172355000, "duration": 2500,
+            "localEndpoint": {"serviceName": "frontend", "ipv4": "172.19.0.2"},
+            "remoteEndpoint": {"serviceName": "backend"},
+            "tags": {"http.status_code": "200"}
+          },
+          {
+            "traceId": "5af7183fb1d4cf5f", "id": "6b221d5bc9e6496c",
+            "kind": "SERVER", "name": "get /", "timestamp": 1556604172354000, "duration": 5000,
+            "localEndpoint": {"serviceName": "frontend"},
+            "remoteEndpoint": {"ipv6": "::1"},
+            "tags": {"error": "timeout"}
+          },
+          {"traceId": "5af7183fb1d4cf5f", "id": "7c1", "kind": "CLIENT", "name": "incomplete"}
+        ]"#;
+
+        let session = parse(source).unwrap();
+        let links = session.links.iter()
+            .map(|link| (link.from.name.as_str(), link.to.name.as_str(), link.label.as_deref()))
+            .collect::<Vec<_>>();
+        assert_eq!(vec![
+            ("::1", "frontend", Some("get /")),
+            ("frontend", "backend", Some("get /api")),
+            ("backend", "frontend", Some("200")),
+            ("frontend", "::1", Some("error")),
+        ], links);
+        assert_eq!(1_556_604_172_357_500, session.links[2].timestamp.timestamp_micros());
+    }
+
+    #[test]
+    fn list_of_traces() {
+        let source = r#"[[{"traceId": "a", "id": "1", "kind": "SERVER", "name": "x", "timestamp": 1}], []]"#;
+        assert_eq!(2, parse(source).unwrap().links.len());
+
+        let error = parse("[\n{\"id\": 1}]").map(|_| ()).unwrap_err();
+        assert_eq!(2, error.line);
+    }
+
+    #[test]
+    fn skip_out_of_range_duration() {
+        let source = r#"[
+          {"traceId": "a", "id": "1", "kind": "SERVER", "name": "x", "timestamp": 1, "duration": 9223372036854775807},
+          {"traceId": "a", "id": "2", "kind": "SERVER", "name": "y", "timestamp": 1, "duration": 1}
+        ]"#;
+        let session = parse(source).unwrap();
+        let labels = session.links.iter().map(|link| link.label.as_deref()).collect::<Vec<_>>();
+        assert_eq!(vec![Some("y"), None], labels);
+    }
+}
@@ -8,10 +8,17 @@ extern crate rocket_include_tera;
 
 #[cfg(feature = "api")]
 pub mod api;
+pub mod archive;
 #[cfg(feature = "client")]
 pub mod client;
 pub mod data;
+pub mod diff;
+#[cfg(feature = "import")]
+pub mod import;
+pub mod recorder;
 #[cfg(feature = "render")]
 pub mod render;
 #[cfg(feature = "server")]
 pub mod server;
+#[cfg(any(feature = "testing", all(test, feature = "mermaid")))]
+pub mod testing;
@@ -0,0 +1,165 @@
+use crate::{
+    archive::Archive,
+    data::SessionInner,
+};
+
+use chrono::{DateTime, Utc};
+use std::sync::{Arc, RwLock};
+
+/// In-process recorder for sequence diagrams without a running server.
+///
+/// The recorder is a cheap handle to a shared session and can be cloned and
+/// moved into other threads. All clones record into the same session.
+#[derive(Clone)]
+pub struct Recorder {
+    session: Arc<RwLock<SessionInner>>,
+}
+
+impl Default for Recorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl From<Arc<RwLock<SessionInner>>> for Recorder {
+    fn from(session: Arc<RwLock<SessionInner>>) -> Self {
+        Self { session }
+    }
+}
+
+impl Recorder {
+    pub fn new() -> Self {
+        Self::from(Arc::new(RwLock::new(SessionInner::new(0))))
+    }
+
+    /// Records a link with the current time and returns the link id.
+    pub fn record(&self, from: &str, to: &str, label: Option<&str>) -> u64 {
+        self.record_at(Utc::now(), from, to, label)
+    }
+
+    /// Records a link with an explicit timestamp and returns the link id.
+    pub fn record_at(&self, timestamp: DateTime<Utc>, from: &str, to: &str, label: Option<&str>) -> u64 {
+        let mut session = self.session.write().unwrap();
+        session.add_link(timestamp, from, to, label)
+    }
+
+    /// The shared session the recorder writes into.
+    pub fn session(&self) -> Arc<RwLock<SessionInner>> {
+        self.session.clone()
+    }
+
+    /// Runs `f` with read access to the recorded session.
+    pub fn with_session<R>(&self, f: impl FnOnce(&SessionInner) -> R) -> R {
+        let session = self.session.read().unwrap();
+        f(&session)
+    }
+
+    /// Continues recording into a session written by [`Recorder::write_archive`].
+    pub fn read_archive<P: AsRef<std::path::Path>>(path: P) -> std::io::Result<Self> {
+        let invalid = |e| std::io::Error::new(std::io::ErrorKind::InvalidData, e);
+        let archive = Archive::from_json(&std::fs::read_to_string(path)?).map_err(invalid)?;
+        let session = SessionInner::try_from(archive).map_err(invalid)?;
+        Ok(Self::from(Arc::new(RwLock::new(session))))
+    }
+
+    /// The recorded session as JSON archive, e.g. for test fixtures.
+    pub fn archive(&self) -> String {
+        self.with_session(|session| Archive::from(session).to_json())
+    }
+
+    pub fn write_archive<P: AsRef<std::path::Path>>(&self, path: P) -> std::io::Result<()> {
+        std::fs::write(path, self.archive())
+    }
+
+    #[cfg(feature = "mermaid")]
+    pub fn mermaid(&self) -> String {
+        use crate::render::mermaid::Document;
+        self.with_session(|session| Document::from(session).into())
+    }
+
+    #[cfg(feature = "mermaid")]
+    pub fn write_mermaid<P: AsRef<std::path::Path>>(&self, path: P) -> std::io::Result<()> {
+        std::fs::write(path, self.mermaid())
+    }
+
+    #[cfg(feature = "svg")]
+    pub fn svg(&self) -> String {
+        use crate::render::svg::Document;
+        self.with_session(|session| Document::from(session).into())
+    }
+
+    #[cfg(feature = "svg")]
+    pub fn write_svg<P: AsRef<std::path::Path>>(&self, path: P) -> std::io::Result<()> {
+        std::fs::write(path, self.svg())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn record_from_threads() {
+        let recorder = Recorder::new();
+        let handles = (0..4)
+            .map(|i| {
+                let recorder = recorder.clone();
+                thread::spawn(move || {
+                    for _ in 0..10 {
+                        recorder.record(&format!("worker-{i}"), "db", Some("query"));
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        recorder.with_session(|session| {
+            assert_eq!(40, session.links.len());
+            assert_eq!(5, session.parties.len());
+            assert_eq!(40, session.links_highest_id);
+        });
+    }
+
+    #[cfg(all(feature = "mermaid", feature = "svg"))]
+    #[test]
+    fn write_files() {
+        let recorder = Recorder::new();
+        recorder.record("a", "b", Some("Request"));
+        recorder.record("b", "a", Some("Response"));
+
+        let dir = std::env::temp_dir().join(format!("diagramer-recorder-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let svg_path = dir.join("session.svg");
+        let mermaid_path = dir.join("session.mmd");
+        recorder.write_svg(&svg_path).unwrap();
+        recorder.write_mermaid(&mermaid_path).unwrap();
+
+        let svg = std::fs::read_to_string(&svg_path).unwrap();
+        assert!(svg.contains("<svg"));
+        assert!(svg.contains("Request"));
+        let mermaid = std::fs::read_to_string(&mermaid_path).unwrap();
+        assert!(mermaid.starts_with("sequenceDiagram\n"));
+        assert!(mermaid.contains("a ->> b: Request"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn archive_fixture() {
+        let recorder = Recorder::new();
+        recorder.record("a", "b", Some("Request"));
+
+        let path = std::env::temp_dir().join(format!("diagramer-archive-{}.json", std::process::id()));
+        recorder.write_archive(&path).unwrap();
+        let loaded = Recorder::read_archive(&path).unwrap();
+        assert_eq!(recorder.archive(), loaded.archive());
+        assert_eq!(2, loaded.record("b", "a", None));
+
+        std::fs::write(&path, "{}").unwrap();
+        assert_eq!(std::io::ErrorKind::InvalidData, Recorder::read_archive(&path).err().unwrap().kind());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
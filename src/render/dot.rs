@@ -0,0 +1,139 @@
+use crate::data;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+/// Number of distinct labels listed on an edge before the rest is summarized.
+pub const MAX_EDGE_LABELS: usize = 5;
+
+/// Graphviz graph of who talks to whom, the order of the links is dropped.
+#[derive(Deserialize, Serialize)]
+#[cfg_attr(feature = "server", derive(Responder), response(status = 200, content_type = "text/vnd.graphviz"))]
+pub struct Document(String);
+
+/// Quoted DOT strings end at a quote, line breaks are written as `\n`.
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace("\r\n", "\\n")
+        .replace('\n', "\\n")
+}
+
+fn node(party: &data::Party) -> String {
+    format!("P{}", party.id)
+}
+
+#[derive(Default)]
+struct Edge<'a> {
+    count: usize,
+    /// Distinct labels in order of their first link.
+    labels: Vec<&'a str>,
+    seen: HashSet<&'a str>,
+}
+
+impl<'a> Edge<'a> {
+    fn label(&self) -> String {
+        let mut lines = vec![if self.count == 1 {
+            "1 message".to_string()
+        } else {
+            format!("{} messages", self.count)
+        }];
+        lines.extend(self.labels.iter().take(MAX_EDGE_LABELS).map(|label| escape(label)));
+        if self.labels.len() > MAX_EDGE_LABELS {
+            lines.push(format!("and {} more", self.labels.len() - MAX_EDGE_LABELS));
+        }
+        lines.join("\\n")
+    }
+}
+
+impl From<&data::SessionInner> for Document {
+    fn from(session: &data::SessionInner) -> Self {
+        let mut definition = String::new();
+        definition.push_str("digraph session {\n");
+        definition.push_str("    node [shape=box];\n");
+
+        let mut parties = session.parties.iter().map(|i| &i.0).collect::<Vec<_>>();
+        parties.sort();
+
+        for party in parties.iter() {
+            let name = party.label.as_deref().unwrap_or(party.name.as_str());
+            definition.push_str(&format!("    {} [label=\"{}\"];\n", node(party), escape(name)));
+        }
+
+        // Edges in order of their first link
+        let mut edges: Vec<((u64, u64), Edge)> = vec![];
+        let mut positions = HashMap::new();
+        for link in &session.links {
+            let key = (link.from.id, link.to.id);
+            let position = *positions.entry(key).or_insert_with(|| {
+                edges.push((key, Edge::default()));
+                edges.len() - 1
+            });
+            let edge = &mut edges[position].1;
+            edge.count += 1;
+            if let Some(label) = link.label.as_deref() {
+                if edge.seen.insert(label) {
+                    edge.labels.push(label);
+                }
+            }
+        }
+
+        for ((from, to), edge) in &edges {
+            definition.push_str(&format!(
+                "    P{} -> P{} [label=\"{}\", weight={}, penwidth={:.1}];\n",
+                from, to, edge.label(), edge.count, 1.0 + (edge.count as f32).log2(),
+            ));
+        }
+
+        definition.push_str("}\n");
+        Document(definition)
+    }
+}
+
+impl From<Document> for String {
+    fn from(doc: Document) -> String {
+        doc.0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::data::SessionInner;
+    use chrono::Utc;
+
+    #[test]
+    fn generate() {
+        let mut session = SessionInner::new(0);
+        let now = Utc::now();
+        session.add_labeled_party("web", Some("Web \"Client\""));
+        session.add_link(now, "web", "api", Some("GET /items"));
+        session.add_link(now, "api", "web", Some("200\nOK"));
+        session.add_link(now, "web", "api", Some("GET /items"));
+        session.add_link(now, "web", "api", Some("POST /items"));
+        session.add_link(now, "web", "api", None);
+        session.add_link(now, "api", "api", None);
+
+        let doc: String = Document::from(&session).into();
+        assert_eq!(doc, "digraph session {\n\
+            \x20   node [shape=box];\n\
+            \x20   P1 [label=\"Web \\\"Client\\\"\"];\n\
+            \x20   P2 [label=\"api\"];\n\
+            \x20   P1 -> P2 [label=\"4 messages\\nGET /items\\nPOST /items\", weight=4, penwidth=3.0];\n\
+            \x20   P2 -> P1 [label=\"1 message\\n200\\nOK\", weight=1, penwidth=1.0];\n\
+            \x20   P2 -> P2 [label=\"1 message\", weight=1, penwidth=1.0];\n\
+            }\n");
+    }
+
+    #[test]
+    fn summarize_many_labels() {
+        let mut session = SessionInner::new(0);
+        let now = Utc::now();
+        for i in 0..MAX_EDGE_LABELS + 2 {
+            session.add_link(now, "a", "b", Some(&format!("call {i}")));
+        }
+
+        let doc: String = Document::from(&session).into();
+        assert!(doc.contains("call 4\\nand 2 more\""));
+        assert!(!doc.contains("call 5"));
+    }
+}
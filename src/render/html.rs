@@ -0,0 +1,104 @@
+use crate::data;
+use super::{
+    svg::{self, escape},
+    theme::Theme,
+};
+use serde::{Deserialize, Serialize};
+
+const CSS: &str = include_str!(concat!(std::env!("CARGO_MANIFEST_DIR"), "/static/export.css"));
+const JS: &str = include_str!(concat!(std::env!("CARGO_MANIFEST_DIR"), "/static/export.js"));
+
+/// A single HTML page with the diagram, styles and scripts inlined, which
+/// works offline without any further requests.
+#[derive(Deserialize, Serialize)]
+#[cfg_attr(feature = "server", derive(Responder), response(status = 200, content_type = "text/html"))]
+pub struct Document(String);
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Options {
+    /// Title of the page, defaults to the session id.
+    pub title: Option<String>,
+    pub theme: Theme,
+}
+
+impl Document {
+    pub fn render(session: &data::SessionInner, options: &Options) -> Self {
+        let title = escape(&options.title.clone().unwrap_or_else(|| format!("Session {}", session.id)));
+        let diagram: String = svg::Document::render(session, &svg::Options { theme: options.theme, ..Default::default() }).into();
+        let background = options.theme.background;
+
+        Document(format!(r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<meta name="generator" content="diagramer">
+<title>{title}</title>
+<style>
+{CSS}body {{ background: {background}; }}
+</style>
+</head>
+<body>
+<div class="toolbar">
+<h1>{title}</h1>
+<input type="search" id="search" placeholder="Search parties and labels">
+<button id="previous" title="Previous match">&#8593;</button>
+<button id="next" title="Next match">&#8595;</button>
+<span id="matches"></span>
+<button id="reset">Reset view</button>
+</div>
+<div id="diagram">
+{diagram}
+</div>
+<div id="tooltip"></div>
+<script>
+{JS}</script>
+</body>
+</html>
+"#))
+    }
+}
+
+impl From<&data::SessionInner> for Document {
+    fn from(session: &data::SessionInner) -> Self {
+        Self::render(session, &Options::default())
+    }
+}
+
+impl From<Document> for String {
+    fn from(doc: Document) -> String {
+        doc.0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::data::SessionInner;
+    use chrono::Utc;
+
+    #[test]
+    fn self_contained() {
+        let mut session = SessionInner::new(7);
+        session.add_link(Utc::now(), "a", "b", Some("Request"));
+        session.add_link(Utc::now(), "b", "a", Some("Response"));
+
+        let html: String = Document::from(&session).into();
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<title>Session 7</title>"));
+        assert!(html.contains("<svg"));
+        assert!(html.contains(r#"data-link-id="2""#));
+        assert!(html.contains("<style>"));
+        assert!(html.contains("<script>"));
+        assert!(!html.contains("<script src"));
+        assert!(!html.contains("<link "));
+        assert!(!html.contains(" src=") && !html.contains(" href="));
+    }
+
+    #[test]
+    fn escape_title() {
+        let options = Options { title: Some("<b>Audit</b>".to_string()), ..Options::default() };
+        let html: String = Document::render(&SessionInner::new(0), &options).into();
+        assert!(html.contains("<title>&lt;b&gt;Audit&lt;/b&gt;</title>"));
+    }
+}
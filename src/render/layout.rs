@@ -0,0 +1,543 @@
+use crate::{
+    data,
+    render::{
+        metrics::{text_width, wrap},
+        repeat,
+    },
+};
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    sync::Arc,
+};
+
+pub const FONT_SIZE: f32 = 16.0;
+pub const LINE_HEIGHT: f32 = 20.0;
+/// Lanes are never narrower, even for short party names.
+pub const MIN_LANE_WIDTH: f32 = 200.0;
+/// Labels wider than this are wrapped onto several lines.
+pub const MAX_LABEL_WIDTH: f32 = 320.0;
+pub const INTER_LANE_SPACE: f32 = 20.0;
+pub const SPACER_SIZE: f32 = 20.0;
+pub const TEXT_PADDING: f32 = 10.0;
+pub const PARTY_TEXT_OFFSET: f32 = 50.0;
+pub const LINKS_START_HEIGHT: f32 = 100.0;
+pub const STEP_HEIGHT: f32 = 100.0;
+pub const LINK_TEXT_OFFSET: f32 = 20.0;
+pub const LINK_LINE_OFFSET: f32 = 70.0;
+pub const ARROW_SIZE: f32 = SPACER_SIZE / 2.0;
+/// Horizontal size of the loop drawn for links of a party to itself.
+pub const SELF_LINK_WIDTH: f32 = 30.0;
+
+/// Space above the first row of a frame, for its title.
+pub const FRAME_HEADER: f32 = 30.0;
+/// Space below the last row of a frame.
+pub const FRAME_PADDING: f32 = 10.0;
+
+/// A position as `[x, y]`.
+pub type Point = (f32, f32);
+
+/// Lines of text, the first line with its baseline at `y`.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct TextBlock {
+    pub x: f32,
+    pub y: f32,
+    /// `text-anchor` of the lines.
+    pub anchor: &'static str,
+    pub lines: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct Lane {
+    #[serde(skip)]
+    pub party: Arc<data::Party>,
+    pub id: u64,
+    pub name: String,
+    pub center: f32,
+    pub width: f32,
+    /// Top of the box around the lane.
+    pub top: f32,
+    pub height: f32,
+    /// Label or name of the party.
+    pub title: TextBlock,
+    pub lifeline: [Point; 2],
+    /// Arrow head at the end of the lifeline.
+    pub lifeline_end: [Point; 3],
+}
+
+impl Lane {
+    pub fn left(&self) -> f32 {
+        self.center - self.width / 2.0
+    }
+}
+
+#[derive(Serialize)]
+pub struct Row {
+    #[serde(skip)]
+    pub link: Arc<data::Link>,
+    pub id: u64,
+    /// Index of the lane the link starts at.
+    pub from: usize,
+    /// Index of the lane the link ends at.
+    pub to: usize,
+    /// Top of the row.
+    pub y: f32,
+    pub height: f32,
+    /// Wrapped label, `None` without label.
+    pub label: Option<TextBlock>,
+    /// Points of the arrow line, a loop for links of a party to itself.
+    pub line: Vec<Point>,
+    pub arrow: [Point; 3],
+    /// Interrupts the lifelines of the parties the arrow crosses.
+    pub gap: Option<[Point; 2]>,
+}
+
+impl Row {
+    /// Lines of the label, empty without label.
+    pub fn lines(&self) -> &[String] {
+        self.label.as_ref().map_or(&[], |label| &label.lines)
+    }
+
+    /// Vertical position of the arrow, below all lines of the label.
+    pub fn line_y(&self) -> f32 {
+        self.y + LINK_LINE_OFFSET + self.height - STEP_HEIGHT
+    }
+
+    fn height_for(lines: usize) -> f32 {
+        STEP_HEIGHT + lines.saturating_sub(1) as f32 * LINE_HEIGHT
+    }
+
+    /// Places the label and arrow between the lanes.
+    fn place(&mut self, from: &Lane, to: &Lane) {
+        let lines = self.label.take().map(|label| label.lines).unwrap_or_default();
+        let line_y = self.line_y();
+        let text_y = self.y + LINK_TEXT_OFFSET;
+        let (from_x, to_x) = (from.center, to.center);
+
+        if self.from == self.to {
+            let (top, bottom) = (line_y - ARROW_SIZE, line_y + ARROW_SIZE);
+            self.line = vec![
+                (from_x, top),
+                (from_x + SELF_LINK_WIDTH, top),
+                (from_x + SELF_LINK_WIDTH, bottom),
+                (from_x + ARROW_SIZE, bottom),
+            ];
+            self.arrow = [
+                (from_x, bottom),
+                (from_x + ARROW_SIZE, bottom - ARROW_SIZE / 2.0),
+                (from_x + ARROW_SIZE, bottom + ARROW_SIZE / 2.0),
+            ];
+            self.label = (!lines.is_empty()).then_some(TextBlock {
+                x: from_x + SELF_LINK_WIDTH + TEXT_PADDING,
+                y: text_y,
+                anchor: "start",
+                lines,
+            });
+            return;
+        }
+
+        let direction = if to_x > from_x { 1.0 } else { -1.0 };
+        let gap = (
+            from_x + direction * from.width / 2.0,
+            to_x - direction * (ARROW_SIZE + to.width / 2.0),
+        );
+        self.gap = ((gap.1 - gap.0) * direction > 0.0).then_some([(gap.0, line_y), (gap.1, line_y)]);
+        self.line = vec![(from_x, line_y), (to_x - direction * ARROW_SIZE, line_y)];
+        self.arrow = [
+            (to_x, line_y),
+            (to_x - direction * ARROW_SIZE, line_y - ARROW_SIZE / 2.0),
+            (to_x - direction * ARROW_SIZE, line_y + ARROW_SIZE / 2.0),
+        ];
+        self.label = (!lines.is_empty()).then_some(TextBlock {
+            x: (from_x + to_x) / 2.0,
+            y: text_y,
+            anchor: "middle",
+            lines,
+        });
+    }
+}
+
+/// Frame around links repeated one after the other, which are drawn once.
+#[derive(Serialize)]
+pub struct Frame {
+    /// Index of the first row inside.
+    pub first_row: usize,
+    /// Number of rows inside.
+    pub rows: usize,
+    /// Number of repetitions.
+    pub count: usize,
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    /// `loop ×N` in the tab at the top left corner.
+    pub title: TextBlock,
+    pub tab: [Point; 5],
+}
+
+/// How [`Layout::append`] changed the layout.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Change {
+    /// Only rows were added, existing rows kept their positions.
+    RowsAppended,
+    /// Lanes moved apart for the new labels, all rows were placed again.
+    LanesMoved,
+    /// Links of unknown parties weren't added, the layout must be built again.
+    NewParties,
+}
+
+/// Positions of all parts of a sequence diagram, sized to fit the texts.
+///
+/// The SVG renderer and the live view of the frontend both draw from this
+/// model.
+#[derive(Serialize)]
+pub struct Layout {
+    pub lanes: Vec<Lane>,
+    pub rows: Vec<Row>,
+    /// Frames of repeated rows, only with [`Layout::collapsed`].
+    pub frames: Vec<Frame>,
+    /// End of the last row.
+    pub lanes_end_y: f32,
+    pub width: f32,
+    pub height: f32,
+    pub line_height: f32,
+    /// Lane indices by party name.
+    #[serde(skip)]
+    indices: HashMap<String, usize>,
+    /// Distances between the centers of neighbouring lanes.
+    #[serde(skip)]
+    gaps: Vec<f32>,
+    /// Space right of the last lane for labels of links to itself.
+    #[serde(skip)]
+    overhang: f32,
+}
+
+fn lines_width(lines: &[String]) -> f32 {
+    lines.iter()
+        .map(|line| text_width(line, FONT_SIZE))
+        .fold(0.0, f32::max)
+}
+
+/// Widens the gaps between `from` and `to` evenly until they sum up to
+/// `required`.
+fn widen(gaps: &mut [f32], from: usize, to: usize, required: f32) {
+    let current: f32 = gaps[from..to].iter().sum();
+    if current < required {
+        let extra = (required - current) / (to - from) as f32;
+        for gap in &mut gaps[from..to] {
+            *gap += extra;
+        }
+    }
+}
+
+impl Layout {
+    /// Lanes of `parties` without any rows.
+    pub fn new(parties: &[&Arc<data::Party>]) -> Self {
+        let names = parties.iter()
+            .map(|party| party.label.as_deref()
+                .unwrap_or(party.name.as_str())
+                .lines()
+                .map(str::to_string)
+                .collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        let header_lines = names.iter().map(Vec::len).max().unwrap_or(1).max(1);
+        let extra_header = (header_lines - 1) as f32 * LINE_HEIGHT;
+
+        let lanes = parties.iter()
+            .zip(names)
+            .map(|(party, lines)| Lane {
+                party: Arc::clone(party),
+                id: party.id,
+                name: party.name.clone(),
+                center: 0.0,
+                width: MIN_LANE_WIDTH.max(lines_width(&lines) + 2.0 * TEXT_PADDING),
+                top: SPACER_SIZE,
+                height: 0.0,
+                title: TextBlock { x: 0.0, y: PARTY_TEXT_OFFSET, anchor: "middle", lines },
+                lifeline: [(0.0, SPACER_SIZE + PARTY_TEXT_OFFSET + extra_header); 2],
+                lifeline_end: Default::default(),
+            })
+            .collect::<Vec<_>>();
+        let gaps = lanes.windows(2)
+            .map(|pair| (pair[0].width + pair[1].width) / 2.0 + INTER_LANE_SPACE)
+            .collect();
+        let indices = parties.iter()
+            .enumerate()
+            .map(|(index, party)| (party.name.clone(), index))
+            .collect();
+
+        let mut layout = Layout {
+            lanes,
+            rows: vec![],
+            frames: vec![],
+            lanes_end_y: LINKS_START_HEIGHT + extra_header,
+            width: 0.0,
+            height: 0.0,
+            line_height: LINE_HEIGHT,
+            indices,
+            gaps,
+            overhang: 0.0,
+        };
+        layout.place_lanes();
+        layout
+    }
+
+    /// Adds rows for links appended to the session.
+    ///
+    /// Every gap between two lanes is widened for the labels crossing it, in
+    /// order of the links, so appending gives the same layout as building it
+    /// with all links at once.
+    pub fn append(&mut self, links: &[Arc<data::Link>]) -> Change {
+        let known = |party: &data::Party| self.indices.contains_key(&party.name);
+        if !links.iter().all(|link| known(&link.from) && known(&link.to)) {
+            return Change::NewParties;
+        }
+
+        let gaps = self.gaps.clone();
+        let first = self.rows.len();
+        for link in links {
+            let lines = link.label.as_deref()
+                .map(|label| wrap(label, FONT_SIZE, MAX_LABEL_WIDTH))
+                .unwrap_or_default();
+            let (from, to) = (self.indices[&link.from.name], self.indices[&link.to.name]);
+            self.widen_for(from, to, lines_width(&lines));
+
+            let height = Row::height_for(lines.len());
+            self.rows.push(Row {
+                link: Arc::clone(link),
+                id: link.id,
+                from,
+                to,
+                y: self.lanes_end_y,
+                height,
+                label: (!lines.is_empty()).then_some(TextBlock { x: 0.0, y: 0.0, anchor: "middle", lines }),
+                line: vec![],
+                arrow: Default::default(),
+                gap: None,
+            });
+            self.lanes_end_y += height;
+        }
+        self.place_lanes();
+
+        let change = if gaps == self.gaps { Change::RowsAppended } else { Change::LanesMoved };
+        let first = if change == Change::LanesMoved { 0 } else { first };
+        for row in &mut self.rows[first..] {
+            row.place(&self.lanes[row.from], &self.lanes[row.to]);
+        }
+        change
+    }
+
+    /// Like [`Layout::from`], but links repeated one after the other are
+    /// placed once inside a frame.
+    pub fn collapsed(session: &data::SessionInner) -> Self {
+        let mut parties = session.parties.iter().map(|i| &i.0).collect::<Vec<_>>();
+        parties.sort();
+
+        let mut layout = Layout::new(&parties);
+        let mut next = 0;
+        let mut frames = vec![];
+        for repeat in repeat::find(&session.links) {
+            layout.append(&session.links[next..repeat.start]);
+            let y = layout.lanes_end_y;
+            layout.lanes_end_y += FRAME_HEADER;
+            let first_row = layout.rows.len();
+            layout.append(&session.links[repeat.start..repeat.start + repeat.len]);
+            layout.lanes_end_y += FRAME_PADDING;
+            frames.push((first_row, repeat, y, layout.lanes_end_y - y));
+            next = repeat.end();
+        }
+        layout.append(&session.links[next..]);
+        layout.place_lanes();
+
+        // The lanes are only in place once all rows are added
+        for (first_row, repeat, y, height) in frames {
+            let rows = &layout.rows[first_row..first_row + repeat.len];
+            let first_lane = rows.iter().map(|row| row.from.min(row.to)).min().unwrap_or_default();
+            let last_lane = rows.iter().map(|row| row.from.max(row.to)).max().unwrap_or_default();
+            let x = layout.lanes[first_lane].left();
+            let right = rows.iter()
+                .filter_map(|row| row.label.as_ref().filter(|label| label.anchor == "start"))
+                .map(|label| label.x + lines_width(&label.lines) + TEXT_PADDING)
+                .fold(layout.lanes[last_lane].left() + layout.lanes[last_lane].width, f32::max);
+
+            let title = format!("loop ×{}", repeat.count);
+            let tab_width = text_width(&title, FONT_SIZE) + 3.0 * TEXT_PADDING;
+            let tab_height = LINE_HEIGHT + ARROW_SIZE / 2.0;
+            let corner = ARROW_SIZE / 2.0;
+            layout.frames.push(Frame {
+                first_row,
+                rows: repeat.len,
+                count: repeat.count,
+                x,
+                y,
+                width: right - x,
+                height,
+                title: TextBlock { x: x + TEXT_PADDING, y: y + LINE_HEIGHT - corner, anchor: "start", lines: vec![title] },
+                tab: [
+                    (x, y),
+                    (x + tab_width, y),
+                    (x + tab_width, y + tab_height - corner),
+                    (x + tab_width - corner, y + tab_height),
+                    (x, y + tab_height),
+                ],
+            });
+        }
+        layout
+    }
+
+    /// Makes room for a label of `width` between the lanes `from` and `to`.
+    fn widen_for(&mut self, from: usize, to: usize, width: f32) {
+        let (from, to) = (from.min(to), from.max(to));
+        if from != to {
+            widen(&mut self.gaps, from, to, width + 2.0 * TEXT_PADDING);
+            return;
+        }
+        // Labels of links to the same party are right of the loop
+        let required = SELF_LINK_WIDTH + width + 2.0 * TEXT_PADDING;
+        if from < self.gaps.len() {
+            widen(&mut self.gaps, from, from + 1, required);
+        } else {
+            self.overhang = self.overhang.max(required - self.lanes[from].width / 2.0);
+        }
+    }
+
+    /// Positions the lanes along the gaps, down to the end of the last row.
+    fn place_lanes(&mut self) {
+        let end = SPACER_SIZE + self.lanes_end_y;
+        let mut center = SPACER_SIZE + self.lanes.first().map_or(0.0, |lane| lane.width / 2.0);
+        for (index, lane) in self.lanes.iter_mut().enumerate() {
+            let lifeline_y = lane.lifeline[0].1;
+            lane.center = center;
+            lane.height = self.lanes_end_y + SPACER_SIZE;
+            lane.title.x = center;
+            lane.lifeline = [(center, lifeline_y), (center, end - ARROW_SIZE)];
+            lane.lifeline_end = [
+                (center, end),
+                (center - ARROW_SIZE / 2.0, end - ARROW_SIZE),
+                (center + ARROW_SIZE / 2.0, end - ARROW_SIZE),
+            ];
+            center += self.gaps.get(index).copied().unwrap_or_default();
+        }
+
+        self.width = self.lanes.last()
+            .map_or(SPACER_SIZE, |lane| lane.center + lane.width / 2.0 + self.overhang.max(0.0))
+            + INTER_LANE_SPACE;
+        self.height = self.lanes_end_y + 2.0 * SPACER_SIZE;
+    }
+}
+
+impl From<&data::SessionInner> for Layout {
+    fn from(session: &data::SessionInner) -> Self {
+        let mut parties = session.parties.iter().map(|i| &i.0).collect::<Vec<_>>();
+        parties.sort();
+
+        let mut layout = Layout::new(&parties);
+        layout.append(&session.links);
+        layout
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::data::SessionInner;
+    use chrono::Utc;
+
+    #[test]
+    fn default_spacing() {
+        let mut session = SessionInner::new(0);
+        session.add_link(Utc::now(), "a", "b", Some("Request"));
+        session.add_link(Utc::now(), "b", "a", None);
+
+        let layout = Layout::from(&session);
+        assert_eq!(vec![120.0, 340.0], layout.lanes.iter().map(|lane| lane.center).collect::<Vec<_>>());
+        assert_eq!(vec![100.0, 200.0], layout.rows.iter().map(|row| row.y).collect::<Vec<_>>());
+        assert_eq!(460.0, layout.width);
+        assert_eq!(340.0, layout.height);
+    }
+
+    #[test]
+    fn widen_gaps_for_labels() {
+        let mut session = SessionInner::new(0);
+        let long = "GET /api/session/2888964795923373081/links";
+        session.add_link(Utc::now(), "a", "b", Some("short"));
+        session.add_link(Utc::now(), "b", "c", Some(long));
+        session.add_link(Utc::now(), "a", "c", Some(&"x".repeat(100)));
+        session.add_link(Utc::now(), "c", "c", Some("Process\nsecond line"));
+
+        let layout = Layout::from(&session);
+        let centers = layout.lanes.iter().map(|lane| lane.center).collect::<Vec<_>>();
+        assert_eq!(220.0, centers[1] - centers[0]);
+        let label_width = lines_width(layout.rows[1].lines());
+        assert!(label_width <= MAX_LABEL_WIDTH);
+        assert!(centers[2] - centers[1] >= label_width + 2.0 * TEXT_PADDING);
+        assert!(centers[2] - centers[0] >= MAX_LABEL_WIDTH);
+
+        assert_eq!(2, layout.rows[1].lines().len());
+        assert_eq!(["Process", "second line"], layout.rows[3].lines());
+        assert_eq!(layout.rows[3].y + 120.0, layout.lanes_end_y);
+        let loop_end = centers[2] + SELF_LINK_WIDTH + text_width("second line", FONT_SIZE);
+        assert!(layout.width > loop_end);
+    }
+
+    #[test]
+    fn wide_party_names() {
+        let mut session = SessionInner::new(0);
+        session.add_labeled_party("a", Some("A party with a very long display name"));
+        session.add_link(Utc::now(), "a", "b", None);
+
+        let layout = Layout::from(&session);
+        assert!(layout.lanes[0].width > MIN_LANE_WIDTH);
+        assert_eq!(MIN_LANE_WIDTH, layout.lanes[1].width);
+        assert!(layout.lanes[1].left() - (layout.lanes[0].left() + layout.lanes[0].width) >= INTER_LANE_SPACE - 0.001);
+    }
+
+    #[test]
+    fn append_links() {
+        let mut session = SessionInner::new(0);
+        session.add_link(Utc::now(), "a", "b", Some("Request"));
+        session.add_link(Utc::now(), "b", "a", None);
+        let mut layout = Layout::from(&session);
+
+        session.add_link(Utc::now(), "a", "b", Some("Retry"));
+        assert_eq!(Change::RowsAppended, layout.append(&session.links[2..]));
+        session.add_link(Utc::now(), "a", "a", Some(&"x".repeat(100)));
+        assert_eq!(Change::LanesMoved, layout.append(&session.links[3..]));
+        let expected = Layout::from(&session);
+        session.add_link(Utc::now(), "b", "c", None);
+        assert_eq!(Change::NewParties, layout.append(&session.links[4..]));
+        assert_eq!(4, layout.rows.len());
+
+        assert_eq!(expected.width, layout.width);
+        assert_eq!(expected.height, layout.height);
+        assert_eq!(
+            expected.lanes.iter().map(|lane| (lane.center, lane.lifeline)).collect::<Vec<_>>(),
+            layout.lanes.iter().map(|lane| (lane.center, lane.lifeline)).collect::<Vec<_>>());
+        assert_eq!(
+            expected.rows.iter().map(|row| (row.y, row.label.clone(), row.arrow)).collect::<Vec<_>>(),
+            layout.rows.iter().map(|row| (row.y, row.label.clone(), row.arrow)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn collapse_repeated_rows() {
+        let mut session = SessionInner::new(0);
+        session.add_link(Utc::now(), "a", "b", Some("Start"));
+        for _ in 0..20 {
+            session.add_link(Utc::now(), "a", "b", Some("Poll"));
+            session.add_link(Utc::now(), "b", "a", Some("Pending"));
+        }
+        session.add_link(Utc::now(), "b", "b", Some("Done"));
+
+        let layout = Layout::collapsed(&session);
+        assert_eq!(vec![1, 2, 3, 42], layout.rows.iter().map(|row| row.id).collect::<Vec<_>>());
+        let frame = &layout.frames[0];
+        assert_eq!((1, 2, 20), (frame.first_row, frame.rows, frame.count));
+        assert_eq!(["loop ×20"], frame.title.lines.as_slice());
+        assert_eq!(200.0, frame.y);
+        assert_eq!(frame.y + FRAME_HEADER, layout.rows[1].y);
+        assert_eq!(layout.rows[3].y, frame.y + frame.height);
+        assert_eq!(layout.lanes[0].left(), frame.x);
+        assert_eq!(layout.lanes[1].left() + layout.lanes[1].width, frame.x + frame.width);
+        assert!(Layout::from(&session).frames.is_empty());
+    }
+}
@@ -1,12 +1,25 @@
 use crate::data;
 use serde::{Deserialize, Serialize};
+use super::repeat;
 
-#[derive(Deserialize, Serialize, Responder)]
-#[response(status = 200)]
+#[derive(Deserialize, Serialize)]
+#[cfg_attr(feature = "server", derive(Responder), response(status = 200))]
 pub struct Document(String);
 
-impl From<&data::SessionInner> for Document {
-    fn from(session: &data::SessionInner) -> Self {
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Options {
+    /// Draws links repeated one after the other once inside a `loop` block.
+    pub collapse_repeats: bool,
+}
+
+fn message(link: &data::Link) -> String {
+    let from = link.from.name.as_str().replace("\n", "<br>");
+    let to = link.to.name.as_str().replace("\n", "<br>");
+    format!("{from} ->> {to}: {}", link.label.as_deref().unwrap_or_default())
+}
+
+impl Document {
+    pub fn render(session: &data::SessionInner, options: &Options) -> Self {
         let mut definition = String::new();
         definition.push_str("sequenceDiagram\n");
 
@@ -21,22 +34,65 @@ impl From<&data::SessionInner> for Document {
                     ));
         }
 
-        for link in &session.links {
-            let from = link.from.name.as_str().replace("\n", "<br>");
-            let to = link.to.name.as_str().replace("\n", "<br>");
-            definition.push_str(&format!(
-                    "  {from} ->> {to}: {}\n",
-                    if let Some(label) = link.label.as_deref() { label } else { "" }
-                    ));
+        let repeats = if options.collapse_repeats { repeat::find(&session.links) } else { vec![] };
+        let mut next = 0;
+        for repeat in repeats {
+            for link in &session.links[next..repeat.start] {
+                definition.push_str(&format!("  {}\n", message(link)));
+            }
+            definition.push_str(&format!("  loop ×{}\n", repeat.count));
+            for link in &session.links[repeat.start..repeat.start + repeat.len] {
+                definition.push_str(&format!("    {}\n", message(link)));
+            }
+            definition.push_str("  end\n");
+            next = repeat.end();
+        }
+        for link in &session.links[next..] {
+            definition.push_str(&format!("  {}\n", message(link)));
         }
 
         Document(definition)
     }
 }
 
-impl Into<String> for Document {
-    fn into(self) -> String {
-        self.0
+impl From<&data::SessionInner> for Document {
+    fn from(session: &data::SessionInner) -> Self {
+        Self::render(session, &Options::default())
     }
 }
 
+impl From<Document> for String {
+    fn from(doc: Document) -> String {
+        doc.0
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::data::SessionInner;
+    use chrono::Utc;
+
+    #[test]
+    fn collapse_repeats() {
+        let mut session = SessionInner::new(0);
+        let now = Utc::now();
+        for _ in 0..3 {
+            session.add_link(now, "web", "api", Some("GET /health"));
+            session.add_link(now, "api", "web", Some("200"));
+        }
+        session.add_link(now, "web", "api", Some("POST /order"));
+
+        let doc: String = Document::render(&session, &Options { collapse_repeats: true }).into();
+        assert!(doc.ends_with("\
+            \x20 loop ×3\n\
+            \x20   web ->> api: GET /health\n\
+            \x20   api ->> web: 200\n\
+            \x20 end\n\
+            \x20 web ->> api: POST /order\n"));
+
+        let doc: String = Document::from(&session).into();
+        assert_eq!(3, doc.matches("GET /health").count());
+    }
+}
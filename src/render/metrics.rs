@@ -0,0 +1,136 @@
+/// Advance widths of Helvetica for the printable ASCII range in 1/1000 em,
+/// starting with the space character.
+const HELVETICA_WIDTHS: [u16; 95] = [
+    278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278,
+    556, 556, 556, 556, 556, 556, 556, 556, 556, 556, 278, 278, 584, 584, 584, 556,
+    1015, 667, 667, 722, 722, 667, 611, 778, 722, 278, 500, 667, 556, 833, 722, 778,
+    667, 778, 722, 667, 611, 722, 667, 944, 667, 667, 611, 278, 278, 278, 469, 556,
+    333, 556, 556, 500, 556, 556, 278, 556, 556, 222, 222, 500, 222, 833, 556, 556,
+    556, 556, 333, 500, 278, 556, 500, 722, 500, 500, 500, 334, 260, 334, 584,
+];
+
+/// Width used for characters outside of the table.
+const FALLBACK_WIDTH: u16 = 600;
+
+/// Estimated advance width of a character in 1/1000 em.
+pub fn char_width(c: char) -> u16 {
+    match c {
+        ' '..='~' => HELVETICA_WIDTHS[c as usize - ' ' as usize],
+        _ => FALLBACK_WIDTH,
+    }
+}
+
+/// Estimated width of a single line of `text` at `font_size`.
+pub fn text_width(text: &str, font_size: f32) -> f32 {
+    text.chars().map(|c| char_width(c) as f32).sum::<f32>() * font_size / 1000.0
+}
+
+/// Shortens `text` with an ellipsis until it fits into `max_width`.
+pub fn truncate(text: &str, font_size: f32, max_width: f32) -> String {
+    if text_width(text, font_size) <= max_width {
+        return text.to_string();
+    }
+
+    let ellipsis = "...";
+    let mut width = text_width(ellipsis, font_size);
+    let mut truncated = String::new();
+    for c in text.chars() {
+        width += char_width(c) as f32 * font_size / 1000.0;
+        if width > max_width {
+            break;
+        }
+        truncated.push(c);
+    }
+    truncated.push_str(ellipsis);
+    truncated
+}
+
+/// Splits a line into pieces which may be moved to the next line, breaking
+/// after spaces, commas and dashes and before the separators of URLs.
+fn break_opportunities(line: &str) -> Vec<&str> {
+    let mut pieces = vec![];
+    let mut start = 0;
+    for (pos, c) in line.char_indices() {
+        if matches!(c, '/' | '?' | '&' | '=' | '.') && pos > start {
+            pieces.push(&line[start..pos]);
+            start = pos;
+        } else if matches!(c, ' ' | ',' | '-') {
+            pieces.push(&line[start..pos + c.len_utf8()]);
+            start = pos + c.len_utf8();
+        }
+    }
+    if start < line.len() {
+        pieces.push(&line[start..]);
+    }
+    pieces
+}
+
+/// Wraps `text` into lines which fit into `max_width`, keeping the line
+/// breaks of the text. Pieces longer than `max_width` are split anywhere.
+pub fn wrap(text: &str, font_size: f32, max_width: f32) -> Vec<String> {
+    let mut lines = vec![];
+    for line in text.lines() {
+        let mut current = String::new();
+        for piece in break_opportunities(line) {
+            let candidate = format!("{current}{piece}");
+            if text_width(candidate.trim_end(), font_size) <= max_width || current.is_empty() {
+                current = candidate;
+            } else {
+                lines.push(current.trim_end().to_string());
+                current = piece.trim_start().to_string();
+            }
+
+            while text_width(current.trim_end(), font_size) > max_width {
+                let mut width = 0.0;
+                let split = current.char_indices()
+                    .find(|(_, c)| {
+                        width += char_width(*c) as f32 * font_size / 1000.0;
+                        width > max_width
+                    })
+                    .map(|(pos, _)| pos.max(current.chars().next().map_or(0, char::len_utf8)))
+                    .unwrap_or(current.len());
+                lines.push(current[..split].to_string());
+                current = current[split..].to_string();
+            }
+        }
+        lines.push(current.trim_end().to_string());
+    }
+    if lines.is_empty() {
+        lines.push(String::new());
+    }
+    lines
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn widths() {
+        assert_eq!(278, char_width(' '));
+        assert_eq!(584, char_width('~'));
+        assert_eq!(667, char_width('A'));
+        assert_eq!(FALLBACK_WIDTH, char_width('ä'));
+        assert_eq!(11.12, text_width("ab", 10.0));
+    }
+
+    #[test]
+    fn truncate_long_text() {
+        assert_eq!("short", truncate("short", 10.0, 100.0));
+        let truncated = truncate("a rather long label", 10.0, 40.0);
+        assert!(truncated.ends_with("..."));
+        assert!(text_width(&truncated, 10.0) <= 40.0);
+    }
+
+    #[test]
+    fn wrap_lines() {
+        assert_eq!(vec!["short"], wrap("short", 10.0, 100.0));
+        assert_eq!(vec!["first", "second line"], wrap("first\nsecond line", 10.0, 100.0));
+        assert_eq!(vec!["a rather long", "label"], wrap("a rather long label", 10.0, 70.0));
+        assert_eq!(
+            vec!["GET /api/session", "/12345/links?from", "=a&to=b"],
+            wrap("GET /api/session/12345/links?from=a&to=b", 10.0, 90.0));
+        assert_eq!(vec!["abcd", "efghi", "j"], wrap("abcdefghij", 10.0, 23.0));
+        assert_eq!(vec![""], wrap("", 10.0, 10.0));
+    }
+}
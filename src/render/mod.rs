@@ -1,4 +1,26 @@
+#[cfg(feature = "dot")]
+pub mod dot;
+#[cfg(feature = "html")]
+pub mod html;
+#[cfg(feature = "svg")]
+pub mod layout;
 #[cfg(feature = "mermaid")]
 pub mod mermaid;
+pub mod metrics;
+#[cfg(feature = "pdf")]
+pub mod pdf;
+#[cfg(feature = "plantuml")]
+pub mod plantuml;
+#[cfg(feature = "png")]
+pub mod png;
+pub mod registry;
+#[cfg(any(feature = "mermaid", feature = "svg"))]
+pub mod repeat;
 #[cfg(feature = "svg")]
 pub mod svg;
+#[cfg(feature = "text")]
+pub mod text;
+#[cfg(feature = "svg")]
+pub mod theme;
+
+pub use registry::{Registry, Renderer};
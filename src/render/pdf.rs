@@ -0,0 +1,471 @@
+use crate::{
+    data,
+    render::metrics::{text_width, truncate},
+};
+use chrono::SecondsFormat;
+use pdf_writer::{Content, Finish, Name, Pdf, Rect, Ref, Str, TextStr};
+use serde::{Deserialize, Serialize};
+use std::{
+    ops::Deref,
+    str::FromStr,
+    sync::Arc,
+};
+
+const MARGIN: f32 = 40.0;
+const HEADER_HEIGHT: f32 = 28.0;
+const HEADER_GAP: f32 = 12.0;
+const FOOTER_HEIGHT: f32 = 20.0;
+const ROW_HEIGHT: f32 = 28.0;
+const ARROW_SIZE: f32 = 6.0;
+const FONT_SIZE: f32 = 9.0;
+const LINE_HEIGHT: f32 = 1.2 * FONT_SIZE;
+/// Lines of a party name fitting into the header.
+const HEADER_LINES: usize = 2;
+const TITLE_FONT_SIZE: f32 = 22.0;
+const FONT: Name = Name(b"F1");
+
+#[derive(Deserialize, Serialize)]
+#[cfg_attr(feature = "server", derive(Responder), response(status = 200, content_type = "application/pdf"))]
+pub struct Document(Vec<u8>);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Paper {
+    #[default]
+    A4,
+    Letter,
+}
+
+impl Paper {
+    /// Portrait size in points.
+    pub fn size(&self) -> (f32, f32) {
+        match self {
+            Paper::A4 => (595.0, 842.0),
+            Paper::Letter => (612.0, 792.0),
+        }
+    }
+}
+
+impl FromStr for Paper {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "a4" => Ok(Paper::A4),
+            "letter" => Ok(Paper::Letter),
+            _ => Err(format!("Unknown paper size {s}, expected a4 or letter")),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Options {
+    pub paper: Paper,
+    pub landscape: bool,
+    /// Title on the first page, defaults to the session id.
+    pub title: Option<String>,
+}
+
+impl Options {
+    fn page_size(&self) -> (f32, f32) {
+        let (width, height) = self.paper.size();
+        if self.landscape { (height, width) } else { (width, height) }
+    }
+
+    fn rows_height(&self) -> f32 {
+        let (_, height) = self.page_size();
+        height - 2.0 * MARGIN - HEADER_HEIGHT - HEADER_GAP - FOOTER_HEIGHT
+    }
+
+    /// Number of links with single line labels fitting on one page below the
+    /// participant header.
+    pub fn rows_per_page(&self) -> usize {
+        ((self.rows_height() / ROW_HEIGHT) as usize).max(1)
+    }
+
+    /// Most lines of a label, so that its row fits on a page.
+    fn max_label_lines(&self) -> usize {
+        ((self.rows_height() - ROW_HEIGHT) / LINE_HEIGHT).max(0.0) as usize + 1
+    }
+
+    /// Height of the row of `link`, growing with the lines of its label.
+    fn row_height(&self, link: &data::Link) -> f32 {
+        let lines = link.label.as_deref().map_or(1, |label| label.lines().count().clamp(1, self.max_label_lines()));
+        ROW_HEIGHT + (lines - 1) as f32 * LINE_HEIGHT
+    }
+
+    /// Splits `links` into the rows of the pages, at least one on each page.
+    fn pages<'l>(&self, links: &'l [Arc<data::Link>]) -> Vec<&'l [Arc<data::Link>]> {
+        let rows_height = self.rows_height();
+        let mut pages = vec![];
+        let (mut start, mut used) = (0, 0.0);
+        for (index, link) in links.iter().enumerate() {
+            let height = self.row_height(link);
+            if index > start && used + height > rows_height {
+                pages.push(&links[start..index]);
+                (start, used) = (index, 0.0);
+            }
+            used += height;
+        }
+        if start < links.len() {
+            pages.push(&links[start..]);
+        }
+        pages
+    }
+}
+
+/// Encodes text for the WinAnsi encoded standard font, unsupported
+/// characters are replaced.
+fn encode(text: &str) -> Vec<u8> {
+    text.chars()
+        .map(|c| match c as u32 {
+            0x20..=0x7e | 0xa0..=0xff => c as u8,
+            _ => b'?',
+        })
+        .collect()
+}
+
+/// The first `max_lines` lines of `text`, with an ellipsis if there are more.
+fn cut_lines(text: &str, max_lines: usize) -> Vec<String> {
+    let mut lines = text.lines().map(str::to_string).collect::<Vec<_>>();
+    if lines.len() > max_lines {
+        lines.truncate(max_lines);
+        if let Some(last) = lines.last_mut() {
+            last.push_str("...");
+        }
+    }
+    lines
+}
+
+struct Writer<'a> {
+    session: &'a data::SessionInner,
+    options: &'a Options,
+    width: f32,
+    height: f32,
+    parties: Vec<&'a data::Party>,
+}
+
+impl<'a> Writer<'a> {
+    fn text(&self, content: &mut Content, x: f32, y: f32, size: f32, text: &str) {
+        content.begin_text();
+        content.set_font(FONT, size);
+        content.next_line(x, y);
+        content.show(Str(&encode(text)));
+        content.end_text();
+    }
+
+    fn centered_text(&self, content: &mut Content, x: f32, y: f32, size: f32, text: &str) {
+        self.text(content, x - text_width(text, size) / 2.0, y, size, text);
+    }
+
+    /// Draws `lines` upwards from the last one on `y`, each truncated to
+    /// `max_width`, as the font has no line breaks.
+    fn lines(&self, content: &mut Content, x: f32, y: f32, max_width: f32, lines: &[String], centered: bool) {
+        for (index, line) in lines.iter().rev().enumerate() {
+            let line = truncate(line, FONT_SIZE, max_width);
+            let y = y + index as f32 * LINE_HEIGHT;
+            match centered {
+                true => self.centered_text(content, x, y, FONT_SIZE, &line),
+                false => self.text(content, x, y, FONT_SIZE, &line),
+            }
+        }
+    }
+
+    fn lane_width(&self) -> f32 {
+        (self.width - 2.0 * MARGIN) / self.parties.len().max(1) as f32
+    }
+
+    fn lane_center(&self, name: &str) -> f32 {
+        let index = self.parties.iter().position(|party| party.name == name).unwrap_or(0);
+        MARGIN + (index as f32 + 0.5) * self.lane_width()
+    }
+
+    fn footer(&self, content: &mut Content, page: usize, pages: usize) {
+        let text = format!("Session {} - page {page} of {pages}", self.session.id);
+        self.centered_text(content, self.width / 2.0, MARGIN, FONT_SIZE, &text);
+    }
+
+    fn title_page(&self, pages: usize) -> Vec<u8> {
+        let mut content = Content::new();
+        let title = self.options.title.clone()
+            .unwrap_or_else(|| format!("Session {}", self.session.id));
+        let mut y = self.height - 3.0 * MARGIN;
+        self.text(&mut content, MARGIN, y, TITLE_FONT_SIZE, &truncate(&title, TITLE_FONT_SIZE, self.width - 2.0 * MARGIN));
+        y -= 2.0 * TITLE_FONT_SIZE;
+
+        let timestamps = self.session.links.iter().map(|link| link.timestamp);
+        let lines = match (timestamps.clone().min(), timestamps.max()) {
+            (Some(first), Some(last)) => vec![
+                format!("From: {}", first.to_rfc3339_opts(SecondsFormat::Millis, true)),
+                format!("To: {}", last.to_rfc3339_opts(SecondsFormat::Millis, true)),
+            ],
+            _ => vec!["No links recorded".to_string()],
+        };
+        let lines = lines.into_iter().chain([
+            format!("Parties: {}", self.parties.len()),
+            format!("Links: {}", self.session.links.len()),
+        ]);
+        for line in lines {
+            self.text(&mut content, MARGIN, y, 2.0 * FONT_SIZE, &line);
+            y -= 3.0 * FONT_SIZE;
+        }
+
+        self.footer(&mut content, 1, pages);
+        content.finish()
+    }
+
+    fn links_page(&self, links: &[Arc<data::Link>], page: usize, pages: usize) -> Vec<u8> {
+        let mut content = Content::new();
+        let lane_width = self.lane_width();
+        let header_top = self.height - MARGIN;
+        let header_bottom = header_top - HEADER_HEIGHT;
+        let rows_top = header_bottom - HEADER_GAP;
+        let heights = links.iter().map(|link| self.options.row_height(link)).collect::<Vec<_>>();
+        let rows_bottom = rows_top - heights.iter().sum::<f32>();
+
+        // Participant header and life lines, repeated on every page
+        content.set_stroke_gray(0.6);
+        content.set_line_width(0.8);
+        for index in 0..self.parties.len() {
+            let x = MARGIN + index as f32 * lane_width;
+            content.rect(x + 2.0, header_bottom, lane_width - 4.0, HEADER_HEIGHT);
+            content.stroke();
+            content.move_to(x + lane_width / 2.0, header_bottom);
+            content.line_to(x + lane_width / 2.0, rows_bottom);
+            content.stroke();
+        }
+        for (index, party) in self.parties.iter().enumerate() {
+            let x = MARGIN + (index as f32 + 0.5) * lane_width;
+            let party = party.label.as_deref().unwrap_or(party.name.as_str());
+            let lines = cut_lines(party, HEADER_LINES);
+            let y = header_bottom + (HEADER_HEIGHT - FONT_SIZE) / 2.0 + 1.0
+                - lines.len().saturating_sub(1) as f32 * LINE_HEIGHT / 2.0;
+            self.lines(&mut content, x, y, lane_width - 8.0, &lines, true);
+        }
+
+        content.set_stroke_gray(0.0);
+        content.set_fill_gray(0.0);
+        content.set_line_width(1.0);
+        let mut row_top = rows_top;
+        for (link, height) in links.iter().zip(heights) {
+            // The arrow is at the bottom of the row, the label lines above it
+            let y = row_top - height + 0.25 * ROW_HEIGHT;
+            row_top -= height;
+            let label = link.label.as_deref().map(|label| cut_lines(label, self.options.max_label_lines()));
+            let from = self.lane_center(&link.from.name);
+            let to = self.lane_center(&link.to.name);
+
+            if from == to {
+                let loop_width = lane_width * 0.3;
+                content.move_to(from, y + ARROW_SIZE);
+                content.line_to(from + loop_width, y + ARROW_SIZE);
+                content.line_to(from + loop_width, y);
+                content.line_to(from + ARROW_SIZE, y);
+                content.stroke();
+                content.move_to(from, y);
+                content.line_to(from + ARROW_SIZE, y + ARROW_SIZE / 2.0);
+                content.line_to(from + ARROW_SIZE, y - ARROW_SIZE / 2.0);
+                content.close_path();
+                content.fill_nonzero();
+
+                if let Some(label) = &label {
+                    self.lines(&mut content, from + 4.0, y + ARROW_SIZE + 4.0, lane_width / 2.0 - 4.0, label, false);
+                }
+                continue;
+            }
+
+            let direction = if to > from { 1.0 } else { -1.0 };
+            content.move_to(from, y);
+            content.line_to(to - direction * ARROW_SIZE, y);
+            content.stroke();
+            content.move_to(to, y);
+            content.line_to(to - direction * ARROW_SIZE, y + ARROW_SIZE / 2.0);
+            content.line_to(to - direction * ARROW_SIZE, y - ARROW_SIZE / 2.0);
+            content.close_path();
+            content.fill_nonzero();
+
+            if let Some(label) = &label {
+                self.lines(&mut content, (from + to) / 2.0, y + 4.0, (to - from).abs() - 8.0, label, true);
+            }
+        }
+
+        self.footer(&mut content, page, pages);
+        content.finish()
+    }
+}
+
+impl Document {
+    pub fn render(session: &data::SessionInner, options: &Options) -> Self {
+        let (width, height) = options.page_size();
+        let mut parties = session.parties.iter().map(|i| i.0.deref()).collect::<Vec<_>>();
+        parties.sort();
+        let writer = Writer {
+            session,
+            options,
+            width,
+            height,
+            parties,
+        };
+
+        let chunks = options.pages(&session.links);
+        let pages = 1 + chunks.len();
+
+        let catalog_id = Ref::new(1);
+        let page_tree_id = Ref::new(2);
+        let font_id = Ref::new(3);
+        let info_id = Ref::new(4);
+        let page_ids = (0..pages as i32).map(|i| Ref::new(5 + 2 * i)).collect::<Vec<_>>();
+
+        let mut pdf = Pdf::new();
+        pdf.catalog(catalog_id).pages(page_tree_id);
+        pdf.pages(page_tree_id).kids(page_ids.iter().copied()).count(pages as i32);
+        pdf.type1_font(font_id)
+            .base_font(Name(b"Helvetica"))
+            .encoding_predefined(Name(b"WinAnsiEncoding"));
+        let title = options.title.clone().unwrap_or_else(|| format!("Session {}", session.id));
+        pdf.document_info(info_id)
+            .title(TextStr(&title))
+            .creator(TextStr(concat!(std::env!("CARGO_PKG_NAME"), " v", std::env!("CARGO_PKG_VERSION"))));
+
+        let contents = std::iter::once(writer.title_page(pages))
+            .chain(chunks.iter()
+                .enumerate()
+                .map(|(i, links)| writer.links_page(links, i + 2, pages)));
+
+        for (page_id, content) in page_ids.iter().zip(contents) {
+            let content_id = Ref::new(page_id.get() + 1);
+            let mut page = pdf.page(*page_id);
+            page.media_box(Rect::new(0.0, 0.0, width, height));
+            page.parent(page_tree_id);
+            page.contents(content_id);
+            page.resources().fonts().pair(FONT, font_id);
+            page.finish();
+            pdf.stream(content_id, &content);
+        }
+
+        Document(pdf.finish())
+    }
+}
+
+impl From<&data::SessionInner> for Document {
+    fn from(session: &data::SessionInner) -> Self {
+        Self::render(session, &Options::default())
+    }
+}
+
+impl From<Document> for Vec<u8> {
+    fn from(doc: Document) -> Vec<u8> {
+        doc.0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::data::SessionInner;
+    use chrono::Utc;
+
+    fn count(haystack: &[u8], needle: &[u8]) -> usize {
+        haystack.windows(needle.len()).filter(|window| *window == needle).count()
+    }
+
+    fn page_count(pdf: &[u8]) -> usize {
+        count(pdf, b"/Type /Page") - count(pdf, b"/Type /Pages")
+    }
+
+    #[test]
+    fn generate() {
+        let mut session = SessionInner::new(42);
+        let now = Utc::now();
+        session.add_link(now, "a", "b", Some("Request"));
+        session.add_link(now, "b", "b", Some("Think"));
+        session.add_link(now, "b", "a", Some("Response"));
+        let pdf: Vec<u8> = Document::from(&session).into();
+        assert!(pdf.starts_with(b"%PDF-"));
+        assert_eq!(2, page_count(&pdf));
+        assert_eq!(1, count(&pdf, b"(Session 42) Tj"));
+        assert_eq!(1, count(&pdf, b"(Request) Tj"));
+    }
+
+    #[test]
+    fn paginate_with_repeated_header() {
+        let options = Options {
+            paper: Paper::Letter,
+            title: Some("Audit".to_string()),
+            ..Options::default()
+        };
+        let rows = options.rows_per_page();
+        let mut session = SessionInner::new(0);
+        let now = Utc::now();
+        for i in 0..(2 * rows + 1) {
+            session.add_link(now, "client", "server", Some(&format!("Request {i}")));
+        }
+
+        let pdf: Vec<u8> = Document::render(&session, &options).into();
+        assert_eq!(4, page_count(&pdf));
+        assert_eq!(3, count(&pdf, b"(client) Tj"));
+        assert_eq!(3, count(&pdf, b"(server) Tj"));
+        assert_eq!(1, count(&pdf, b"(Audit) Tj"));
+        assert_eq!(1, count(&pdf, b"page 4 of 4"));
+    }
+
+    #[test]
+    fn multi_line_text() {
+        let mut session = SessionInner::new(0);
+        let now = Utc::now();
+        session.add_labeled_party("web", Some("Web\nClient"));
+        session.add_link(now, "web", "api", Some("GET\n/items"));
+        session.add_link(now, "api", "api", Some("Load\nitems"));
+        let pdf: Vec<u8> = Document::from(&session).into();
+        for line in [&b"(Web) Tj"[..], b"(Client) Tj", b"(GET) Tj", b"(/items) Tj", b"(Load) Tj", b"(items) Tj"] {
+            assert_eq!(1, count(&pdf, line), "{}", String::from_utf8_lossy(line));
+        }
+        assert_eq!(0, count(&pdf, b"?"));
+    }
+
+    /// Baseline of the first text showing `text`.
+    fn text_y(pdf: &[u8], text: &str) -> f32 {
+        let content = String::from_utf8_lossy(pdf);
+        let end = content.find(&format!("({text}) Tj")).unwrap();
+        let td = content[..end].rfind(" Td").unwrap();
+        content[..td].split_whitespace().last().unwrap().parse().unwrap()
+    }
+
+    #[test]
+    fn grow_rows_with_label_lines() {
+        let mut session = SessionInner::new(0);
+        let now = Utc::now();
+        session.add_link(now, "a", "b", Some("one\ntwo\nthree"));
+        session.add_link(now, "b", "a", Some("next"));
+        let pdf: Vec<u8> = Document::from(&session).into();
+
+        let (_, height) = Options::default().page_size();
+        let rows_top = height - MARGIN - HEADER_HEIGHT - HEADER_GAP;
+        assert!(text_y(&pdf, "one") + FONT_SIZE <= rows_top);
+        assert!((text_y(&pdf, "one") - text_y(&pdf, "three") - 2.0 * LINE_HEIGHT).abs() < 0.01);
+        assert!((text_y(&pdf, "three") - text_y(&pdf, "next") - ROW_HEIGHT).abs() < 0.01);
+
+        let options = Options::default();
+        let label = vec!["line"; 200].join("\n");
+        let mut session = SessionInner::new(0);
+        session.add_link(now, "a", "b", Some(&label));
+        session.add_link(now, "a", "b", Some("next"));
+        let pdf: Vec<u8> = Document::render(&session, &options).into();
+        assert_eq!(3, page_count(&pdf));
+        assert_eq!(options.max_label_lines() - 1, count(&pdf, b"(line) Tj"));
+        assert_eq!(1, count(&pdf, b"(line...) Tj"));
+    }
+
+    #[test]
+    fn paper_from_str() {
+        assert_eq!(Ok(Paper::A4), "A4".parse());
+        assert_eq!(Ok(Paper::Letter), "letter".parse());
+        assert!("a3".parse::<Paper>().is_err());
+    }
+
+    #[test]
+    fn landscape_has_fewer_rows() {
+        let portrait = Options::default();
+        let landscape = Options { landscape: true, ..Options::default() };
+        assert!(landscape.rows_per_page() < portrait.rows_per_page());
+    }
+}
@@ -0,0 +1,80 @@
+use crate::data;
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize, Serialize)]
+#[cfg_attr(feature = "server", derive(Responder), response(status = 200))]
+pub struct Document(String);
+
+/// Quoted participant names can't contain quotes or line breaks.
+fn escape_name(name: &str) -> String {
+    name.replace('"', "'").replace("\r\n", " ").replace('\n', " ")
+}
+
+/// Message texts end at the line break, line breaks are written as `\n`.
+fn escape_label(label: &str) -> String {
+    label.replace('\\', "\\\\").replace("\r\n", "\\n").replace('\n', "\\n")
+}
+
+fn alias(party: &data::Party) -> String {
+    format!("P{}", party.id)
+}
+
+impl From<&data::SessionInner> for Document {
+    fn from(session: &data::SessionInner) -> Self {
+        let mut definition = String::new();
+        definition.push_str("@startuml\n");
+
+        let mut parties = session.parties.iter().map(|i| &i.0).collect::<Vec<_>>();
+        parties.sort();
+
+        for party in parties.iter() {
+            let name = party.label.as_deref().unwrap_or(party.name.as_str());
+            definition.push_str(&format!("participant \"{}\" as {}\n", escape_name(name), alias(party)));
+        }
+
+        for link in &session.links {
+            definition.push_str(&format!("{} -> {}", alias(&link.from), alias(&link.to)));
+            if let Some(label) = link.label.as_deref() {
+                definition.push_str(&format!(" : {}", escape_label(label)));
+            }
+            definition.push('\n');
+        }
+
+        definition.push_str("@enduml\n");
+        Document(definition)
+    }
+}
+
+impl From<Document> for String {
+    fn from(doc: Document) -> String {
+        doc.0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::data::SessionInner;
+    use chrono::Utc;
+
+    #[test]
+    fn generate() {
+        let mut session = SessionInner::new(0);
+        let now = Utc::now();
+        session.add_link(now, "web client", "api", Some("GET /items"));
+        session.add_link(now, "api", "\"db\"", Some("SELECT *\nFROM items"));
+        session.add_link(now, "api", "web client", None);
+        session.add_link(now, "api", "api", Some("C:\\temp"));
+
+        let doc: String = Document::from(&session).into();
+        assert_eq!(doc, "@startuml\n\
+            participant \"web client\" as P1\n\
+            participant \"api\" as P2\n\
+            participant \"'db'\" as P3\n\
+            P1 -> P2 : GET /items\n\
+            P2 -> P3 : SELECT *\\nFROM items\n\
+            P2 -> P1\n\
+            P2 -> P2 : C:\\\\temp\n\
+            @enduml\n");
+    }
+}
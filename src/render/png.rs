@@ -0,0 +1,181 @@
+use crate::{
+    data,
+    render::{
+        svg::{self, Document as SvgDocument},
+        theme::Theme,
+    },
+};
+use resvg::{tiny_skia, usvg};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    sync::{Arc, OnceLock},
+};
+
+const FONT: &[u8] = include_bytes!(concat!(std::env!("CARGO_MANIFEST_DIR"), "/fonts/DejaVuSans.ttf"));
+const FONT_FAMILY: &str = "DejaVu Sans";
+
+/// Resolution the SVG coordinates are defined in.
+pub const BASE_DPI: f32 = 96.0;
+/// Upper bound of the edge length of a rendered image in pixels.
+pub const MAX_SIDE: u32 = 32_000;
+/// Upper bound of the pixel count of a rendered image.
+pub const MAX_PIXELS: u64 = 40_000_000;
+
+#[derive(Deserialize, Serialize)]
+#[cfg_attr(feature = "server", derive(Responder), response(status = 200, content_type = "image/png"))]
+pub struct Document(Vec<u8>);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Options {
+    /// Zoom factor applied on top of the DPI.
+    pub scale: f32,
+    /// Target resolution, the SVG is defined with 96 DPI.
+    pub dpi: f32,
+    pub theme: Theme,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            scale: 1.0,
+            dpi: BASE_DPI,
+            theme: Theme::default(),
+        }
+    }
+}
+
+impl Options {
+    fn zoom(&self) -> f32 {
+        self.scale * self.dpi / BASE_DPI
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    InvalidOptions(String),
+    TooLarge { width: u32, height: u32 },
+    Svg(String),
+    Encode(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidOptions(cause) => write!(f, "Invalid options: {cause}"),
+            Error::TooLarge { width, height } => write!(
+                f, "Image of {width}x{height} pixels exceeds the limit, reduce scale or dpi"),
+            Error::Svg(cause) => write!(f, "Can't parse SVG: {cause}"),
+            Error::Encode(cause) => write!(f, "Can't encode PNG: {cause}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+fn fontdb() -> Arc<usvg::fontdb::Database> {
+    static FONTDB: OnceLock<Arc<usvg::fontdb::Database>> = OnceLock::new();
+    FONTDB.get_or_init(|| {
+        let mut db = usvg::fontdb::Database::new();
+        db.load_font_data(FONT.to_vec());
+        // Themes select generic families, all map to the bundled font
+        db.set_sans_serif_family(FONT_FAMILY);
+        db.set_serif_family(FONT_FAMILY);
+        db.set_monospace_family(FONT_FAMILY);
+        Arc::new(db)
+    }).clone()
+}
+
+impl Document {
+    /// Rasterizes SVG source with the bundled font.
+    pub fn from_svg(svg: &str, options: &Options) -> Result<Self, Error> {
+        let zoom = options.zoom();
+        if !zoom.is_finite() || zoom <= 0.0 {
+            return Err(Error::InvalidOptions("scale and dpi must be positive".to_string()));
+        }
+
+        let usvg_options = usvg::Options {
+            font_family: FONT_FAMILY.to_string(),
+            fontdb: fontdb(),
+            ..usvg::Options::default()
+        };
+        let tree = usvg::Tree::from_str(svg, &usvg_options)
+            .map_err(|e| Error::Svg(e.to_string()))?;
+
+        let size = tree.size();
+        let width = (size.width() * zoom).ceil() as u32;
+        let height = (size.height() * zoom).ceil() as u32;
+        if width > MAX_SIDE || height > MAX_SIDE || width as u64 * height as u64 > MAX_PIXELS {
+            return Err(Error::TooLarge { width, height });
+        }
+
+        let mut pixmap = tiny_skia::Pixmap::new(width.max(1), height.max(1))
+            .ok_or(Error::TooLarge { width, height })?;
+        pixmap.fill(tiny_skia::Color::WHITE);
+        resvg::render(&tree, tiny_skia::Transform::from_scale(zoom, zoom), &mut pixmap.as_mut());
+
+        pixmap.encode_png()
+            .map(Document)
+            .map_err(|e| Error::Encode(e.to_string()))
+    }
+
+    pub fn render(session: &data::SessionInner, options: &Options) -> Result<Self, Error> {
+        let svg: String = SvgDocument::render(session, &svg::Options { theme: options.theme, ..Default::default() }).into();
+        Self::from_svg(&svg, options)
+    }
+}
+
+impl From<Document> for Vec<u8> {
+    fn from(doc: Document) -> Vec<u8> {
+        doc.0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::data::SessionInner;
+    use chrono::Utc;
+
+    const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+    fn session() -> SessionInner {
+        let mut session = SessionInner::new(0);
+        let now = Utc::now();
+        session.add_link(now, "a", "b", Some("Request"));
+        session.add_link(now, "b", "a", Some("Response"));
+        session
+    }
+
+    fn dimensions(png: &[u8]) -> (u32, u32) {
+        let width = u32::from_be_bytes(png[16..20].try_into().unwrap());
+        let height = u32::from_be_bytes(png[20..24].try_into().unwrap());
+        (width, height)
+    }
+
+    #[test]
+    fn generate() {
+        let png: Vec<u8> = Document::render(&session(), &Options::default()).unwrap().into();
+        assert!(png.starts_with(PNG_SIGNATURE));
+        assert_eq!((460, 340), dimensions(&png));
+    }
+
+    #[test]
+    fn scale_and_dpi() {
+        let options = Options { scale: 0.5, dpi: 192.0, ..Options::default() };
+        let png: Vec<u8> = Document::render(&session(), &options).unwrap().into();
+        assert_eq!((460, 340), dimensions(&png));
+
+        let options = Options { scale: 2.0, dpi: BASE_DPI, ..Options::default() };
+        let png: Vec<u8> = Document::render(&session(), &options).unwrap().into();
+        assert_eq!((920, 680), dimensions(&png));
+    }
+
+    #[test]
+    fn reject_invalid_options() {
+        let options = Options { scale: 0.0, dpi: BASE_DPI, ..Options::default() };
+        assert!(matches!(Document::render(&session(), &options), Err(Error::InvalidOptions(_))));
+        let options = Options { scale: 1000.0, dpi: BASE_DPI, ..Options::default() };
+        assert!(matches!(Document::render(&session(), &options), Err(Error::TooLarge { .. })));
+    }
+}
@@ -0,0 +1,342 @@
+use crate::data::SessionInner;
+use rocket::http::{Accept, MediaType};
+use std::{
+    collections::HashMap,
+    fmt,
+    str::FromStr,
+};
+
+/// Format specific options, taken from the query of a render request.
+pub type Options = HashMap<String, String>;
+
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    /// The options can't be used, the request should be fixed.
+    InvalidOption(String),
+    /// The session can't be rendered with valid options.
+    Render(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidOption(message) | Error::Render(message) => f.write_str(message),
+        }
+    }
+}
+
+/// Parses the option `name`, which is `None` when it's missing.
+pub fn option<T: FromStr>(options: &Options, name: &str) -> Result<Option<T>, Error> {
+    options.get(name)
+        .map(|value| value.parse()
+            .map_err(|_| Error::InvalidOption(format!("Invalid value `{value}` for option `{name}`"))))
+        .transpose()
+}
+
+/// Parses the `theme` option, the default theme when it's missing.
+#[cfg(feature = "svg")]
+pub fn theme(options: &Options) -> Result<super::theme::Theme, Error> {
+    options.get("theme")
+        .map(|name| name.parse().map_err(Error::InvalidOption))
+        .unwrap_or(Ok(Default::default()))
+}
+
+/// An output format which can be registered in a [`Registry`].
+pub trait Renderer: Send + Sync {
+    /// MIME type of the rendered document, like `image/svg+xml`.
+    fn content_type(&self) -> &str;
+
+    fn render(&self, session: &SessionInner, options: &Options) -> Result<Vec<u8>, Error>;
+}
+
+#[cfg(feature = "svg")]
+impl TryFrom<&Options> for super::svg::Options {
+    type Error = Error;
+
+    fn try_from(options: &Options) -> Result<Self, Error> {
+        Ok(super::svg::Options {
+            theme: theme(options)?,
+            collapse_repeats: option(options, "collapse")?.unwrap_or(false),
+        })
+    }
+}
+
+#[cfg(feature = "png")]
+impl TryFrom<&Options> for super::png::Options {
+    type Error = Error;
+
+    fn try_from(options: &Options) -> Result<Self, Error> {
+        let defaults = super::png::Options::default();
+        Ok(super::png::Options {
+            scale: option(options, "scale")?.unwrap_or(defaults.scale),
+            dpi: option(options, "dpi")?.unwrap_or(defaults.dpi),
+            theme: theme(options)?,
+        })
+    }
+}
+
+#[cfg(feature = "pdf")]
+impl TryFrom<&Options> for super::pdf::Options {
+    type Error = Error;
+
+    fn try_from(options: &Options) -> Result<Self, Error> {
+        Ok(super::pdf::Options {
+            paper: options.get("paper")
+                .map(|paper| paper.parse())
+                .transpose()
+                .map_err(Error::InvalidOption)?
+                .unwrap_or_default(),
+            landscape: option(options, "landscape")?.unwrap_or(false),
+            title: options.get("title").cloned(),
+        })
+    }
+}
+
+#[cfg(feature = "html")]
+impl TryFrom<&Options> for super::html::Options {
+    type Error = Error;
+
+    fn try_from(options: &Options) -> Result<Self, Error> {
+        Ok(super::html::Options {
+            title: options.get("title").cloned(),
+            theme: theme(options)?,
+        })
+    }
+}
+
+#[cfg(feature = "text")]
+impl TryFrom<&Options> for super::text::Options {
+    type Error = Error;
+
+    fn try_from(options: &Options) -> Result<Self, Error> {
+        let defaults = super::text::Options::default();
+        Ok(super::text::Options {
+            width: option(options, "width")?.unwrap_or(defaults.width),
+            ascii: option(options, "ascii")?.unwrap_or(defaults.ascii),
+        })
+    }
+}
+
+#[cfg(feature = "mermaid")]
+impl TryFrom<&Options> for super::mermaid::Options {
+    type Error = Error;
+
+    fn try_from(options: &Options) -> Result<Self, Error> {
+        Ok(super::mermaid::Options { collapse_repeats: option(options, "collapse")?.unwrap_or(false) })
+    }
+}
+
+/// Renderer for the formats of this crate.
+#[cfg(any(
+    feature = "dot", feature = "html", feature = "mermaid", feature = "pdf",
+    feature = "plantuml", feature = "png", feature = "svg", feature = "text",
+))]
+struct Builtin {
+    content_type: &'static str,
+    render: fn(&SessionInner, &Options) -> Result<Vec<u8>, Error>,
+}
+
+#[cfg(any(
+    feature = "dot", feature = "html", feature = "mermaid", feature = "pdf",
+    feature = "plantuml", feature = "png", feature = "svg", feature = "text",
+))]
+impl Renderer for Builtin {
+    fn content_type(&self) -> &str {
+        self.content_type
+    }
+
+    fn render(&self, session: &SessionInner, options: &Options) -> Result<Vec<u8>, Error> {
+        (self.render)(session, options)
+    }
+}
+
+/// Output formats by name, in order of registration.
+///
+/// The order decides between formats with the same content type during
+/// content negotiation.
+pub struct Registry {
+    renderers: Vec<(String, Box<dyn Renderer>)>,
+}
+
+impl Registry {
+    /// A registry without any formats.
+    pub fn new() -> Self {
+        Registry { renderers: vec![] }
+    }
+
+    /// Adds a format, an existing format with the same name is replaced.
+    pub fn register(&mut self, name: &str, renderer: impl Renderer + 'static) -> &mut Self {
+        let renderer: Box<dyn Renderer> = Box::new(renderer);
+        match self.renderers.iter_mut().find(|(existing, _)| existing == name) {
+            Some((_, existing)) => *existing = renderer,
+            None => self.renderers.push((name.to_string(), renderer)),
+        }
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn Renderer> {
+        self.renderers.iter()
+            .find(|(existing, _)| existing == name)
+            .map(|(_, renderer)| renderer.as_ref())
+    }
+
+    pub fn formats(&self) -> impl Iterator<Item = &str> {
+        self.renderers.iter().map(|(name, _)| name.as_str())
+    }
+
+    /// Finds the format preferred by `accept`, the first format for any
+    /// type, `None` when none of the registered formats is acceptable.
+    pub fn negotiate(&self, accept: &Accept) -> Option<&str> {
+        let mut media_types = accept.iter().collect::<Vec<_>>();
+        media_types.sort_by(|a, b| b.weight_or(1.0).total_cmp(&a.weight_or(1.0)));
+        media_types.into_iter().find_map(|media_type| self.format_of(media_type.media_type()))
+    }
+
+    /// The first format of a type matching `media_type`, which may contain
+    /// wildcards.
+    pub fn format_of(&self, media_type: &MediaType) -> Option<&str> {
+        let (name, _) = self.renderers.iter().find(|(_, renderer)| {
+            let Some(content_type) = MediaType::parse_flexible(renderer.content_type()) else { return false };
+            (media_type.top() == "*" || content_type.top() == media_type.top())
+                && (media_type.sub() == "*" || content_type.sub() == media_type.sub())
+        })?;
+        Some(name)
+    }
+}
+
+impl Default for Registry {
+    /// A registry with all enabled formats of this crate.
+    #[allow(unused_mut)]
+    fn default() -> Self {
+        let mut registry = Registry::new();
+
+        #[cfg(feature = "svg")]
+        registry.register("svg", Builtin {
+            content_type: "image/svg+xml",
+            render: |session, options| {
+                use super::svg;
+                Ok(String::from(svg::Document::render(session, &options.try_into()?)).into_bytes())
+            },
+        });
+        #[cfg(feature = "png")]
+        registry.register("png", Builtin {
+            content_type: "image/png",
+            render: |session, options| {
+                use super::png;
+                png::Document::render(session, &options.try_into()?)
+                    .map(Into::into)
+                    .map_err(|e| match e {
+                        png::Error::InvalidOptions(_) | png::Error::TooLarge { .. } => Error::InvalidOption(e.to_string()),
+                        png::Error::Svg(_) | png::Error::Encode(_) => Error::Render(e.to_string()),
+                    })
+            },
+        });
+        #[cfg(feature = "pdf")]
+        registry.register("pdf", Builtin {
+            content_type: "application/pdf",
+            render: |session, options| {
+                use super::pdf;
+                Ok(pdf::Document::render(session, &options.try_into()?).into())
+            },
+        });
+        #[cfg(feature = "html")]
+        registry.register("html", Builtin {
+            content_type: "text/html; charset=utf-8",
+            render: |session, options| {
+                use super::html;
+                Ok(String::from(html::Document::render(session, &options.try_into()?)).into_bytes())
+            },
+        });
+        #[cfg(feature = "text")]
+        registry.register("text", Builtin {
+            content_type: "text/plain; charset=utf-8",
+            render: |session, options| {
+                use super::text;
+                Ok(String::from(text::Document::render(session, &options.try_into()?)).into_bytes())
+            },
+        });
+        #[cfg(feature = "mermaid")]
+        registry.register("mermaid", Builtin {
+            content_type: "text/plain; charset=utf-8",
+            render: |session, options| {
+                use super::mermaid;
+                Ok(String::from(mermaid::Document::render(session, &options.try_into()?)).into_bytes())
+            },
+        });
+        #[cfg(feature = "plantuml")]
+        registry.register("plantuml", Builtin {
+            content_type: "text/plain; charset=utf-8",
+            render: |session, _| Ok(String::from(super::plantuml::Document::from(session)).into_bytes()),
+        });
+        #[cfg(feature = "dot")]
+        registry.register("dot", Builtin {
+            content_type: "text/vnd.graphviz",
+            render: |session, _| Ok(String::from(super::dot::Document::from(session)).into_bytes()),
+        });
+
+        registry
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct Csv;
+
+    impl Renderer for Csv {
+        fn content_type(&self) -> &str {
+            "text/csv"
+        }
+
+        fn render(&self, session: &SessionInner, _: &Options) -> Result<Vec<u8>, Error> {
+            Ok(session.links.iter()
+                .map(|link| format!("{},{},{}\n", link.id, link.from.name, link.to.name))
+                .collect::<String>()
+                .into_bytes())
+        }
+    }
+
+    fn negotiate<'a>(registry: &'a Registry, accept: &str) -> Option<&'a str> {
+        registry.negotiate(&accept.parse().unwrap())
+    }
+
+    #[test]
+    fn register_custom_format() {
+        let mut registry = Registry::new();
+        registry.register("csv", Csv);
+        assert_eq!(vec!["csv"], registry.formats().collect::<Vec<_>>());
+
+        let mut session = SessionInner::new(0);
+        session.add_link(chrono::Utc::now(), "a", "b", None);
+        let csv = registry.get("csv").unwrap().render(&session, &Options::new()).unwrap();
+        assert_eq!(b"1,a,b\n".to_vec(), csv);
+        assert!(registry.get("svg").is_none());
+    }
+
+    #[test]
+    fn negotiate_by_accept() {
+        let mut registry = Registry::new();
+        registry.register("csv", Csv);
+        assert_eq!(Some("csv"), negotiate(&registry, "text/csv"));
+        assert_eq!(Some("csv"), negotiate(&registry, "application/json;q=0.5, text/*"));
+        assert_eq!(Some("csv"), negotiate(&registry, "application/json, text/csv;q=0.2"));
+        assert_eq!(Some("csv"), negotiate(&registry, "*/*"));
+        assert_eq!(None, negotiate(&registry, "image/png, application/json"));
+    }
+
+    #[test]
+    fn parse_options() {
+        let options = Options::from([("width".to_string(), "x".to_string())]);
+        assert_eq!(Ok(None), option::<usize>(&options, "height"));
+        assert_eq!(
+            Err(Error::InvalidOption("Invalid value `x` for option `width`".to_string())),
+            option::<usize>(&options, "width"));
+    }
+
+    #[cfg(feature = "text")]
+    #[test]
+    fn text_preferred_over_diagram_code() {
+        assert_eq!(Some("text"), negotiate(&Registry::default(), "text/plain"));
+    }
+}
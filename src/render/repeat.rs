@@ -0,0 +1,95 @@
+use crate::data::Link;
+use std::sync::Arc;
+
+/// Longest sequence of links searched for repetitions.
+pub const MAX_PATTERN_LEN: usize = 8;
+
+/// Links repeated one after the other, drawn once inside a loop frame.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Repeat {
+    /// Index of the first link.
+    pub start: usize,
+    /// Number of links in one repetition.
+    pub len: usize,
+    /// Number of repetitions, at least 2.
+    pub count: usize,
+}
+
+impl Repeat {
+    /// Index after the last repetition.
+    pub fn end(&self) -> usize {
+        self.start + self.len * self.count
+    }
+}
+
+/// Links are repeated with the same parties and label, their timestamps
+/// differ.
+fn same(a: &Link, b: &Link) -> bool {
+    a.from.name == b.from.name && a.to.name == b.to.name && a.label == b.label
+}
+
+/// Number of times the `len` links from `start` repeat one after the other.
+fn count(links: &[Arc<Link>], start: usize, len: usize) -> usize {
+    let mut count = 1;
+    while (0..len).all(|i| links.get(start + count * len + i).is_some_and(|link| same(link, &links[start + i]))) {
+        count += 1;
+    }
+    count
+}
+
+/// Finds repeated sequences from the start, preferring the sequence which
+/// covers most links, and the shortest of those.
+pub fn find(links: &[Arc<Link>]) -> Vec<Repeat> {
+    let mut repeats = vec![];
+    let mut start = 0;
+    while start < links.len() {
+        let best = (1..=MAX_PATTERN_LEN)
+            .map(|len| Repeat { start, len, count: count(links, start, len) })
+            .filter(|repeat| repeat.count > 1)
+            .max_by_key(|repeat| (repeat.len * repeat.count, usize::MAX - repeat.len));
+        match best {
+            Some(repeat) => {
+                start = repeat.end();
+                repeats.push(repeat);
+            }
+            None => start += 1,
+        }
+    }
+    repeats
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::data::SessionInner;
+    use chrono::Utc;
+
+    #[test]
+    fn find_polling() {
+        let mut session = SessionInner::new(0);
+        let now = Utc::now();
+        session.add_link(now, "web", "api", Some("login"));
+        for _ in 0..50 {
+            session.add_link(now, "web", "api", Some("GET /status"));
+            session.add_link(now, "api", "web", Some("pending"));
+        }
+        session.add_link(now, "web", "api", Some("GET /status"));
+        session.add_link(now, "api", "web", Some("done"));
+        session.add_link(now, "web", "web", None);
+        session.add_link(now, "web", "web", None);
+
+        assert_eq!(vec![
+            Repeat { start: 1, len: 2, count: 50 },
+            Repeat { start: 103, len: 1, count: 2 },
+        ], find(&session.links));
+    }
+
+    #[test]
+    fn nothing_repeated() {
+        let mut session = SessionInner::new(0);
+        session.add_link(Utc::now(), "a", "b", Some("1"));
+        session.add_link(Utc::now(), "a", "b", Some("2"));
+        session.add_link(Utc::now(), "b", "a", Some("1"));
+        assert!(find(&session.links).is_empty());
+    }
+}
@@ -1,144 +1,380 @@
 use crate::data;
+use chrono::SecondsFormat;
 use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use super::theme::Theme;
+use super::layout::{Change, Frame, Lane, Layout, Point, Row, TextBlock, LINE_HEIGHT};
 use svg::{
     self,
     Document as SvgDocument,
-    node::element::Rectangle,
-};
-
-use std::{
-    collections::HashMap,
-    ops::Deref,
+    node::element::{Description as Desc, Group, Line, Polygon, Polyline, Rectangle, Style, TSpan, Text, Title},
 };
 
-#[derive(Deserialize, Serialize, Responder)]
-#[response(status = 200, content_type = "image/svg+xml")]
+#[derive(Deserialize, Serialize)]
+#[cfg_attr(feature = "server", derive(Responder), response(status = 200, content_type = "image/svg+xml"))]
 pub struct Document(String);
 
-impl From<&data::SessionInner> for Document {
-    fn from(session: &data::SessionInner) -> Self {
-        let mut doc = SvgDocument::new();
-        let mut party_pos_map = HashMap::new();
-
-        const LANE_WIDTH: i32 = 200;
-        const INTER_LANE_SPACE: i32 = LANE_WIDTH / 10;
-        const STEP_HEIGHT: i32 = 100;
-        const SPACER_SIZE: i32 = LANE_WIDTH/10;
-        const PARTY_TEXT_OFFSET: i32 = 50;
-        const LINKS_START_HEIGHT: i32 = 100;
-        const LINK_TEXT_OFFSET: i32 = 20;
-        const LINK_LINE_OFFSET: i32 = 70;
-        const ARROW_SIZE: i32 = SPACER_SIZE / 2;
-
-        let mut x: i32 = SPACER_SIZE;
-        let mut y: i32 = SPACER_SIZE;
-        let max_lane_y: i32 = LINKS_START_HEIGHT + STEP_HEIGHT * session.links.len() as i32;
-
-        let mut parties = session.parties.iter().map(|i| &i.0).collect::<Vec<_>>();
-        parties.sort();
-
-        for party in parties.iter() {
-            doc = doc.add(Rectangle::new()
-                .set("x", x)
-                .set("y", y)
-                .set("width", LANE_WIDTH)
-                .set("height", max_lane_y + SPACER_SIZE)
-                .set("style", "fill:white;stroke:lightgray")
-                );
-            doc = doc.add(svg::node::element::Text::new()
-                .set("x", x + LANE_WIDTH/2)
-                .set("y", PARTY_TEXT_OFFSET)
-                .set("fill", "black")
-                .set("text-anchor", "middle")
-                .add(svg::node::Text::new(party.name.deref()))
-                );
-            doc = doc.add(
-                svg::node::element::Line::new()
-                    .set("x1", x + LANE_WIDTH/2)
-                    .set("y1", y + PARTY_TEXT_OFFSET)
-                    .set("x2", x + LANE_WIDTH/2)
-                    .set("y2", y + max_lane_y - ARROW_SIZE)
-                    .set("style", "stroke:rgb(0,0,0);stroke-width:2")
-                );
-            doc = doc.add(
-                svg::node::element::Polygon::new()
-                    .set("points", format!(
-                            "{},{} {},{} {},{}",
-                            x + LANE_WIDTH/2, y + max_lane_y,
-                            x + LANE_WIDTH/2 - ARROW_SIZE/2, y + max_lane_y - ARROW_SIZE,
-                            x + LANE_WIDTH/2 + ARROW_SIZE/2, y + max_lane_y - ARROW_SIZE,
-                            ))
-                    .set("style", "fill:black;stroke-width:0")
-                );
+/// The svg crate writes text and attributes verbatim.
+pub(crate) fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Text with one `tspan` per line.
+fn text(block: &TextBlock) -> Text {
+    let mut text = Text::new()
+        .set("x", block.x)
+        .set("y", block.y)
+        .set("text-anchor", block.anchor);
+    for (index, line) in block.lines.iter().enumerate() {
+        text = text.add(TSpan::new()
+            .set("x", block.x)
+            .set("y", block.y + index as f32 * LINE_HEIGHT)
+            .add(svg::node::Text::new(escape(line))));
+    }
+    text
+}
+
+fn line(points: &[Point]) -> Line {
+    Line::new()
+        .set("x1", points[0].0)
+        .set("y1", points[0].1)
+        .set("x2", points[1].0)
+        .set("y2", points[1].1)
+}
+
+fn points(points: &[Point]) -> String {
+    points.iter()
+        .map(|(x, y)| format!("{x},{y}"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn display_name(lane: &Lane) -> String {
+    lane.title.lines.join(" ")
+}
+
+/// Message of a link for screen readers.
+fn message(layout: &Layout, row: &Row) -> String {
+    let from = display_name(&layout.lanes[row.from]);
+    let to = display_name(&layout.lanes[row.to]);
+    let mut message = format!("Message {} from {from} to {to}", row.link.id);
+    if !row.lines().is_empty() {
+        message.push_str(": ");
+        message.push_str(&row.lines().join(" "));
+    }
+    message
+}
+
+/// Text alternative with the parties and all messages in reading order,
+/// the messages already escaped.
+fn description(layout: &Layout, messages: &[String]) -> String {
+    let mut description = escape(&format!(
+        "{} parties: {}.\n{} messages in order:",
+        layout.lanes.len(),
+        layout.lanes.iter().map(display_name).collect::<Vec<_>>().join(", "),
+        layout.rows.len(),
+    ));
+    for message in messages {
+        description.push('\n');
+        description.push_str(message);
+    }
+    description
+}
+
+fn lane_group(lane: &Lane) -> Group {
+    Group::new()
+        .set("id", format!("party-{}", lane.id))
+        .set("class", format!("party party-{}", lane.id))
+        .set("role", "graphics-object")
+        .set("aria-label", escape(&format!("Party {}", display_name(lane))))
+        .set("data-party-id", lane.id)
+        .set("data-party", escape(&lane.name))
+        .add(Rectangle::new()
+            .set("class", "lane")
+            .set("x", lane.left())
+            .set("y", lane.top)
+            .set("width", lane.width)
+            .set("height", lane.height)
+            )
+        .add(text(&lane.title).set("class", "party-name"))
+        .add(line(&lane.lifeline).set("class", "lifeline"))
+        .add(Polygon::new()
+            .set("class", "lifeline-end")
+            .set("points", points(&lane.lifeline_end))
+            )
+}
 
-            party_pos_map.insert(party.name.deref(), x + LANE_WIDTH/2).unwrap_or(x);
-            x += LANE_WIDTH + INTER_LANE_SPACE;
+fn frame_group(index: usize, frame: &Frame) -> Group {
+    let messages = if frame.rows == 1 { "1 message".to_string() } else { format!("{} messages", frame.rows) };
+    Group::new()
+        .set("id", format!("frame-{}", index + 1))
+        .set("class", "loop")
+        .set("role", "graphics-object")
+        .set("aria-label", format!("Loop of {messages} repeated {} times", frame.count))
+        .add(Rectangle::new()
+            .set("class", "frame")
+            .set("x", frame.x)
+            .set("y", frame.y)
+            .set("width", frame.width)
+            .set("height", frame.height)
+            )
+        .add(Polygon::new()
+            .set("class", "frame-tab")
+            .set("points", points(&frame.tab))
+            )
+        .add(text(&frame.title).set("class", "frame-title"))
+}
+
+/// Group of a link, `message` is its escaped message for screen readers.
+fn row_group(row: &Row, message: &str) -> Group {
+    let link = &row.link;
+    let mut group = Group::new()
+        .set("id", format!("link-{}", link.id))
+        .set("class", format!("link link-{}", link.id))
+        .set("role", "graphics-symbol")
+        .set("aria-label", message)
+        .set("data-link-id", link.id)
+        .set("data-from", escape(&link.from.name))
+        .set("data-to", escape(&link.to.name))
+        .set("data-timestamp", link.timestamp.to_rfc3339_opts(SecondsFormat::Millis, true));
+
+    if let Some(label) = &row.label {
+        group = group.add(text(label).set("class", "label"));
+    }
+    if let Some(gap) = &row.gap {
+        group = group.add(line(gap)
+            .set("class", "gap")
+            .set("aria-hidden", "true")
+            );
+    }
+    group = if row.line.len() == 2 {
+        group.add(line(&row.line).set("class", "line"))
+    } else {
+        group.add(Polyline::new()
+            .set("class", "line")
+            .set("points", points(&row.line))
+            )
+    };
+    group.add(Polygon::new()
+        .set("class", "arrow")
+        .set("points", points(&row.arrow))
+        )
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Options {
+    pub theme: Theme,
+    /// Draws links repeated one after the other once inside a loop frame.
+    pub collapse_repeats: bool,
+}
+
+/// A rendered session, kept to only render the links appended later.
+pub struct Rendered {
+    session_id: u64,
+    links_highest_id: u64,
+    parties: usize,
+    colors: data::Colors,
+    options: Options,
+    layout: Layout,
+    /// Escaped messages of the links for screen readers.
+    messages: Vec<String>,
+    /// Groups of the links.
+    rows: Vec<String>,
+}
+
+impl Rendered {
+    pub fn new(session: &data::SessionInner, options: &Options) -> Self {
+        let mut rendered = Rendered {
+            session_id: session.id,
+            links_highest_id: session.links_highest_id,
+            parties: session.parties.len(),
+            colors: session.colors.clone(),
+            options: *options,
+            layout: if options.collapse_repeats { Layout::collapsed(session) } else { Layout::from(session) },
+            messages: vec![],
+            rows: vec![],
+        };
+        rendered.render_rows(0);
+        rendered
+    }
+
+    /// Renders the links appended to `session` since the last render. It's
+    /// `false` when anything else changed and the session has to be rendered
+    /// from scratch, always with collapsed repeats.
+    pub fn update(&mut self, session: &data::SessionInner) -> bool {
+        if self.options.collapse_repeats
+            || session.id != self.session_id
+            || session.links_highest_id < self.links_highest_id
+            || session.parties.len() != self.parties
+            || session.colors != self.colors
+        {
+            return false;
         }
 
-        y = LINKS_START_HEIGHT;
-
-        for link in &session.links {
-            let from: i32 = *party_pos_map.get(link.from.name.deref()).unwrap();
-            let to: i32 = *party_pos_map.get(link.to.name.deref()).unwrap();
-            let direction = if to > from { 1 } else { -1 };
-
-            if let Some(label) = link.label.deref() {
-                doc = doc.add(
-                    svg::node::element::Text::new()
-                    .set("x", to - direction * LANE_WIDTH/2)
-                    .set("y", y + LINK_TEXT_OFFSET)
-                    .set("fill", "black")
-                    .set("text-anchor", "middle")
-                    .set("stroke", "white")
-                    .set("stroke-width", "0.5em")
-                    .set("paint-order", "stroke")
-                    .set("stroke-linejoin", "round")
-                    .add(svg::node::Text::new(label.deref()))
-                    );
-            }
+        let first = self.layout.rows.len();
+        let appended = session.links.partition_point(|link| link.id <= self.links_highest_id);
+        match self.layout.append(&session.links[appended..]) {
+            Change::RowsAppended => self.render_rows(first),
+            Change::LanesMoved => self.render_rows(0),
+            Change::NewParties => return false,
+        }
+        self.links_highest_id = session.links_highest_id;
+        true
+    }
 
-            doc = doc.add(
-                svg::node::element::Line::new()
-                    .set("x1", from + direction * LANE_WIDTH/2)
-                    .set("y1", y + LINK_LINE_OFFSET)
-                    .set("x2", to - direction * (ARROW_SIZE + LANE_WIDTH/2))
-                    .set("y2", y + LINK_LINE_OFFSET)
-                    .set("style", "stroke:rgb(255,255,255);stroke-width:8")
-                );
+    /// Renders the rows from `first` on again, and the messages of new rows.
+    fn render_rows(&mut self, first: usize) {
+        for row in &self.layout.rows[self.messages.len()..] {
+            self.messages.push(escape(&message(&self.layout, row)));
+        }
+        self.rows.truncate(first);
+        for (row, message) in self.layout.rows[first..].iter().zip(&self.messages[first..]) {
+            self.rows.push(row_group(row, message).to_string());
+        }
+    }
 
-            doc = doc.add(
-                svg::node::element::Line::new()
-                    .set("x1", from)
-                    .set("y1", y + LINK_LINE_OFFSET)
-                    .set("x2", to - direction * ARROW_SIZE)
-                    .set("y2", y + LINK_LINE_OFFSET)
-                    .set("style", "stroke:rgb(0,0,0);stroke-width:2")
-                );
+    pub fn document(&self) -> Document {
+        let layout = &self.layout;
+        let theme = &self.options.theme;
+        let css = theme.css() + &theme.colors_css(&self.colors, |name| layout.lanes.iter()
+            .find(|lane| lane.name == name)
+            .map(|lane| lane.id));
 
-            doc = doc.add(
-                svg::node::element::Polygon::new()
-                    .set("points", format!(
-                            "{},{} {},{} {},{}",
-                            to, y + LINK_LINE_OFFSET,
-                            to - direction * ARROW_SIZE, y - ARROW_SIZE/2 + LINK_LINE_OFFSET,
-                            to - direction * ARROW_SIZE, y + ARROW_SIZE/2 + LINK_LINE_OFFSET,
-                            ))
-                    .set("style", "fill:black;stroke-width:0")
+        // The ids are unique per session, as diagrams may be inlined together
+        let title_id = format!("diagram-{}-title", self.session_id);
+        let desc_id = format!("diagram-{}-desc", self.session_id);
+        let mut doc = SvgDocument::new()
+            .set("role", "graphics-document document")
+            .set("aria-labelledby", title_id.as_str())
+            .set("aria-describedby", desc_id.as_str())
+            .add(Title::new()
+                .set("id", title_id)
+                .add(svg::node::Text::new(format!("Sequence diagram of session {}", self.session_id))))
+            .add(Desc::new()
+                .set("id", desc_id)
+                .add(svg::node::Text::new(description(layout, &self.messages))))
+            .add(Style::new(css))
+            .add(Rectangle::new()
+                .set("class", "background")
+                .set("aria-hidden", "true")
+                .set("width", "100%")
+                .set("height", "100%")
                 );
+        for lane in &layout.lanes {
+            doc = doc.add(lane_group(lane));
+        }
+        for (index, frame) in layout.frames.iter().enumerate() {
+            doc = doc.add(frame_group(index, frame));
+        }
+        doc = doc.set("viewBox", (0.0, 0.0, layout.width, layout.height));
+
+        // The groups of the links are already rendered, they go before the end tag
+        let head = doc.to_string();
+        let head = head.strip_suffix("\n</svg>").unwrap_or(&head);
+        let mut svg = String::with_capacity(head.len() + self.rows.iter().map(|row| row.len() + 1).sum::<usize>() + 7);
+        svg.push_str(head);
+        for row in &self.rows {
+            svg.push('\n');
+            svg.push_str(row);
+        }
+        svg.push_str("\n</svg>");
+        Document(svg)
+    }
+}
+
+/// A session rendered by one request after the other.
+type Entry = Arc<Mutex<Option<Rendered>>>;
+
+/// Entries kept by [`Cache::default`].
+pub const CACHE_ENTRIES: usize = 64;
+
+#[derive(Default)]
+struct Entries {
+    /// Entries with the use count of their last use.
+    entries: HashMap<(u64, &'static str, bool), (u64, Entry)>,
+    uses: u64,
+}
 
-            y += STEP_HEIGHT;
+/// Rendered sessions by session id, theme and collapsing of repeats, only the links appended since
+/// the last request are rendered. The least recently used entries are dropped beyond the maximum
+/// number of entries.
+pub struct Cache {
+    entries: Mutex<Entries>,
+    max_entries: usize,
+}
+
+impl Default for Cache {
+    fn default() -> Self {
+        Cache::new(CACHE_ENTRIES)
+    }
+}
+
+impl Cache {
+    pub fn new(max_entries: usize) -> Self {
+        Cache {
+            entries: Mutex::default(),
+            max_entries,
         }
+    }
+
+    /// Renders `session`, which should be a snapshot so the lock of the
+    /// session isn't held while rendering.
+    pub fn render(&self, session: &data::SessionInner, options: &Options) -> Document {
+        let entry = {
+            let mut entries = self.entries.lock().unwrap();
+            entries.uses += 1;
+            let uses = entries.uses;
+            let key = (session.id, options.theme.name, options.collapse_repeats);
+            if !entries.entries.contains_key(&key) && entries.entries.len() >= self.max_entries {
+                let oldest = entries.entries.iter().min_by_key(|(_, (used, _))| *used).map(|(key, _)| *key);
+                if let Some(oldest) = oldest {
+                    entries.entries.remove(&oldest);
+                }
+            }
+            let (used, entry) = entries.entries.entry(key).or_default();
+            *used = uses;
+            Arc::clone(entry)
+        };
+        let mut entry = entry.lock().unwrap();
 
-        doc = doc.set("viewBox", (0, 0, x, max_lane_y + 2*SPACER_SIZE));
-        let mut buf = Vec::<u8>::new();
-        svg::write(&mut buf, &doc).unwrap();
-        Document(String::from_utf8(buf).unwrap())
+        if let Some(rendered) = entry.as_mut() {
+            if rendered.links_highest_id > session.links_highest_id {
+                // An older snapshot than the cached one
+                return Document::render(session, options);
+            }
+            if rendered.update(session) {
+                return rendered.document();
+            }
+        }
+        let rendered = Rendered::new(session, options);
+        let doc = rendered.document();
+        *entry = Some(rendered);
+        doc
     }
 }
 
-impl Into<String> for Document {
-    fn into(self) -> String {
-        self.0
+impl Document {
+    pub fn render(session: &data::SessionInner, options: &Options) -> Self {
+        Rendered::new(session, options).document()
+    }
+}
+
+impl From<&data::SessionInner> for Document {
+    fn from(session: &data::SessionInner) -> Self {
+        Self::render(session, &Options::default())
+    }
+}
+
+impl From<Document> for String {
+    fn from(doc: Document) -> String {
+        doc.0
     }
 }
 
@@ -146,8 +382,9 @@ impl Into<String> for Document {
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::data::{Sessions};
+    use crate::data::{SessionInner, Sessions};
     use chrono::Utc;
+    use std::ops::Deref;
 
     #[test]
     fn generate() {
@@ -163,5 +400,144 @@ mod test {
         assert!(svg_text.0.contains("<polygon "));
         assert!(svg_text.0.contains("<rect "));
         assert!(svg_text.0.contains("</svg>"));
+        assert!(svg_text.0.contains(r#"data-from="a" data-link-id="1" "#));
+    }
+
+    #[test]
+    fn escape_markup() {
+        let session = Sessions::new().new_session();
+        let mut session = session.write().unwrap();
+        session.add_link(Utc::now(), "<a>", "\"b\"", Some("x < y & z"));
+        let svg_text: String = Document::from(session.deref()).into();
+        assert!(svg_text.contains(">\n&lt;a&gt;\n</tspan>"));
+        assert!(svg_text.contains(r#"data-to="&quot;b&quot;""#));
+        assert!(svg_text.contains("x &lt; y &amp; z"));
+    }
+
+    #[test]
+    fn wrap_long_labels() {
+        let session = Sessions::new().new_session();
+        let mut session = session.write().unwrap();
+        let now = Utc::now();
+        session.add_link(now, "client", "api", Some("GET /api/session/2888964795923373081/events/42?wait=true"));
+        session.add_link(now, "api", "api", Some("first\nsecond"));
+        let svg_text: String = Document::from(session.deref()).into();
+        assert!(svg_text.contains("GET /api/session"));
+        assert!(svg_text.contains(">\n/events/42?wait=true\n</tspan>"));
+        assert_eq!(6, svg_text.matches("<tspan").count());
+        assert!(svg_text.contains("<polyline "));
+    }
+
+    #[test]
+    fn themes_and_custom_colors() {
+        let session = Sessions::new().new_session();
+        let mut session = session.write().unwrap();
+        session.add_link(Utc::now(), "a", "b", Some("Request"));
+        session.colors.parties.insert("b".to_string(), "teal".to_string());
+        session.colors.links.insert(1, "#ff0000".to_string());
+
+        let svg_text: String = Document::render(session.deref(), &Options { theme: Theme::DARK, ..Default::default() }).into();
+        assert!(svg_text.contains(".background { fill: #1e1e1e; }"));
+        assert!(svg_text.contains(".party-2 .lane, .party-2 .lifeline { stroke: teal; }"));
+        assert!(svg_text.contains(".link-1 .line { stroke: #ff0000; }"));
+        assert!(!svg_text.contains("style="));
+    }
+
+    #[test]
+    fn accessibility() {
+        let session = Sessions::new().new_session();
+        let mut session = session.write().unwrap();
+        let now = Utc::now();
+        session.add_labeled_party("web", Some("Web Client"));
+        session.add_link(now, "web", "api", Some("GET\n/items"));
+        session.add_link(now, "api", "web", None);
+        let svg_text: String = Document::from(session.deref()).into();
+
+        assert!(svg_text.contains(r#"role="graphics-document document""#));
+        assert!(svg_text.contains(&format!(r#"aria-labelledby="diagram-{}-title""#, session.id)));
+        assert!(svg_text.contains(&format!(r#"<title id="diagram-{}-title">"#, session.id)));
+        assert!(svg_text.contains(&format!(r#"<desc id="diagram-{}-desc">"#, session.id)));
+        assert!(svg_text.contains(&format!(">\nSequence diagram of session {}\n</title>", session.id)));
+        assert!(svg_text.contains("2 parties: Web Client, api.\n2 messages in order:\n\
+            Message 1 from Web Client to api: GET /items\n\
+            Message 2 from api to Web Client\n</desc>"));
+        assert!(svg_text.contains(r#"id="party-1""#));
+        assert!(svg_text.contains(r#"aria-label="Party Web Client""#));
+        assert!(svg_text.contains(r#"id="link-2""#));
+        assert!(svg_text.contains(r#"aria-label="Message 2 from api to Web Client""#));
+    }
+
+    #[test]
+    fn cache_appended_links() {
+        let mut session = SessionInner::new(1);
+        let now = Utc::now();
+        session.add_link(now, "a", "b", Some("Request"));
+        session.add_link(now, "b", "a", None);
+
+        let cache = Cache::default();
+        let options = Options::default();
+        let render = |session: &SessionInner| String::from(cache.render(session, &options));
+        assert_eq!(String::from(Document::render(&session, &options)), render(&session));
+
+        // Fits between the lanes
+        session.add_link(now, "a", "b", Some("Retry"));
+        assert_eq!(String::from(Document::render(&session, &options)), render(&session));
+
+        // Moves the lanes apart
+        session.add_link(now, "b", "a", Some(&"Response with a long label ".repeat(3)));
+        assert_eq!(String::from(Document::render(&session, &options)), render(&session));
+
+        session.add_link(now, "b", "c", None);
+        session.colors.links.insert(1, "red".to_string());
+        let svg_text = render(&session);
+        assert_eq!(String::from(Document::render(&session, &options)), svg_text);
+        assert!(svg_text.contains(r#"id="party-3""#));
+        assert!(svg_text.contains(".link-1 .line { stroke: red; }"));
+
+        let dark = Options { theme: Theme::DARK, ..Default::default() };
+        assert_eq!(String::from(Document::render(&session, &dark)), String::from(cache.render(&session, &dark)));
+    }
+
+    #[test]
+    fn evict_least_recently_used() {
+        let sessions = (1..=3).map(|id| {
+            let mut session = SessionInner::new(id);
+            session.add_link(Utc::now(), "a", "b", None);
+            session
+        }).collect::<Vec<_>>();
+        let cache = Cache::new(2);
+        let options = Options::default();
+        let keys = || {
+            let mut keys = cache.entries.lock().unwrap().entries.keys().map(|(id, ..)| *id).collect::<Vec<_>>();
+            keys.sort();
+            keys
+        };
+
+        cache.render(&sessions[0], &options);
+        cache.render(&sessions[1], &options);
+        cache.render(&sessions[0], &options);
+        cache.render(&sessions[2], &options);
+        assert_eq!(vec![1, 3], keys());
+        cache.render(&sessions[2], &Options { theme: Theme::DARK, ..Default::default() });
+        assert_eq!(vec![3, 3], keys());
+    }
+
+    #[test]
+    fn collapse_repeats() {
+        let mut session = SessionInner::new(1);
+        for _ in 0..10 {
+            session.add_link(Utc::now(), "web", "api", Some("GET /health"));
+        }
+        let options = Options { collapse_repeats: true, ..Default::default() };
+        let svg_text: String = Document::render(&session, &options).into();
+        assert_eq!(1, svg_text.matches(">\nGET /health\n</tspan>").count());
+        assert!(svg_text.contains(">\nloop ×10\n</tspan>"));
+        assert!(svg_text.contains(r#"aria-label="Loop of 1 message repeated 10 times""#));
+
+        let cache = Cache::default();
+        assert_eq!(svg_text, String::from(cache.render(&session, &options)));
+        session.add_link(Utc::now(), "web", "api", Some("GET /health"));
+        assert!(String::from(cache.render(&session, &options)).contains(">\nloop ×11\n</tspan>"));
+        assert!(!String::from(cache.render(&session, &Options::default())).contains("loop ×"));
     }
 }
@@ -0,0 +1,350 @@
+use crate::data;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use unicode_width::UnicodeWidthChar;
+
+/// Smallest width of a lane in terminal columns.
+pub const MIN_LANE_WIDTH: usize = 8;
+
+#[derive(Deserialize, Serialize)]
+#[cfg_attr(feature = "server", derive(Responder), response(status = 200))]
+pub struct Document(String);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Options {
+    /// Target width in terminal columns, exceeded when the lanes don't fit.
+    pub width: usize,
+    /// Draw with plain ASCII instead of Unicode box-drawing characters.
+    pub ascii: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            width: 80,
+            ascii: false,
+        }
+    }
+}
+
+struct Charset {
+    vertical: char,
+    horizontal: char,
+    top_left: char,
+    top_right: char,
+    bottom_left: char,
+    bottom_right: char,
+    bottom_tee: char,
+    start_right: char,
+    start_left: char,
+    ellipsis: &'static str,
+}
+
+const UNICODE: Charset = Charset {
+    vertical: '│',
+    horizontal: '─',
+    top_left: '┌',
+    top_right: '┐',
+    bottom_left: '└',
+    bottom_right: '┘',
+    bottom_tee: '┬',
+    start_right: '├',
+    start_left: '┤',
+    ellipsis: "…",
+};
+
+const ASCII: Charset = Charset {
+    vertical: '|',
+    horizontal: '-',
+    top_left: '+',
+    top_right: '+',
+    bottom_left: '+',
+    bottom_right: '+',
+    bottom_tee: '+',
+    start_right: '+',
+    start_left: '+',
+    ellipsis: "...",
+};
+
+/// Columns taken by `text` in a terminal, the measure for both cutting and
+/// placing text.
+fn columns(text: &str) -> usize {
+    text.chars().map(|c| c.width().unwrap_or(0)).sum()
+}
+
+/// Shortens `text` to `width` columns, control characters become spaces as
+/// they would move the cursor.
+fn truncate(text: &str, width: usize, ellipsis: &str) -> String {
+    let text = text.chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .collect::<String>();
+    if columns(&text) <= width {
+        return text;
+    }
+    let fitting = |width: usize| {
+        let mut used = 0;
+        text.chars()
+            .take_while(|c| {
+                used += c.width().unwrap_or(0);
+                used <= width
+            })
+            .collect::<String>()
+    };
+    let ellipsis_width = columns(ellipsis);
+    if width <= ellipsis_width {
+        return fitting(width);
+    }
+    fitting(width - ellipsis_width) + ellipsis
+}
+
+struct Canvas<'a> {
+    charset: &'a Charset,
+    centers: Vec<usize>,
+    width: usize,
+    lines: Vec<String>,
+}
+
+impl<'a> Canvas<'a> {
+    fn lifelines(&self) -> Vec<char> {
+        let mut line = vec![' '; self.width];
+        for center in &self.centers {
+            line[*center] = self.charset.vertical;
+        }
+        line
+    }
+
+    /// Replaces as many cells as `text` is wide, so wide characters take two
+    /// cells and combining ones none. Cells right of `start` move when the
+    /// text isn't one column per character.
+    fn put(line: &mut Vec<char>, start: usize, text: &str) {
+        let end = (start + columns(text)).min(line.len());
+        line.splice(start..end, text.chars());
+    }
+
+    fn push(&mut self, line: Vec<char>) {
+        self.lines.push(line.into_iter().collect::<String>().trim_end().to_string());
+    }
+}
+
+impl Document {
+    pub fn render(session: &data::SessionInner, options: &Options) -> Self {
+        let charset = if options.ascii { &ASCII } else { &UNICODE };
+
+        let mut parties = session.parties.iter().map(|i| &i.0).collect::<Vec<_>>();
+        parties.sort();
+        if parties.is_empty() {
+            return Document(String::new());
+        }
+
+        let lane = (options.width / parties.len()).max(MIN_LANE_WIDTH);
+        let centers = (0..parties.len()).map(|i| i * lane + (lane - 1) / 2).collect::<Vec<_>>();
+        let positions = parties.iter()
+            .zip(centers.iter())
+            .map(|(party, center)| (party.name.as_str(), *center))
+            .collect::<HashMap<_, _>>();
+        let mut canvas = Canvas {
+            charset,
+            centers,
+            width: lane * parties.len(),
+            lines: vec![],
+        };
+
+        // Participant boxes
+        let mut top = vec![' '; canvas.width];
+        let mut middle = vec![' '; canvas.width];
+        let mut bottom = vec![' '; canvas.width];
+        let mut names = vec![];
+        for (i, party) in parties.iter().enumerate() {
+            let (left, right) = (i * lane, i * lane + lane - 2);
+            for pos in left..=right {
+                top[pos] = charset.horizontal;
+                bottom[pos] = charset.horizontal;
+            }
+            top[left] = charset.top_left;
+            top[right] = charset.top_right;
+            bottom[left] = charset.bottom_left;
+            bottom[right] = charset.bottom_right;
+            bottom[canvas.centers[i]] = charset.bottom_tee;
+            middle[left] = charset.vertical;
+            middle[right] = charset.vertical;
+
+            let name = party.label.as_deref().unwrap_or(party.name.as_str());
+            let name = truncate(name, lane - 4, charset.ellipsis);
+            let free = (lane - 3).saturating_sub(columns(&name));
+            names.push((left + 1 + free.div_ceil(2), name));
+        }
+        // From the right, as names move the cells after them
+        for (start, name) in names.iter().rev() {
+            Canvas::put(&mut middle, *start, name);
+        }
+        canvas.push(top);
+        canvas.push(middle);
+        canvas.push(bottom);
+
+        for link in &session.links {
+            let from = positions[link.from.name.as_str()];
+            let to = positions[link.to.name.as_str()];
+
+            if let Some(label) = link.label.as_deref() {
+                let mut line = canvas.lifelines();
+                if from == to {
+                    let label = truncate(label, (lane - 3).min(canvas.width - from - 2), charset.ellipsis);
+                    Canvas::put(&mut line, from + 2, &label);
+                } else {
+                    let (low, high) = (from.min(to), from.max(to));
+                    let label = truncate(label, high - low - 1, charset.ellipsis);
+                    let free = (high - low - 1).saturating_sub(columns(&label));
+                    Canvas::put(&mut line, low + 1 + free / 2, &label);
+                }
+                canvas.push(line);
+            }
+
+            let mut line = canvas.lifelines();
+            if from == to {
+                line[from] = charset.start_right;
+                line[from + 1] = charset.horizontal;
+                line[from + 2] = charset.horizontal;
+                line[from + 3] = charset.top_right;
+                canvas.push(line);
+
+                let mut line = canvas.lifelines();
+                line[from + 1] = '<';
+                line[from + 2] = charset.horizontal;
+                line[from + 3] = charset.bottom_right;
+                canvas.push(line);
+            } else {
+                let (low, high) = (from.min(to), from.max(to));
+                for cell in &mut line[low + 1..high] {
+                    *cell = charset.horizontal;
+                }
+                if to > from {
+                    line[from] = charset.start_right;
+                    line[to - 1] = '>';
+                } else {
+                    line[from] = charset.start_left;
+                    line[to + 1] = '<';
+                }
+                canvas.push(line);
+            }
+        }
+
+        let end = canvas.lifelines();
+        canvas.push(end);
+
+        let mut text = canvas.lines.join("\n");
+        text.push('\n');
+        Document(text)
+    }
+}
+
+impl From<&data::SessionInner> for Document {
+    fn from(session: &data::SessionInner) -> Self {
+        Self::render(session, &Options::default())
+    }
+}
+
+impl From<Document> for String {
+    fn from(doc: Document) -> String {
+        doc.0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::data::SessionInner;
+    use chrono::Utc;
+
+    fn session() -> SessionInner {
+        let mut session = SessionInner::new(0);
+        let now = Utc::now();
+        session.add_link(now, "a", "server", Some("Request"));
+        session.add_link(now, "server", "server", Some("Process"));
+        session.add_link(now, "server", "a", Some("A very long response label"));
+        session.add_link(now, "a", "server", None);
+        session
+    }
+
+    #[test]
+    fn unicode() {
+        let text: String = Document::render(&session(), &Options { width: 30, ascii: false }).into();
+        assert_eq!(text, [
+            "┌────────────┐ ┌────────────┐",
+            "│      a     │ │   server   │",
+            "└──────┬─────┘ └──────┬─────┘",
+            "       │   Request    │",
+            "       ├─────────────>│",
+            "       │              │ Proce…",
+            "       │              ├──┐",
+            "       │              │<─┘",
+            "       │A very long r…│",
+            "       │<─────────────┤",
+            "       ├─────────────>│",
+            "       │              │",
+            "",
+        ].join("\n"));
+    }
+
+    #[test]
+    fn ascii_with_minimal_lanes() {
+        let text: String = Document::render(&session(), &Options { width: 4, ascii: true }).into();
+        assert_eq!(text, [
+            "+-----+ +-----+",
+            "|  a  | | s...|",
+            "+--+--+ +--+--+",
+            "   |Request|",
+            "   +------>|",
+            "   |       | Pro",
+            "   |       +--+",
+            "   |       |<-+",
+            "   |A ve...|",
+            "   |<------+",
+            "   +------>|",
+            "   |       |",
+            "",
+        ].join("\n"));
+    }
+
+    #[test]
+    fn wide_and_combining_characters() {
+        let mut session = SessionInner::new(0);
+        let now = Utc::now();
+        session.add_labeled_party("a", Some("東京"));
+        session.add_labeled_party("b", Some("Cafe\u{301}"));
+        session.add_link(now, "a", "b", Some("🚀 start"));
+        session.add_link(now, "b", "a", Some("日本語のとても長いラベル"));
+        let text: String = Document::render(&session, &Options { width: 30, ascii: false }).into();
+        assert_eq!(text, [
+            "┌────────────┐ ┌────────────┐",
+            "│    東京    │ │    Cafe\u{301}    │",
+            "└──────┬─────┘ └──────┬─────┘",
+            "       │   🚀 start   │",
+            "       ├─────────────>│",
+            "       │日本語のとて… │",
+            "       │<─────────────┤",
+            "       │              │",
+            "",
+        ].join("\n"));
+        for line in text.lines() {
+            assert!(columns(line) <= 29, "{line}");
+        }
+    }
+
+    #[test]
+    fn control_characters() {
+        let mut session = SessionInner::new(0);
+        let now = Utc::now();
+        session.add_labeled_party("a", Some("\x1b[31mred\tparty"));
+        session.add_link(now, "a", "b", Some("tab\tand\x1b[0m escape"));
+        let text: String = Document::render(&session, &Options { width: 30, ascii: true }).into();
+        assert!(!text.contains(['\t', '\x1b']));
+        assert_eq!(text.lines().nth(1), Some("|  [31mred...| |      b     |"));
+        assert_eq!(text.lines().nth(3), Some("       |tab and [0m...|"));
+    }
+
+    #[test]
+    fn empty_session() {
+        let text: String = Document::from(&SessionInner::new(0)).into();
+        assert!(text.is_empty());
+    }
+}
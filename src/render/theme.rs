@@ -0,0 +1,149 @@
+use crate::data::Colors;
+use std::{
+    fmt::Write,
+    str::FromStr,
+};
+
+/// Colors, strokes and fonts of a diagram, applied to the CSS classes of
+/// the elements.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Theme {
+    pub name: &'static str,
+    pub background: &'static str,
+    pub lane_fill: &'static str,
+    pub lane_stroke: &'static str,
+    pub text: &'static str,
+    /// Outline around labels, keeps them readable on top of lifelines.
+    pub halo: &'static str,
+    pub line: &'static str,
+    pub line_width: f32,
+    pub font_family: &'static str,
+}
+
+impl Theme {
+    pub const LIGHT: Theme = Theme {
+        name: "light",
+        background: "white",
+        lane_fill: "white",
+        lane_stroke: "lightgray",
+        text: "black",
+        halo: "white",
+        line: "black",
+        line_width: 2.0,
+        font_family: "sans-serif",
+    };
+
+    pub const DARK: Theme = Theme {
+        name: "dark",
+        background: "#1e1e1e",
+        lane_fill: "#252526",
+        lane_stroke: "#3c3c3c",
+        text: "#d4d4d4",
+        halo: "#252526",
+        line: "#9cdcfe",
+        line_width: 2.0,
+        font_family: "sans-serif",
+    };
+
+    pub const HIGH_CONTRAST: Theme = Theme {
+        name: "high-contrast",
+        background: "black",
+        lane_fill: "black",
+        lane_stroke: "white",
+        text: "yellow",
+        halo: "black",
+        line: "white",
+        line_width: 3.0,
+        font_family: "sans-serif",
+    };
+
+    /// Without any filled areas to save ink.
+    pub const PRINT: Theme = Theme {
+        name: "print",
+        background: "white",
+        lane_fill: "none",
+        lane_stroke: "gray",
+        text: "black",
+        halo: "white",
+        line: "black",
+        line_width: 1.0,
+        font_family: "serif",
+    };
+
+    pub const ALL: [Theme; 4] = [Theme::LIGHT, Theme::DARK, Theme::HIGH_CONTRAST, Theme::PRINT];
+
+    /// Style sheet for the classes of the SVG elements.
+    pub fn css(&self) -> String {
+        let Theme { background, lane_fill, lane_stroke, text, halo, line, line_width, font_family, .. } = self;
+        format!("\
+            .background {{ fill: {background}; }}\n\
+            .lane {{ fill: {lane_fill}; stroke: {lane_stroke}; }}\n\
+            .party-name, .label, .frame-title {{ fill: {text}; font-family: {font_family}; font-size: 16px; }}\n\
+            .label {{ stroke: {halo}; stroke-width: 0.5em; stroke-linejoin: round; paint-order: stroke; }}\n\
+            .lifeline, .line {{ stroke: {line}; stroke-width: {line_width}; fill: none; }}\n\
+            .lifeline-end, .arrow {{ fill: {line}; }}\n\
+            .gap {{ stroke: {lane_fill}; stroke-width: 8; }}\n\
+            .frame {{ fill: none; stroke: {line}; }}\n\
+            .frame-tab {{ fill: {lane_fill}; stroke: {line}; }}\n")
+    }
+
+    /// Style sheet for the custom colors of a session, overriding the theme.
+    pub fn colors_css(&self, colors: &Colors, party_id: impl Fn(&str) -> Option<u64>) -> String {
+        let mut css = String::new();
+        for (name, color) in &colors.parties {
+            if let Some(id) = party_id(name) {
+                let _ = writeln!(css, ".party-{id} .lane, .party-{id} .lifeline {{ stroke: {color}; }}");
+                let _ = writeln!(css, ".party-{id} .party-name, .party-{id} .lifeline-end {{ fill: {color}; }}");
+            }
+        }
+        for (id, color) in &colors.links {
+            let _ = writeln!(css, ".link-{id} .line {{ stroke: {color}; }}");
+            let _ = writeln!(css, ".link-{id} .arrow, .link-{id} .label {{ fill: {color}; }}");
+        }
+        css
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme::LIGHT
+    }
+}
+
+impl FromStr for Theme {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Theme::ALL.into_iter()
+            .find(|theme| theme.name == name)
+            .ok_or_else(|| format!(
+                "Unknown theme `{name}`, available themes: {}",
+                Theme::ALL.map(|theme| theme.name).join(", "),
+            ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_names() {
+        assert_eq!(Ok(Theme::DARK), "dark".parse());
+        assert_eq!(Ok(Theme::HIGH_CONTRAST), "high-contrast".parse());
+        assert!("sepia".parse::<Theme>().unwrap_err().contains("light, dark, high-contrast, print"));
+    }
+
+    #[test]
+    fn custom_colors() {
+        let mut colors = Colors::default();
+        colors.parties.insert("db".to_string(), "#336699".to_string());
+        colors.parties.insert("unknown".to_string(), "red".to_string());
+        colors.links.insert(4, "green".to_string());
+
+        let css = Theme::default().colors_css(&colors, |name| (name == "db").then_some(2));
+        assert!(css.contains(".party-2 .lane, .party-2 .lifeline { stroke: #336699; }"));
+        assert!(css.contains(".link-4 .arrow, .link-4 .label { fill: green; }"));
+        assert!(!css.contains("red"));
+    }
+}
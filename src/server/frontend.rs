@@ -29,7 +29,7 @@ pub(crate) fn styles_css() -> (ContentType, &'static str) {
 
 #[get("/script.js")]
 pub(crate) fn script_js() -> rocket::response::content::RawJavaScript<&'static str> {
-    const JS: &'static str = include_str!(concat!(std::env!("CARGO_MANIFEST_DIR"), "/static/script.js"));
+    const JS: &str = include_str!(concat!(std::env!("CARGO_MANIFEST_DIR"), "/static/script.js"));
     rocket::response::content::RawJavaScript(JS)
 }
 
@@ -37,15 +37,16 @@ pub(crate) fn script_js() -> rocket::response::content::RawJavaScript<&'static s
 pub(crate) fn new_session(sessions: &State<Sessions>) -> Redirect {
     let session = sessions.new_session();
     let session = session.read().unwrap();
-    Redirect::to(uri!(view(session.id)))
+    Redirect::to(uri!(view(session.id, _)))
 }
 
-#[get("/frontend/<id>")]
+#[get("/frontend/<id>?<theme>")]
 pub(crate) fn view(
     tera_cm: &State<TeraContextManager>,
     etag_if_none_match: EtagIfNoneMatch,
     sessions: &State<Sessions>,
-    id: u64) -> Result<TeraResponse, &'static str> {
+    id: u64,
+    theme: Option<&str>) -> Result<TeraResponse, &'static str> {
     let session = sessions.get(id).ok_or("Session not exists")?;
     let session = session.read().unwrap();
 
@@ -53,9 +54,9 @@ pub(crate) fn view(
     let session = session.deref();
 
     let mut context = HashMap::new();
-    context.insert("events_url", uri!(api::get_events(id, 0)).to_string());
+    context.insert("layout_url", uri!(api::get_layout(id, _)).to_string());
     context.insert("session_id", id.to_string());
-    context.insert("view_url", uri!(view(id)).to_string());
+    context.insert("view_url", uri!(view(id, _)).to_string());
 
     context.insert("add_link_api_url", uri!(api::add_link(id)).to_string());
     context.insert("session_api_url", uri!(api::get_session(id)).to_string());
@@ -67,8 +68,29 @@ pub(crate) fn view(
         context.insert("mermaid_url", uri!(api::get_mermaid(id)).to_string());
     }
 
+    #[cfg(feature = "plantuml")]
+    context.insert("plantuml_url", uri!(api::get_plantuml(id)).to_string());
+
     #[cfg(feature = "svg")]
-    context.insert("svg_url", uri!(api::get_svg(id)).to_string());
+    {
+        use crate::render::theme::Theme;
+        let theme: Theme = theme.map(str::parse).transpose().map_err(|_| "Unknown theme")?.unwrap_or_default();
+        context.insert("theme_css", theme.css() + &theme.colors_css(&session.colors, |name| session.party(name).map(|party| party.id)));
+        context.insert("svg_url", format!("{}?theme={}", uri!(api::get_svg(id)), theme.name));
+    }
+
+    #[cfg(feature = "png")]
+    context.insert("png_url", uri!(api::get_png(id)).to_string());
+
+    #[cfg(feature = "pdf")]
+    context.insert("pdf_url", uri!(api::get_pdf(id)).to_string());
+
+    #[cfg(feature = "text")]
+    context.insert("text_url", uri!(api::get_text(id)).to_string());
+    #[cfg(feature = "html")]
+    context.insert("html_url", uri!(api::get_html(id)).to_string());
+    #[cfg(feature = "dot")]
+    context.insert("dot_url", uri!(api::get_dot(id)).to_string());
 
     Ok(tera_response!(tera_cm, etag_if_none_match, "session-live-view", context))
 }
@@ -81,7 +103,7 @@ pub(crate) fn new_example(sessions: &State<Sessions>) -> Redirect {
     session.add_link(now, "a", "b", Some("Request"));
     session.add_link(now, "b", "c", Some("Forward"));
     session.add_link(now, "c", "a", Some("Response"));
-    Redirect::to(uri!(view(session.id)))
+    Redirect::to(uri!(view(session.id, _)))
 }
 
 #[cfg(test)]
@@ -123,7 +145,7 @@ mod test {
             let session = session.read().unwrap();
             session.id
         };
-        let response = client.get(uri!(view(id))).dispatch().await;
+        let response = client.get(uri!(view(id, _))).dispatch().await;
         assert_eq!(response.status(), Status::Ok);
     }
 }
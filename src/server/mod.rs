@@ -2,10 +2,22 @@
 pub mod frontend;
 
 use crate::{api, data::Sessions};
+#[cfg(feature = "import")]
+use crate::import::logfile;
+#[cfg(feature = "otlp")]
+use crate::import::otlp;
+#[cfg(feature = "render")]
+use crate::render::Registry;
+#[cfg(feature = "svg")]
+use crate::render::svg;
 use rocket::{
     self,
     Build, Rocket,
 };
+#[cfg(any(feature = "import", feature = "otlp"))]
+use rocket::fairing::AdHoc;
+#[cfg(feature = "import")]
+use std::collections::HashMap;
 
 #[cfg(feature = "frontend")]
 use rocket_include_tera::TeraResponse;
@@ -17,6 +29,37 @@ fn index() -> &'static str {
 }
 
 pub fn serve(sessions: Sessions) -> Rocket<Build> {
+    #[cfg(feature = "render")]
+    {
+        serve_with_registry(sessions, Registry::default())
+    }
+    #[cfg(not(feature = "render"))]
+    {
+        build(sessions)
+    }
+}
+
+/// Serves the formats of `registry` on `/api/session/<id>/render/<format>`,
+/// instead of the formats of this crate.
+#[cfg(feature = "render")]
+pub fn serve_with_registry(sessions: Sessions, registry: Registry) -> Rocket<Build> {
+    build(sessions)
+        .manage(registry)
+        .mount("/", routes![api::render])
+}
+
+/// Rule sets of the `log_rules` table of the config, none without table.
+#[cfg(feature = "import")]
+fn log_rules(figment: &rocket::figment::Figment) -> Result<logfile::RuleSets, String> {
+    if figment.find_value("log_rules").is_err() {
+        return Ok(logfile::RuleSets::default());
+    }
+    let sets = figment.extract_inner::<HashMap<String, logfile::RuleSet>>("log_rules")
+        .map_err(|e| e.to_string())?;
+    logfile::RuleSets::new(&sets).map_err(|(name, e)| format!("`{name}` {e}"))
+}
+
+fn build(sessions: Sessions) -> Rocket<Build> {
     let rocket = rocket::build();
 
     #[cfg(feature = "frontend")]
@@ -30,6 +73,49 @@ pub fn serve(sessions: Sessions) -> Rocket<Build> {
         }));
 
     let rocket = rocket.manage(sessions);
+    #[cfg(feature = "svg")]
+    let rocket = rocket.manage(svg::Cache::default());
+    #[cfg(feature = "otlp")]
+    let rocket = rocket.attach(AdHoc::try_on_ignite("OTLP receiver", |rocket| async {
+        let config = match rocket.figment().find_value("otlp") {
+            Ok(_) => rocket.figment().extract_inner::<otlp::Config>("otlp"),
+            Err(_) => Ok(otlp::Config::default()),
+        };
+        match config {
+            Ok(config) => Ok(rocket.manage(otlp::Receiver::new(config))),
+            Err(e) => {
+                error!("Invalid OTLP config: {}", e);
+                Err(rocket)
+            }
+        }
+    }));
+    #[cfg(feature = "otlp")]
+    let rocket = rocket.attach(AdHoc::on_liftoff("OTLP idle traces", |rocket| Box::pin(async move {
+        let receiver = rocket.state::<otlp::Receiver>().unwrap().clone();
+        let sessions = rocket.state::<Sessions>().unwrap().clone();
+        rocket::tokio::spawn(async move {
+            let mut interval = rocket::tokio::time::interval(std::time::Duration::from_secs(1));
+            loop {
+                interval.tick().await;
+                receiver.flush(&sessions);
+            }
+        });
+    })));
+    #[cfg(feature = "otlp")]
+    let rocket = rocket.attach(AdHoc::on_shutdown("OTLP pending traces", |rocket| Box::pin(async move {
+        let receiver = rocket.state::<otlp::Receiver>().unwrap();
+        receiver.finish(rocket.state::<Sessions>().unwrap());
+    })));
+    #[cfg(feature = "import")]
+    let rocket = rocket.attach(AdHoc::try_on_ignite("Log rules", |rocket| async {
+        match log_rules(rocket.figment()) {
+            Ok(rule_sets) => Ok(rocket.manage(rule_sets)),
+            Err(e) => {
+                error!("Invalid log rules: {}", e);
+                Err(rocket)
+            }
+        }
+    }));
 
     #[allow(unused_mut)]
     let mut routes = vec![];
@@ -56,22 +142,54 @@ pub fn serve(sessions: Sessions) -> Rocket<Build> {
         api::get_session,
         api::add_link,
         api::get_links,
+        api::set_colors,
         api::get_events,
+        api::get_diff,
+        api::fork_session,
+        api::merge_sessions,
+        api::export_session,
     ]);
         
+    #[cfg(feature = "import")]
+    routes.append(&mut routes![
+        api::import_session,
+        api::import_mermaid,
+        api::import_mermaid_alias,
+        api::import_plantuml,
+        api::import_plantuml_alias,
+        api::import_zipkin,
+        api::import_jaeger,
+        api::import_har,
+        api::import_log,
+    ]);
+    #[cfg(feature = "otlp")]
+    routes.append(&mut routes![api::receive_traces]);
     #[cfg(feature = "mermaid")]
     routes.append(&mut routes![api::get_mermaid]);
+    #[cfg(feature = "plantuml")]
+    routes.append(&mut routes![api::get_plantuml]);
     #[cfg(feature = "svg")]
-    routes.append(&mut routes![api::get_svg]);
-
-    let rocket = rocket.mount("/", routes);
+    routes.append(&mut routes![api::get_svg, api::get_layout, api::get_diff_svg]);
+    #[cfg(feature = "png")]
+    routes.append(&mut routes![api::get_png]);
+    #[cfg(feature = "pdf")]
+    routes.append(&mut routes![api::get_pdf]);
+    #[cfg(feature = "text")]
+    routes.append(&mut routes![api::get_text]);
+    #[cfg(feature = "html")]
+    routes.append(&mut routes![api::get_html]);
+    #[cfg(feature = "dot")]
+    routes.append(&mut routes![api::get_dot]);
 
-    rocket
+    rocket.mount("/", routes)
 }
 
 #[cfg(test)]
 pub(crate) mod test {
     use super::*;
+    use crate::{data, diff};
+    #[cfg(feature = "import")]
+    use crate::import;
     use chrono::Utc;
     use rocket::{
         http::Status,
@@ -128,7 +246,7 @@ pub(crate) mod test {
             (session.id, now)
         };
 
-        let response = client.get(uri!(api::get_links(id))).dispatch().await;
+        let response = client.get(format!("/api/session/{id}/links")).dispatch().await;
         let body: Vec<api::Link> = response.into_json().await.expect("A valid json");
         assert_eq!(1, body.len());
         assert_eq!(now.timestamp_millis(), TryInto::<i64>::try_into(body[0].timestamp).unwrap());
@@ -137,6 +255,176 @@ pub(crate) mod test {
         assert_eq!(Some("label"), body[0].label.as_deref());
     }
 
+    #[cfg(feature = "import")]
+    #[tokio::test]
+    async fn import_mermaid() {
+        let (sessions, client) = tester().await;
+        let source = "sequenceDiagram\n  participant a as Alice\n  a ->> b: Request\n  b -->> a: Response\n";
+        let response = client.post(uri!(api::import_mermaid())).body(source).dispatch().await;
+        assert_eq!(Status::Ok, response.status());
+        let response: api::NewSessionResponse = response.into_json().await.unwrap();
+
+        {
+            let session = sessions.get(response.id.parse().unwrap()).unwrap();
+            let session = session.read().unwrap();
+            assert_eq!(2, session.parties.len());
+            assert_eq!(2, session.links.len());
+            assert_eq!("Response", session.links[1].label.as_deref().unwrap());
+        }
+
+        assert_eq!("/api/session/import/mermaid", uri!(api::import_mermaid()).to_string());
+        let response = client.post(uri!(api::import_mermaid_alias())).body("sequenceDiagram\nend").dispatch().await;
+        assert_eq!(Status::UnprocessableEntity, response.status());
+        let response: api::ImportErrorResponse = response.into_json().await.unwrap();
+        assert_eq!(vec![import::ParseError::new(2, "`end` without an open block")], response.errors);
+    }
+
+    #[cfg(feature = "import")]
+    #[tokio::test]
+    async fn import_plantuml() {
+        let (sessions, client) = tester().await;
+        let source = "@startuml\nparticipant \"Alice\" as a\na -> b : Request\nb --> a : Response\n@enduml\n";
+        let response = client.post(uri!(api::import_plantuml())).body(source).dispatch().await;
+        assert_eq!(Status::Ok, response.status());
+        let response: api::NewSessionResponse = response.into_json().await.unwrap();
+        {
+            let session = sessions.get(response.id.parse().unwrap()).unwrap();
+            let session = session.read().unwrap();
+            assert_eq!(2, session.links.len());
+        }
+
+        let source = "@startuml\na -> b\nreturn x\n!include y\n@enduml\n";
+        assert_eq!("/api/session/import/plantuml", uri!(api::import_plantuml()).to_string());
+        let response = client.post(uri!(api::import_plantuml_alias())).body(source).dispatch().await;
+        assert_eq!(Status::UnprocessableEntity, response.status());
+        let response: api::ImportErrorResponse = response.into_json().await.unwrap();
+        assert_eq!(vec![3, 4], response.errors.iter().map(|e| e.line).collect::<Vec<_>>());
+    }
+
+    #[cfg(feature = "import")]
+    #[tokio::test]
+    async fn import_zipkin_and_jaeger() {
+        let (sessions, client) = tester().await;
+        let source = r#"[{"traceId": "a1", "id": "1", "kind": "SERVER", "name": "GET /", "timestamp": 1,
+            "localEndpoint": {"serviceName": "web"}, "remoteEndpoint": {"serviceName": "browser"}}]"#;
+        let response = client.post(uri!(api::import_zipkin())).body(source).dispatch().await;
+        let response: api::NewSessionResponse = response.into_json().await.unwrap();
+        let session = sessions.get(response.id.parse().unwrap()).unwrap();
+        assert_eq!("browser", session.read().unwrap().links[0].from.name);
+
+        let response = client.post(uri!(api::import_jaeger())).body(source).dispatch().await;
+        assert_eq!(Status::UnprocessableEntity, response.status());
+    }
+
+    #[cfg(feature = "import")]
+    #[tokio::test]
+    async fn import_har() {
+        let (sessions, client) = tester().await;
+        let source = r#"{"log": {"entries": [{
+            "startedDateTime": "2024-03-01T09:00:00Z", "time": 50,
+            "request": {"method": "GET", "url": "https://example.com/index.html"},
+            "response": {"status": 404, "statusText": "Not Found"}
+        }]}}"#;
+        let response = client.post(uri!(api::import_har())).body(source).dispatch().await;
+        let response: api::NewSessionResponse = response.into_json().await.unwrap();
+        let session = sessions.get(response.id.parse().unwrap()).unwrap();
+        let labels = session.read().unwrap().links.iter()
+            .map(|link| link.label.as_deref().unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["GET /index.html", "404 Not Found"], labels);
+
+        let response = client.post(uri!(api::import_har())).body("{}").dispatch().await;
+        assert_eq!(Status::UnprocessableEntity, response.status());
+    }
+
+    #[cfg(feature = "import")]
+    #[tokio::test]
+    async fn import_log() {
+        let rules = rocket::serde::json::json!({
+            "billing": {
+                "timestamp_format": "%Y-%m-%d %H:%M:%S",
+                "rules": [{ "pattern": r"^(?P<timestamp>\S+ \S+) (?P<from>\w+) -> (?P<to>\w+): (?P<label>.*)$" }],
+            },
+        });
+        let sessions = Sessions::new();
+        let rocket = serve(sessions.clone())
+            .configure(rocket::Config::figment().merge(("log_rules", &rules)).merge(("limits.log", 128)))
+            .ignite()
+            .await
+            .expect("A server");
+        let client = Client::tracked(rocket).await.unwrap();
+
+        let log = "2024-03-01 09:00:00 shop -> billing: invoice\r\nstarting worker\n2024-03-01 09:00:01 billing -> shop: done";
+        let response = client.post(uri!(api::import_log("billing"))).body(log).dispatch().await;
+        let response: api::LogImportResponse = response.into_json().await.expect("A valid json");
+        assert_eq!(2, response.links);
+        let session = sessions.get(response.id.parse().unwrap()).unwrap();
+        assert_eq!(Some("invoice"), session.read().unwrap().links[0].label.as_deref());
+
+        let log = "2024-03-01 09:00:00 shop -> billing: invoice\nyesterday 10:00 billing -> shop: done\n";
+        let response = client.post(uri!(api::import_log("billing"))).body(log).dispatch().await;
+        assert_eq!(Status::UnprocessableEntity, response.status());
+        let response: api::ImportErrorResponse = response.into_json().await.expect("A valid json");
+        assert_eq!(vec![2], response.errors.iter().map(|e| e.line).collect::<Vec<_>>());
+
+        let response = client.post(uri!(api::import_log("billing"))).body(b"\xff\n".as_slice()).dispatch().await;
+        assert_eq!(Status::BadRequest, response.status());
+
+        let response = client.post(uri!(api::import_log("billing"))).body(log.repeat(2)).dispatch().await;
+        assert_eq!(Status::BadRequest, response.status());
+        assert_eq!(1, sessions.sessions.read().unwrap().len());
+
+        let response = client.post(uri!(api::import_log("nginx"))).body(log).dispatch().await;
+        assert_eq!(Status::NotFound, response.status());
+
+        let invalid = rocket::serde::json::json!({ "billing": { "rules": [{ "pattern": "(?P<from>a)" }] } });
+        let rocket = serve(Sessions::new())
+            .configure(rocket::Config::figment().merge(("log_rules", &invalid)))
+            .ignite()
+            .await;
+        let error = rocket.map(|_| ()).unwrap_err();
+        assert!(matches!(error.kind(), rocket::error::ErrorKind::FailedFairings(_)));
+    }
+
+    #[cfg(feature = "otlp")]
+    #[tokio::test]
+    async fn receive_traces() {
+        use std::io::Write;
+
+        let (sessions, client) = tester().await;
+        let request = r#"{"resourceSpans": [{
+            "resource": {"attributes": [{"key": "service.name", "value": {"stringValue": "api"}}]},
+            "scopeSpans": [{"spans": [{
+                "traceId": "5b8efff798038103d269b633813fc60c", "spanId": "eee19b7ec3c1b174", "name": "GET /items", "kind": 2,
+                "startTimeUnixNano": "1544712660000000000", "endTimeUnixNano": "1544712661000000000",
+                "attributes": [{"key": "http.response.status_code", "value": {"intValue": "200"}}]
+            }]}]
+        }]}"#;
+        let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        encoder.write_all(request.as_bytes()).unwrap();
+
+        let response = client.post(uri!(api::receive_traces))
+            .header(rocket::http::ContentType::JSON)
+            .header(rocket::http::Header::new("Content-Encoding", "gzip"))
+            .body(encoder.finish().unwrap())
+            .dispatch().await;
+        assert_eq!(Status::Ok, response.status());
+        assert_eq!("{}", response.into_string().await.unwrap());
+
+        let receiver = client.rocket().state::<otlp::Receiver>().unwrap();
+        assert_eq!(2, receiver.finish(&sessions));
+        let session = sessions.get(receiver.session_id("5b8efff798038103d269b633813fc60c").unwrap()).unwrap();
+        let labels = session.read().unwrap().links.iter()
+            .map(|link| link.label.as_deref().unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["GET /items", "200"], labels);
+
+        let response = client.post(uri!(api::receive_traces)).body([0x0a, 0x05]).dispatch().await;
+        assert_eq!(Status::BadRequest, response.status());
+        let response = client.post(uri!(api::receive_traces)).body([]).dispatch().await;
+        assert_eq!(Some(rocket::http::ContentType::new("application", "x-protobuf")), response.content_type());
+    }
+
     #[cfg(feature = "mermaid")]
     #[tokio::test]
     async fn mermaid_output() {
@@ -154,4 +442,465 @@ pub(crate) mod test {
         let response = client.get(uri!(api::get_mermaid(id))).dispatch().await;
         let _ = response.into_string().await.unwrap();
     }
+
+    #[cfg(feature = "mermaid")]
+    #[tokio::test]
+    async fn collapse_repeats() {
+        let (sessions, client) = tester().await;
+        let id = {
+            let session = sessions.new_session();
+            let mut session = session.write().unwrap();
+            for _ in 0..5 {
+                session.add_link(Utc::now(), "a", "b", Some("Poll"));
+            }
+            session.id
+        };
+
+        let response = client.get(format!("/api/session/{id}/mermaid?collapse=true")).dispatch().await;
+        assert!(response.into_string().await.unwrap().contains("  loop ×5\n    a ->> b: Poll\n  end\n"));
+
+        let response = client.get(format!("/api/session/{id}/render/mermaid?collapse=yes")).dispatch().await;
+        assert_eq!(Status::BadRequest, response.status());
+    }
+
+    #[cfg(feature = "plantuml")]
+    #[tokio::test]
+    async fn plantuml_output() {
+        let (sessions, client) = tester().await;
+        let id = {
+            let session = sessions.new_session();
+            let mut session = session.write().unwrap();
+            session.add_link(Utc::now(), "a", "b", Some("Request"));
+            session.id
+        };
+
+        let response = client.get(uri!(api::get_plantuml(id))).dispatch().await;
+        assert_eq!(Status::Ok, response.status());
+        let body = response.into_string().await.unwrap();
+        assert!(body.starts_with("@startuml\n"));
+        assert!(body.contains("P1 -> P2 : Request\n"));
+    }
+
+    #[cfg(feature = "png")]
+    #[tokio::test]
+    async fn png_output() {
+        let (sessions, client) = tester().await;
+        let id = {
+            let session = sessions.new_session();
+            let mut session = session.write().unwrap();
+            session.add_link(Utc::now(), "a", "b", Some("Request"));
+            session.id
+        };
+
+        let response = client.get(format!("/api/session/{id}/png?scale=2")).dispatch().await;
+        assert_eq!(Status::Ok, response.status());
+        assert_eq!(Some(rocket::http::ContentType::PNG), response.content_type());
+        let body = response.into_bytes().await.unwrap();
+        assert!(body.starts_with(b"\x89PNG"));
+
+        let response = client.get(format!("/api/session/{id}/png?scale=-1")).dispatch().await;
+        assert_eq!(Status::BadRequest, response.status());
+    }
+
+    #[cfg(all(feature = "svg", feature = "text"))]
+    #[tokio::test]
+    async fn render_registered_formats() {
+        let (sessions, client) = tester().await;
+        let id = {
+            let session = sessions.new_session();
+            let mut session = session.write().unwrap();
+            session.add_link(Utc::now(), "a", "b", Some("Request"));
+            session.id
+        };
+
+        let response = client.get(format!("/api/session/{id}/render/svg")).dispatch().await;
+        assert_eq!(Status::Ok, response.status());
+        assert_eq!(Some(rocket::http::ContentType::SVG), response.content_type());
+
+        let response = client.get(format!("/api/session/{id}/render/text?width=20&ascii=true")).dispatch().await;
+        assert_eq!(Status::Ok, response.status());
+        assert!(response.into_string().await.unwrap().contains("->|"));
+
+        let response = client.get(format!("/api/session/{id}/render/text?width=wide")).dispatch().await;
+        assert_eq!(Status::BadRequest, response.status());
+
+        let response = client.get(format!("/api/session/{id}/render/bmp")).dispatch().await;
+        assert_eq!(Status::NotFound, response.status());
+    }
+
+    #[tokio::test]
+    async fn filter_links() {
+        let (sessions, client) = tester().await;
+        let id = {
+            let session = sessions.new_session();
+            let mut session = session.write().unwrap();
+            session.add_link(Utc::now(), "a", "b", Some("Request"));
+            session.add_link(Utc::now(), "b", "c", Some("Forward"));
+            session.add_link(Utc::now(), "c", "b", Some("Reply"));
+            session.id
+        };
+
+        let response = client.get(format!("/api/session/{id}/links?first_link=2&parties=b,c")).dispatch().await;
+        let body: Vec<api::Link> = response.into_json().await.expect("A valid json");
+        assert_eq!(vec![2, 3], body.iter().map(|link| link.id).collect::<Vec<_>>());
+
+        let response = client.get(format!("/api/session/{id}/links?parties=a,b&others=collapse")).dispatch().await;
+        let body: Vec<api::Link> = response.into_json().await.expect("A valid json");
+        assert_eq!(vec!["b", data::OTHERS], body.iter().skip(1).map(|link| link.from.as_str()).collect::<Vec<_>>());
+
+        let response = client.get(format!("/api/session/{id}/links?end=yesterday")).dispatch().await;
+        assert_eq!(Status::BadRequest, response.status());
+    }
+
+    #[cfg(feature = "svg")]
+    #[tokio::test]
+    async fn render_filtered() {
+        let (sessions, client) = tester().await;
+        let id = {
+            let session = sessions.new_session();
+            let mut session = session.write().unwrap();
+            session.add_link(Utc::now(), "a", "b", Some("Request"));
+            session.add_link(Utc::now(), "b", "c", Some("Forward"));
+            session.id
+        };
+
+        let response = client.get(format!("/api/session/{id}/render/svg?parties=a,b&theme=dark")).dispatch().await;
+        let svg = response.into_string().await.unwrap();
+        assert!(svg.contains(r#"id="link-1""#));
+        assert!(!svg.contains(r#"id="link-2""#));
+        assert!(svg.contains("#1e1e1e"));
+
+        let response = client.get(format!("/api/session/{id}/render/svg?first_link=x")).dispatch().await;
+        assert_eq!(Status::BadRequest, response.status());
+
+        // Filtered renderings bypass the cache of whole sessions
+        let response = client.get(uri!(api::get_svg(id))).dispatch().await;
+        assert!(response.into_string().await.unwrap().contains(r#"id="link-2""#));
+        let response = client.get(format!("/api/session/{id}/svg?last_link=1")).dispatch().await;
+        assert!(!response.into_string().await.unwrap().contains(r#"id="link-2""#));
+        let response = client.get(uri!(api::get_svg(id))).dispatch().await;
+        assert!(response.into_string().await.unwrap().contains(r#"id="link-2""#));
+
+        #[cfg(feature = "mermaid")]
+        {
+            let response = client.get(format!("/api/session/{id}/mermaid?parties=b,c")).dispatch().await;
+            let mermaid = response.into_string().await.unwrap();
+            assert!(mermaid.contains("Forward") && !mermaid.contains("Request"));
+        }
+        #[cfg(feature = "text")]
+        {
+            let response = client.get(format!("/api/session/{id}/text?others=drop")).dispatch().await;
+            assert_eq!(Status::BadRequest, response.status());
+        }
+    }
+
+    #[cfg(feature = "import")]
+    #[tokio::test]
+    async fn export_and_import() {
+        let (sessions, client) = tester().await;
+        let id = {
+            let session = sessions.new_session();
+            let mut session = session.write().unwrap();
+            session.add_labeled_party("web", Some("Web Client"));
+            session.add_link(Utc::now(), "web", "api", Some("Request"));
+            session.colors.parties.insert("api".to_string(), "teal".to_string());
+            session.id
+        };
+
+        let response = client.get(uri!(api::export_session(id))).dispatch().await;
+        assert_eq!(Some(rocket::http::ContentType::JSON), response.content_type());
+        let exported = response.into_string().await.unwrap();
+        assert!(exported.starts_with("{\n  \"format\": \"diagramer-session\",\n  \"version\": 1,\n"));
+
+        let response = client.post(uri!(api::import_session)).body(&exported).dispatch().await;
+        let body: api::NewSessionResponse = response.into_json().await.expect("A valid json");
+        let imported: u64 = body.id.parse().unwrap();
+        assert_ne!(id, imported);
+
+        let response = client.get(uri!(api::export_session(imported))).dispatch().await;
+        let reexported = response.into_string().await.unwrap();
+        assert_eq!(exported.replace(&id.to_string(), &imported.to_string()), reexported);
+
+        // Larger than the 1 MiB limit of JSON requests
+        let padded = exported.clone() + &" ".repeat(2 << 20);
+        let response = client.post(uri!(api::import_session)).body(padded).dispatch().await;
+        assert_eq!(Status::Ok, response.status());
+
+        let response = client.post(uri!(api::import_session)).body(exported.replace("\"version\": 1", "\"version\": 9")).dispatch().await;
+        assert_eq!(Status::UnprocessableEntity, response.status());
+
+        let response = client.post(uri!(api::import_session)).body(exported.replace("\"id\": 1,", "\"id\": \"1\",")).dispatch().await;
+        assert_eq!(Status::UnprocessableEntity, response.status());
+        let response: api::ImportErrorResponse = response.into_json().await.expect("A valid json");
+        assert!(response.errors[0].line > 1);
+    }
+
+    #[tokio::test]
+    async fn fork_and_merge() {
+        let (sessions, client) = tester().await;
+        let (web, api) = {
+            let now = Utc::now();
+            let web = sessions.new_session();
+            let mut web = web.write().unwrap();
+            web.add_link(now, "web", "api", Some("Request"));
+            web.add_link(now + chrono::Duration::seconds(2), "api", "web", Some("Response"));
+            let api = sessions.new_session();
+            let mut api = api.write().unwrap();
+            api.add_link(now + chrono::Duration::seconds(1), "api", "db", Some("Query"));
+            (web.id, api.id)
+        };
+
+        let response = client.post(uri!(api::fork_session(web, Some(1)))).dispatch().await;
+        let body: api::NewSessionResponse = response.into_json().await.expect("A valid json");
+        let fork = sessions.get(body.id.parse().unwrap()).unwrap();
+        assert_eq!(1, fork.read().unwrap().links.len());
+
+        let response = client.post(uri!(api::merge_sessions(web, api))).dispatch().await;
+        let body: api::NewSessionResponse = response.into_json().await.expect("A valid json");
+        let merged = sessions.get(body.id.parse().unwrap()).unwrap();
+        let labels = merged.read().unwrap().links.iter()
+            .map(|link| link.label.as_deref().unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["Request", "Query", "Response"], labels);
+        assert_eq!(2, sessions.get(web).unwrap().read().unwrap().links.len());
+
+        let response = client.post(uri!(api::merge_sessions(web, 1))).dispatch().await;
+        assert_eq!(Status::NotFound, response.status());
+    }
+
+    #[tokio::test]
+    async fn diff_sessions() {
+        let (sessions, client) = tester().await;
+        let add = |links: &[(&str, &str, &str)]| {
+            let session = sessions.new_session();
+            let mut session = session.write().unwrap();
+            for (from, to, label) in links {
+                session.add_link(Utc::now(), from, to, Some(label));
+            }
+            session.id
+        };
+        let baseline = add(&[("web", "api", "GET /v1/items"), ("api", "db", "select"), ("api", "web", "200")]);
+        let current = add(&[("web", "api", "GET /v2/items"), ("api", "web", "200"), ("web", "log", "event")]);
+
+        let response = client.get(uri!(api::get_diff(current, baseline))).dispatch().await;
+        let body: api::DiffResponse = response.into_json().await.expect("A valid json");
+        assert_eq!((1, 1, 1), (body.added, body.removed, body.changed));
+        assert_eq!(Some("GET /v1/items"), body.entries[0].baseline_label.as_deref());
+        assert_eq!(Some("GET /v2/items"), body.entries[0].label.as_deref());
+        assert_eq!(diff::Kind::Removed, body.entries[1].kind);
+        assert_eq!((None, Some(3)), (body.entries[3].baseline_id, body.entries[3].current_id));
+
+        #[cfg(feature = "svg")]
+        {
+            let response = client.get(body.svg_url).dispatch().await;
+            let svg = response.into_string().await.unwrap();
+            assert!(svg.contains(".link-2 .line { stroke: red; }"));
+            assert!(svg.contains(".link-4 .line { stroke: green; }"));
+        }
+
+        let response = client.get(uri!(api::get_diff(current, 1))).dispatch().await;
+        assert_eq!(Status::NotFound, response.status());
+    }
+
+    #[cfg(feature = "svg")]
+    #[tokio::test]
+    async fn negotiate_session_format() {
+        let (sessions, client) = tester().await;
+        let id = sessions.new_session().read().unwrap().id;
+
+        let response = client.get(uri!(api::get_session(id)))
+            .header(rocket::http::Accept::SVG)
+            .dispatch().await;
+        assert_eq!(Status::Ok, response.status());
+        assert_eq!(Some(rocket::http::ContentType::SVG), response.content_type());
+        assert!(response.into_string().await.unwrap().starts_with("<svg"));
+
+        let accept = "image/svg+xml;q=0.5, application/json".parse::<rocket::http::Accept>().unwrap();
+        let response = client.get(uri!(api::get_session(id))).header(accept).dispatch().await;
+        assert_eq!(Some(rocket::http::ContentType::JSON), response.content_type());
+
+        // JSON without specific type, like the frontend and clients
+        for accept in [rocket::http::Accept::JSON, rocket::http::Accept::Any] {
+            let response = client.get(uri!(api::get_session(id))).header(accept).dispatch().await;
+            assert_eq!(Some(rocket::http::ContentType::JSON), response.content_type());
+        }
+        let response = client.get(uri!(api::get_session(id))).dispatch().await;
+        assert_eq!(Some(rocket::http::ContentType::JSON), response.content_type());
+
+        let response = client.get(uri!(api::get_session(id)))
+            .header("video/mp4".parse::<rocket::http::Accept>().unwrap())
+            .dispatch().await;
+        assert_eq!(Status::NotAcceptable, response.status());
+    }
+
+    #[cfg(feature = "render")]
+    #[tokio::test]
+    async fn custom_registry() {
+        use crate::{data::SessionInner, render::registry::{Error, Options, Renderer}};
+
+        struct Count;
+
+        impl Renderer for Count {
+            fn content_type(&self) -> &str {
+                "text/plain"
+            }
+
+            fn render(&self, session: &SessionInner, _: &Options) -> Result<Vec<u8>, Error> {
+                Ok(session.links.len().to_string().into_bytes())
+            }
+        }
+
+        let sessions = Sessions::new();
+        let mut registry = Registry::new();
+        registry.register("count", Count);
+        let client = Client::tracked(serve_with_registry(sessions.clone(), registry)).await.unwrap();
+        let id = sessions.new_session().read().unwrap().id;
+
+        let response = client.get(format!("/api/session/{id}/render/count")).dispatch().await;
+        assert_eq!(Some(rocket::http::ContentType::Plain), response.content_type());
+        assert_eq!("0", response.into_string().await.unwrap());
+        let response = client.get(format!("/api/session/{id}/render/svg")).dispatch().await;
+        assert_eq!(Status::NotFound, response.status());
+    }
+
+    #[cfg(feature = "dot")]
+    #[tokio::test]
+    async fn dot_output() {
+        let (sessions, client) = tester().await;
+        let id = {
+            let session = sessions.new_session();
+            let mut session = session.write().unwrap();
+            session.add_link(Utc::now(), "a", "b", Some("Request"));
+            session.add_link(Utc::now(), "a", "b", Some("Request"));
+            session.id
+        };
+
+        let response = client.get(uri!(api::get_dot(id))).dispatch().await;
+        assert_eq!(Status::Ok, response.status());
+        assert_eq!("text/vnd.graphviz", response.content_type().unwrap().to_string());
+        let body = response.into_string().await.unwrap();
+        assert!(body.starts_with("digraph session {"));
+        assert!(body.contains("P1 -> P2 [label=\"2 messages\\nRequest\""));
+    }
+
+    #[cfg(feature = "svg")]
+    #[tokio::test]
+    async fn layout_since_link() {
+        let (sessions, client) = tester().await;
+        let id = {
+            let session = sessions.new_session();
+            let mut session = session.write().unwrap();
+            session.add_link(Utc::now(), "a", "b", Some("Request"));
+            session.add_link(Utc::now(), "b", "a", Some("Response"));
+            session.id
+        };
+
+        let response = client.get(uri!(api::get_layout(id, _))).dispatch().await;
+        assert_eq!(Status::Ok, response.status());
+        let layout: rocket::serde::json::Value = response.into_json().await.unwrap();
+        assert_eq!(2, layout["lanes"].as_array().unwrap().len());
+        assert_eq!(2, layout["rows"].as_array().unwrap().len());
+        assert_eq!("Request", layout["rows"][0]["label"]["lines"][0]);
+        assert_eq!(120.0, layout["lanes"][0]["center"]);
+        assert_eq!(true, layout["complete"]);
+        assert_eq!(uri!(api::get_layout(id, Some(2))).to_string(), layout["layout_url"]);
+
+        let response = client.get(uri!(api::get_layout(id, Some(1)))).dispatch().await;
+        let layout: rocket::serde::json::Value = response.into_json().await.unwrap();
+        assert_eq!(1, layout["rows"].as_array().unwrap().len());
+        assert_eq!(2, layout["rows"][0]["id"]);
+        assert_eq!(false, layout["complete"]);
+    }
+
+    #[cfg(feature = "svg")]
+    #[tokio::test]
+    async fn svg_theme_and_colors() {
+        let (sessions, client) = tester().await;
+        let id = {
+            let session = sessions.new_session();
+            let mut session = session.write().unwrap();
+            session.add_link(Utc::now(), "a", "b", Some("Request"));
+            session.id
+        };
+
+        let colors = data::Colors {
+            parties: std::collections::HashMap::from([("b".to_string(), "teal".to_string())]),
+            ..Default::default()
+        };
+        let response = client.put(uri!(api::set_colors(id))).json(&colors).dispatch().await;
+        assert_eq!(Status::Ok, response.status());
+
+        let invalid = rocket::serde::json::json!({ "links": { "1": "red}" } });
+        let response = client.put(uri!(api::set_colors(id))).json(&invalid).dispatch().await;
+        assert_eq!(Status::BadRequest, response.status());
+
+        let response = client.get(format!("/api/session/{id}/svg?theme=dark")).dispatch().await;
+        assert_eq!(Status::Ok, response.status());
+        let body = response.into_string().await.unwrap();
+        assert!(body.contains(".background { fill: #1e1e1e; }"));
+        assert!(body.contains(".party-2 .lane, .party-2 .lifeline { stroke: teal; }"));
+
+        let response = client.get(format!("/api/session/{id}/svg?theme=sepia")).dispatch().await;
+        assert_eq!(Status::BadRequest, response.status());
+    }
+
+    #[cfg(feature = "html")]
+    #[tokio::test]
+    async fn html_output() {
+        let (sessions, client) = tester().await;
+        let id = {
+            let session = sessions.new_session();
+            let mut session = session.write().unwrap();
+            session.add_link(Utc::now(), "a", "b", Some("Request"));
+            session.id
+        };
+
+        let response = client.get(format!("/api/session/{id}/html?title=Audit")).dispatch().await;
+        assert_eq!(Status::Ok, response.status());
+        assert_eq!(Some(rocket::http::ContentType::HTML), response.content_type());
+        let body = response.into_string().await.unwrap();
+        assert!(body.contains("<title>Audit</title>"));
+        assert!(body.contains("Request"));
+    }
+
+    #[cfg(feature = "text")]
+    #[tokio::test]
+    async fn text_output() {
+        let (sessions, client) = tester().await;
+        let id = {
+            let session = sessions.new_session();
+            let mut session = session.write().unwrap();
+            session.add_link(Utc::now(), "a", "b", Some("Request"));
+            session.id
+        };
+
+        let response = client.get(format!("/api/session/{id}/text?width=20&ascii=true")).dispatch().await;
+        assert_eq!(Status::Ok, response.status());
+        assert_eq!(Some(rocket::http::ContentType::Text), response.content_type());
+        let body = response.into_string().await.unwrap();
+        assert!(body.contains("Request"));
+        assert!(body.contains("->|"));
+        assert!(body.is_ascii());
+    }
+
+    #[cfg(feature = "pdf")]
+    #[tokio::test]
+    async fn pdf_output() {
+        let (sessions, client) = tester().await;
+        let id = {
+            let session = sessions.new_session();
+            let mut session = session.write().unwrap();
+            session.add_link(Utc::now(), "a", "b", Some("Request"));
+            session.id
+        };
+
+        let response = client.get(format!("/api/session/{id}/pdf?paper=letter&landscape=true&title=Audit")).dispatch().await;
+        assert_eq!(Status::Ok, response.status());
+        assert_eq!(Some(rocket::http::ContentType::PDF), response.content_type());
+        let body = response.into_bytes().await.unwrap();
+        assert!(body.starts_with(b"%PDF-"));
+
+        let response = client.get(format!("/api/session/{id}/pdf?paper=a0")).dispatch().await;
+        assert_eq!(Status::BadRequest, response.status());
+    }
 }
@@ -0,0 +1,287 @@
+use crate::data::{Link, SessionInner};
+use crate::render::mermaid::Document as MermaidDocument;
+
+use regex::Regex;
+use std::fmt;
+
+/// Matcher for a single link in a recorded session.
+///
+/// Parties are matched by name, the label by a regular expression. Unset
+/// parts match everything.
+#[derive(Clone, Debug, Default)]
+pub struct Message {
+    from: Option<String>,
+    to: Option<String>,
+    label: Option<Regex>,
+}
+
+impl Message {
+    /// Matches any link from `from` to `to`.
+    pub fn new(from: &str, to: &str) -> Self {
+        Self::any().from(from).to(to)
+    }
+
+    /// Matches any link.
+    pub fn any() -> Self {
+        Self::default()
+    }
+
+    pub fn from(mut self, party: &str) -> Self {
+        self.from = Some(party.to_string());
+        self
+    }
+
+    pub fn to(mut self, party: &str) -> Self {
+        self.to = Some(party.to_string());
+        self
+    }
+
+    /// Requires a label matching `pattern`. Panics on an invalid pattern.
+    pub fn label(mut self, pattern: &str) -> Self {
+        self.label = Some(Regex::new(pattern).expect("A valid label pattern"));
+        self
+    }
+
+    /// The same message in the opposite direction without label constraint.
+    pub fn reply(&self) -> Self {
+        Self {
+            from: self.to.clone(),
+            to: self.from.clone(),
+            label: None,
+        }
+    }
+
+    pub fn matches(&self, link: &Link) -> bool {
+        self.from.as_deref().is_none_or(|from| from == link.from.name)
+            && self.to.as_deref().is_none_or(|to| to == link.to.name)
+            && self.label.as_ref().is_none_or(|label| {
+                link.label.as_deref().is_some_and(|text| label.is_match(text))
+            })
+    }
+}
+
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ->> {}", self.from.as_deref().unwrap_or("*"), self.to.as_deref().unwrap_or("*"))?;
+        if let Some(label) = &self.label {
+            write!(f, ": /{label}/")?;
+        }
+        Ok(())
+    }
+}
+
+/// Assertions about the links of a recorded session.
+///
+/// Every assertion panics with a description of the expectation and the
+/// actual session rendered as Mermaid. Assertions return `&Self` and can be
+/// chained.
+pub struct SessionAssert<'a> {
+    session: &'a SessionInner,
+}
+
+impl<'a> SessionAssert<'a> {
+    pub fn new(session: &'a SessionInner) -> Self {
+        Self { session }
+    }
+
+    /// Expects the messages in the given order, other links may be in between.
+    pub fn in_order(&self, expected: &[Message]) -> &Self {
+        let mut links = self.session.links.iter();
+        for (n, message) in expected.iter().enumerate() {
+            if !links.any(|link| message.matches(link)) {
+                let after = if n > 0 { format!(" after #{n}") } else { String::new() };
+                self.fail(&format!(
+                    "Expected messages in order, but #{} `{message}` wasn't found{after}",
+                    n + 1,
+                ));
+            }
+        }
+        self
+    }
+
+    /// Expects every message to match a distinct link in any order.
+    pub fn unordered(&self, expected: &[Message]) -> &Self {
+        // Assign links to messages via augmenting paths, so a broad matcher
+        // can't take away the only link of a narrow one.
+        fn assign(
+            message: usize,
+            candidates: &[Vec<usize>],
+            owner: &mut Vec<Option<usize>>,
+            seen: &mut Vec<bool>,
+        ) -> bool {
+            for &link in &candidates[message] {
+                if seen[link] {
+                    continue;
+                }
+                seen[link] = true;
+                if owner[link].is_none_or(|other| assign(other, candidates, owner, seen)) {
+                    owner[link] = Some(message);
+                    return true;
+                }
+            }
+            false
+        }
+
+        let links = &self.session.links;
+        let candidates = expected.iter()
+            .map(|message| links.iter()
+                .enumerate()
+                .filter(|(_, link)| message.matches(link))
+                .map(|(i, _)| i)
+                .collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+
+        let mut owner = vec![None; links.len()];
+        for (n, message) in expected.iter().enumerate() {
+            let mut seen = vec![false; links.len()];
+            if !assign(n, &candidates, &mut owner, &mut seen) {
+                self.fail(&format!("Expected messages in any order, but `{message}` has no matching link left"));
+            }
+        }
+        self
+    }
+
+    /// Expects exactly `count` links matching `message`.
+    pub fn count(&self, message: &Message, count: usize) -> &Self {
+        let actual = self.session.links.iter().filter(|link| message.matches(link)).count();
+        if actual != count {
+            self.fail(&format!("Expected {count} times `{message}`, but found it {actual} times"));
+        }
+        self
+    }
+
+    /// Expects every `first` to be followed by a `last` without any
+    /// `forbidden` message in between.
+    pub fn no_message_between(&self, first: &Message, last: &Message, forbidden: &Message) -> &Self {
+        let links = &self.session.links;
+        let mut found = false;
+
+        for (start, link) in links.iter().enumerate() {
+            if !first.matches(link) {
+                continue;
+            }
+            found = true;
+
+            let end = links.iter()
+                .skip(start + 1)
+                .position(|link| last.matches(link))
+                .map(|pos| start + 1 + pos)
+                .unwrap_or_else(|| self.fail(&format!(
+                    "Expected `{last}` after `{first}` (link {}), but it never followed",
+                    link.id,
+                )));
+
+            if let Some(between) = links[start + 1..end].iter().find(|link| forbidden.matches(link)) {
+                self.fail(&format!(
+                    "Expected no `{forbidden}` between `{first}` (link {}) and `{last}` (link {}), but found link {}",
+                    link.id, links[end].id, between.id,
+                ));
+            }
+        }
+
+        if !found {
+            self.fail(&format!("Expected `{first}` to occur, but it wasn't found"));
+        }
+        self
+    }
+
+    fn fail(&self, reason: &str) -> ! {
+        let actual: String = MermaidDocument::from(self.session).into();
+        panic!("{reason}\n\nActual session:\n{actual}");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::Utc;
+
+    fn protocol() -> SessionInner {
+        let mut session = SessionInner::new(0);
+        let now = Utc::now();
+        session.add_link(now, "client", "server", Some("GET /index.html"));
+        session.add_link(now, "server", "db", Some("SELECT page"));
+        session.add_link(now, "db", "server", Some("page"));
+        session.add_link(now, "server", "client", Some("200 OK"));
+        session.add_link(now, "client", "server", Some("GET /favicon.ico"));
+        session.add_link(now, "server", "client", Some("404 Not Found"));
+        session
+    }
+
+    #[test]
+    fn ordered_subsequence() {
+        let session = protocol();
+        let request = Message::new("client", "server").label("^GET /index");
+        SessionAssert::new(&session)
+            .in_order(&[
+                request.clone(),
+                Message::new("server", "db"),
+                request.reply().label("^200"),
+            ]);
+    }
+
+    #[test]
+    #[should_panic(expected = "wasn't found after #1")]
+    fn ordered_subsequence_in_wrong_order() {
+        let session = protocol();
+        SessionAssert::new(&session)
+            .in_order(&[Message::new("server", "client").label("^404"), Message::new("server", "db")]);
+    }
+
+    #[test]
+    fn unordered_messages() {
+        let session = protocol();
+        SessionAssert::new(&session)
+            .unordered(&[
+                Message::any().to("client"),
+                Message::new("server", "client").label("^404"),
+                Message::new("db", "server"),
+            ]);
+    }
+
+    #[test]
+    #[should_panic(expected = "has no matching link left")]
+    fn unordered_messages_need_distinct_links() {
+        let session = protocol();
+        SessionAssert::new(&session)
+            .unordered(&[Message::new("db", "server"), Message::new("db", "server")]);
+    }
+
+    #[test]
+    fn count_messages() {
+        let session = protocol();
+        SessionAssert::new(&session)
+            .count(&Message::new("client", "server").label("^GET"), 2)
+            .count(&Message::any().label("POST"), 0);
+    }
+
+    #[test]
+    fn no_message_between() {
+        let session = protocol();
+        SessionAssert::new(&session)
+            .no_message_between(
+                &Message::new("server", "db"),
+                &Message::new("server", "client"),
+                &Message::any().from("client"),
+            );
+    }
+
+    #[test]
+    #[should_panic(expected = "but found link 2")]
+    fn message_between() {
+        let session = protocol();
+        SessionAssert::new(&session)
+            .no_message_between(
+                &Message::new("client", "server").label("index"),
+                &Message::new("server", "client"),
+                &Message::new("server", "db"),
+            );
+    }
+
+    #[test]
+    #[should_panic(expected = "Actual session:\nsequenceDiagram\n")]
+    fn failure_contains_mermaid() {
+        let session = protocol();
+        SessionAssert::new(&session).count(&Message::any(), 1);
+    }
+}
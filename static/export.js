@@ -0,0 +1,108 @@
+function setup_export() {
+    const diagram = document.getElementById("diagram");
+    const svg = diagram.querySelector("svg");
+    const tooltip = document.getElementById("tooltip");
+    const search = document.getElementById("search");
+    const matches_info = document.getElementById("matches");
+    const links = Array.from(svg.querySelectorAll("g.link"));
+    const view_box = svg.viewBox.baseVal;
+    const initial = [view_box.x, view_box.y, view_box.width, view_box.height];
+    var matches = [];
+    var current = -1;
+
+    function to_svg_point(event) {
+        let point = svg.createSVGPoint();
+        point.x = event.clientX;
+        point.y = event.clientY;
+        return point.matrixTransform(svg.getScreenCTM().inverse());
+    }
+
+    function reset_view() {
+        [view_box.x, view_box.y, view_box.width, view_box.height] = initial;
+    }
+
+    svg.addEventListener("wheel", (event) => {
+        event.preventDefault();
+        let factor = event.deltaY > 0 ? 1.1 : 1 / 1.1;
+        let point = to_svg_point(event);
+        view_box.x = point.x - (point.x - view_box.x) * factor;
+        view_box.y = point.y - (point.y - view_box.y) * factor;
+        view_box.width *= factor;
+        view_box.height *= factor;
+    });
+
+    var pan_start = undefined;
+    svg.addEventListener("pointerdown", (event) => {
+        pan_start = to_svg_point(event);
+        diagram.classList.add("panning");
+    });
+    window.addEventListener("pointermove", (event) => {
+        if (pan_start == undefined) {
+            return;
+        }
+        let point = to_svg_point(event);
+        view_box.x -= point.x - pan_start.x;
+        view_box.y -= point.y - pan_start.y;
+    });
+    window.addEventListener("pointerup", () => {
+        pan_start = undefined;
+        diagram.classList.remove("panning");
+    });
+
+    links.forEach((link) => {
+        link.addEventListener("mousemove", (event) => {
+            let label = link.querySelector("text");
+            tooltip.textContent = "#" + link.dataset.linkId + " " +
+                link.dataset.from + " → " + link.dataset.to + "\n" +
+                link.dataset.timestamp +
+                (label ? "\n" + label.textContent : "");
+            tooltip.style.left = (event.clientX + 12) + "px";
+            tooltip.style.top = (event.clientY + 12) + "px";
+            tooltip.style.display = "block";
+        });
+        link.addEventListener("mouseleave", () => {
+            tooltip.style.display = "none";
+        });
+    });
+
+    function show_match(index) {
+        links.forEach((link) => link.classList.remove("current"));
+        if (matches.length == 0) {
+            matches_info.textContent = search.value ? "No matches" : "";
+            return;
+        }
+        current = (index + matches.length) % matches.length;
+        let link = matches[current];
+        link.classList.add("current");
+        matches_info.textContent = (current + 1) + " of " + matches.length;
+
+        let box = link.getBBox();
+        view_box.x = box.x + box.width / 2 - view_box.width / 2;
+        view_box.y = box.y + box.height / 2 - view_box.height / 2;
+    }
+
+    search.addEventListener("input", () => {
+        let query = search.value.toLowerCase();
+        matches = [];
+        links.forEach((link) => {
+            let text = [link.dataset.from, link.dataset.to, link.dataset.linkId, link.textContent].join(" ").toLowerCase();
+            let match = query != "" && text.includes(query);
+            link.classList.toggle("match", match);
+            if (match) {
+                matches.push(link);
+            }
+        });
+        diagram.classList.toggle("searching", query != "");
+        show_match(0);
+    });
+    search.addEventListener("keydown", (event) => {
+        if (event.key == "Enter") {
+            show_match(current + (event.shiftKey ? -1 : 1));
+        }
+    });
+    document.getElementById("previous").onclick = () => show_match(current - 1);
+    document.getElementById("next").onclick = () => show_match(current + 1);
+    document.getElementById("reset").onclick = reset_view;
+}
+
+document.addEventListener("DOMContentLoaded", setup_export);
@@ -1,155 +1,108 @@
-const LANE_WIDTH = 200;
-const INTER_LANE_SPACE = LANE_WIDTH / 10;
-const STEP_HEIGHT = 100;
-const SPACER_SIZE = LANE_WIDTH/10;
-const PARTY_TEXT_OFFSET = 50;
-const LINKS_START_HEIGHT = 100;
-const LINK_TEXT_OFFSET = 20;
-const LINK_LINE_OFFSET = 70;
-const ARROW_SIZE = SPACER_SIZE / 2;
+const SVG_NS = "http://www.w3.org/2000/svg";
 
-var x = SPACER_SIZE;
-var y = SPACER_SIZE;
-var party_pos_map = {};
-var party_end_x = SPACER_SIZE;
-var party_end_y = LINKS_START_HEIGHT;
+// Horizontal positions of the lanes drawn last, links are redrawn when they change
+var drawn_lanes = undefined;
 
-function update_view_box() {
-    var svg_doc = document.getElementById("svg-doc");
-    svg_doc.setAttribute("viewBox", "0 0 " + (party_end_x) + " " + (party_end_y + 2*SPACER_SIZE));
-    Object.keys(party_pos_map).forEach((party) => {
-        var rect = document.getElementById("svg_party_rect:" + party);
-        rect.setAttribute("height", party_end_y + SPACER_SIZE);
+function svg_element(name, attributes) {
+    var element = document.createElementNS(SVG_NS, name);
+    Object.entries(attributes).forEach(([key, value]) => element.setAttribute(key, value));
+    return element;
+}
 
-        var line = document.getElementById("svg_party_line:" + party);
-        line.setAttribute("y2", party_end_y);
+function points(list) {
+    return list.map((point) => point[0] + "," + point[1]).join(" ");
+}
 
-        let x = party_pos_map[party];
-        var polygon = document.getElementById("svg_party_arrow:" + party);
-        polygon.setAttribute("points", "" + 
-            (x) + "," + (party_end_y + ARROW_SIZE) + " " +
-            (x - ARROW_SIZE/2) + "," + (party_end_y) + " " +
-            (x + ARROW_SIZE/2) + "," + (party_end_y));
+function draw_text(block, class_name, line_height) {
+    var text = svg_element("text", {
+        "class": class_name,
+        "x": block.x,
+        "y": block.y,
+        "text-anchor": block.anchor,
+    });
+    block.lines.forEach((line, index) => {
+        var tspan = svg_element("tspan", { "x": block.x, "y": block.y + index * line_height });
+        tspan.appendChild(document.createTextNode(line));
+        text.appendChild(tspan);
     });
+    return text;
 }
 
-function add_party(party) {
-    var svg_doc = document.getElementById("svg-doc");
-    if (party_pos_map[party] != undefined) {
-        return; 
-    }
-
-    var x = party_end_x;
-    console.log("Adding party ", party);
-
-    var rect = document.createElementNS("http://www.w3.org/2000/svg", "rect");
-    rect.setAttribute("id", "svg_party_rect:" + party);
-    rect.setAttribute("width", LANE_WIDTH);
-    rect.setAttribute("height", party_end_y + SPACER_SIZE);
-    rect.setAttribute("x", x);
-    rect.setAttribute("y", SPACER_SIZE);
-    rect.setAttribute("style", "fill:white;stroke:lightgray");
-    svg_doc.appendChild(rect);
-
-    var text = document.createElementNS("http://www.w3.org/2000/svg", "text");
-    text.setAttribute("x", x + LANE_WIDTH/2);
-    text.setAttribute("y", PARTY_TEXT_OFFSET);
-    text.setAttribute("fill", "black");
-    text.setAttribute("text-anchor", "middle");
-    text.appendChild(document.createTextNode(party));
-    svg_doc.appendChild(text);
-
-    var line = document.createElementNS("http://www.w3.org/2000/svg", "line");
-    line.setAttribute("id", "svg_party_line:" + party);
-    line.setAttribute("x1", x + LANE_WIDTH/2)
-    line.setAttribute("y1", LINKS_START_HEIGHT)
-    line.setAttribute("x2", x + LANE_WIDTH/2)
-    line.setAttribute("y2", party_end_y)
-    line.setAttribute("style", "stroke:rgb(0,0,0);stroke-width:2");
-    svg_doc.appendChild(line);
-
-    var polygon = document.createElementNS("http://www.w3.org/2000/svg", "polygon");
-    polygon.setAttribute("id", "svg_party_arrow:" + party);
-    polygon.setAttribute("points", "" + 
-        (x + LANE_WIDTH/2) + "," + (party_end_y + ARROW_SIZE) + " " +
-        (x + LANE_WIDTH/2 - ARROW_SIZE/2) + "," + (party_end_y) + " " +
-        (x + LANE_WIDTH/2 + ARROW_SIZE/2) + "," + (party_end_y));
-    polygon.setAttribute("style", "fill:black;stroke-width:0");
-    svg_doc.appendChild(polygon);
+function draw_lane(lane, line_height) {
+    var group = svg_element("g", { "id": "party-" + lane.id, "class": "party party-" + lane.id });
+    group.appendChild(svg_element("rect", {
+        "class": "lane",
+        "x": lane.center - lane.width / 2,
+        "y": lane.top,
+        "width": lane.width,
+        "height": lane.height,
+    }));
+    group.appendChild(draw_text(lane.title, "party-name", line_height));
+    group.appendChild(svg_element("line", {
+        "class": "lifeline",
+        "x1": lane.lifeline[0][0],
+        "y1": lane.lifeline[0][1],
+        "x2": lane.lifeline[1][0],
+        "y2": lane.lifeline[1][1],
+    }));
+    group.appendChild(svg_element("polygon", { "class": "lifeline-end", "points": points(lane.lifeline_end) }));
+    return group;
+}
 
-    party_pos_map[party] = x + LANE_WIDTH/2;
-    party_end_x += LANE_WIDTH + INTER_LANE_SPACE;
+function draw_row(row, line_height) {
+    var group = svg_element("g", { "id": "link-" + row.id, "class": "link link-" + row.id });
+    if (row.label) {
+        group.appendChild(draw_text(row.label, "label", line_height));
+    }
+    if (row.gap) {
+        group.appendChild(svg_element("polyline", { "class": "gap", "points": points(row.gap) }));
+    }
+    group.appendChild(svg_element("polyline", { "class": "line", "points": points(row.line) }));
+    group.appendChild(svg_element("polygon", { "class": "arrow", "points": points(row.arrow) }));
+    return group;
 }
 
-function add_link(from, to, label) {
+// Draws the layout of the server, only the new rows unless the lanes moved
+function draw_layout(layout, full_layout_url) {
     var svg_doc = document.getElementById("svg-doc");
-    add_party(from);
-    add_party(to);
-
-    let from_x = party_pos_map[from];
-    let to_x = party_pos_map[to];
-    let direction = to_x > from_x ? 1 : -1;
-    var y = party_end_y;
-
-    var text = document.createElementNS("http://www.w3.org/2000/svg", "text");
-    text.setAttribute("x", to_x - direction * LANE_WIDTH/2);
-    text.setAttribute("y", y + LINK_TEXT_OFFSET);
-    text.setAttribute("fill", "black");
-    text.setAttribute("text-anchor", "middle");
-    text.setAttribute("stroke", "white");
-    text.setAttribute("stroke-width", "0.5em");
-    text.setAttribute("paint-order", "stroke");
-    text.setAttribute("stroke-linejoin", "round");
-    text.appendChild(document.createTextNode(label));
-    svg_doc.appendChild(text);
-
-    var line = document.createElementNS("http://www.w3.org/2000/svg", "line");
-    line.setAttribute("x1", from_x + direction * LANE_WIDTH/2)
-    line.setAttribute("y1", y + LINK_LINE_OFFSET)
-    line.setAttribute("x2", to_x - direction * LANE_WIDTH/2)
-    line.setAttribute("y2", y + LINK_LINE_OFFSET)
-    line.setAttribute("style", "stroke:rgb(255,255,255);stroke-width:8");
-    svg_doc.appendChild(line);
+    var lanes_group = document.getElementById("svg-lanes");
+    var rows_group = document.getElementById("svg-rows");
 
-    line = document.createElementNS("http://www.w3.org/2000/svg", "line");
-    line.setAttribute("x1", from_x)
-    line.setAttribute("y1", y + LINK_LINE_OFFSET)
-    line.setAttribute("x2", to_x)
-    line.setAttribute("y2", y + LINK_LINE_OFFSET)
-    line.setAttribute("style", "stroke:rgb(0,0,0);stroke-width:2");
-    svg_doc.appendChild(line);
+    let lanes = JSON.stringify(layout.lanes.map((lane) => [lane.id, lane.center, lane.width, lane.title]));
+    if (drawn_lanes != undefined && drawn_lanes != lanes && !layout.complete) {
+        // Positions of existing links are outdated
+        return fetch(full_layout_url)
+            .then((resp) => resp.json())
+            .then((data) => draw_layout(data, full_layout_url));
+    }
+    if (drawn_lanes != lanes) {
+        rows_group.replaceChildren();
+    }
+    drawn_lanes = lanes;
 
-    var polygon = document.createElementNS("http://www.w3.org/2000/svg", "polygon");
-    polygon.setAttribute("points", "" + 
-        (to_x) + ","  + (y + LINK_LINE_OFFSET) + " " +
-        (to_x - direction * ARROW_SIZE) + "," + (y - ARROW_SIZE/2 + LINK_LINE_OFFSET) + " " +
-        (to_x - direction * ARROW_SIZE) + "," + (y + ARROW_SIZE/2 + LINK_LINE_OFFSET));
-    polygon.setAttribute("style", "fill:black;stroke-width:0");
-    svg_doc.appendChild(polygon);
+    lanes_group.replaceChildren(...layout.lanes.map((lane) => draw_lane(lane, layout.line_height)));
 
-    party_end_y += STEP_HEIGHT;
-    update_view_box();
+    var last = undefined;
+    layout.rows.forEach((row) => {
+        console.log("Adding link", row.id);
+        last = draw_row(row, layout.line_height);
+        rows_group.appendChild(last);
+    });
+    svg_doc.setAttribute("viewBox", "0 0 " + layout.width + " " + layout.height);
 
-    return text;
+    if (last != undefined) {
+        last.scrollIntoView({ behavior: "smooth", inline: "center", block: "center" });
+    }
+    return layout;
 }
 
-function check_events(events_url, event_view_elm) {
+function check_layout(layout_url, full_layout_url) {
     if (document.getElementById("auto_update").checked) {
-        fetch(events_url)
+        fetch(layout_url)
             .then((resp) => resp.json())
-            .then((data) => {
-                setTimeout(() => check_events(data.events_url, event_view_elm), 1000);
-                event_handler(data, event_view_elm);
-            });
+            .then((data) => draw_layout(data, full_layout_url))
+            .then((data) => setTimeout(() => check_layout(data.layout_url, full_layout_url), 1000));
     } else {
-        setTimeout(() => check_events(data.events_url, event_view_elm), 1000);
+        setTimeout(() => check_layout(layout_url, full_layout_url), 1000);
     }
 }
-
-function event_handler(data, event_view_elm) {
-    data.new_links.forEach(link => {
-        console.log("Adding link", link);
-        let link_anchor = add_link(link.from, link.to, link.label);
-        link_anchor.scrollIntoView({ behavior: "smooth", inline: "center", block: "center" });
-    });
-}